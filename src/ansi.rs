@@ -0,0 +1,74 @@
+use super::*;
+use pretty::{FogStr, FogStyle, Printer};
+
+const RESET: &str = "\x1b[0m";
+const KEY: &str = "\x1b[1;34m";
+const STR: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[33m";
+const LITERAL: &str = "\x1b[35m";
+const ID: &str = "\x1b[36m";
+const TIME: &str = "\x1b[95m";
+const BINARY: &str = "\x1b[31m";
+const TYPE: &str = "\x1b[2m";
+
+/// Pretty-print JSON for a terminal, colored with ANSI escape codes.
+///
+/// Uses the same layout as [`fog_pretty_string`][crate::fog_pretty_string]. Keys, strings,
+/// numbers, and `true`/`false`/`null` each get their own color. Special `$fog-` strings have their
+/// type tag dimmed, with the rest colored by type: hashes and identities in one color, times in
+/// another, and binary data and lockboxes in a third.
+pub fn render_ansi(json: &JsonValue, width: usize) -> String {
+    Printer::styled(width, key, scalar).print(json)
+}
+
+fn key(key: &str) -> String {
+    format!("{}{}{}", KEY, JsonValue::String(key.to_owned()), RESET)
+}
+
+fn scalar(json: &JsonValue) -> String {
+    let color = match json {
+        JsonValue::Number(_) => NUMBER,
+        JsonValue::String(s) => match FogStr::parse(s) {
+            Some(fog) => return fog_str(&fog),
+            None => STR,
+        },
+        _ => LITERAL,
+    };
+    format!("{}{}{}", color, json, RESET)
+}
+
+/// Color a `$fog-` string by its type.
+fn fog_str(fog: &FogStr) -> String {
+    let color = match fog.style {
+        FogStyle::Str => STR,
+        FogStyle::Id => ID,
+        FogStyle::Time => TIME,
+        FogStyle::Number => NUMBER,
+        FogStyle::Binary => BINARY,
+    };
+    let (tag, rest) = fog.quoted();
+    format!("{}{}{}{}{}{}", TYPE, tag, RESET, color, rest, RESET)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn render_ansi() {
+        let json = json!({"id": "$fog-Hash:abc", "n": [1, null]});
+        let out = super::render_ansi(&json, 80);
+        assert_eq!(out, "{\x1b[1;34m\"id\"\x1b[0m: \x1b[2m\"$fog-Hash:\x1b[0m\x1b[36mabc\"\x1b[0m, \
+            \x1b[1;34m\"n\"\x1b[0m: [\x1b[33m1\x1b[0m, \x1b[35mnull\x1b[0m]}");
+        // Escape codes don't count against the width
+        let plain = fog_pretty_string(&json, 30);
+        assert_eq!(plain.lines().count(), super::render_ansi(&json, 30).lines().count());
+    }
+
+    #[test]
+    fn escaped_type() {
+        let out = super::render_ansi(&json!(["$fog-\"\"é:x\"y"]), 80);
+        assert_eq!(out, "[\x1b[2m\"$fog-\\\"\\\"é:\x1b[0m\x1b[31mx\\\"y\"\x1b[0m]");
+    }
+}
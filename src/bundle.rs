@@ -0,0 +1,494 @@
+use super::*;
+use fog_crypto::hash::HashState;
+use fog_pack::{
+    document::{Document, NewDocument},
+    entry::Entry,
+    types::Hash,
+};
+use std::collections::{HashMap, HashSet};
+
+/// How the documents and entries in a bundle are ordered, as set with [`BundleOptions::order`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BundleOrder {
+    /// Keep documents and entries in the order they were given.
+    #[default]
+    Given,
+    /// Sort documents by hash, and entries by their parent's hash, then key, then their own 
+    /// hash. Converting the same documents and entries always produces the same JSON, no matter 
+    /// what order they came in, which keeps diffs of exported bundles clean.
+    Hash,
+}
+
+/// Options for converting documents and entries into a JSON bundle, on top of the 
+/// [`EncodeOptions`] used to convert each one.
+///
+/// The default options keep items in the order given and add nothing beyond the documents and 
+/// entries themselves. Options are set builder-style:
+///
+/// ```
+/// # use fog_human_json::*;
+/// let opts = BundleOptions::new().order(BundleOrder::Hash).manifest(true);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct BundleOptions {
+    order: BundleOrder,
+    manifest: bool,
+}
+
+impl BundleOptions {
+    /// Create the default set of bundle options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Choose how documents and entries are ordered. See [`BundleOrder`] for the options.
+    pub fn order(mut self, order: BundleOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Include a `"manifest"`, listing the hash of every document and entry along with a 
+    /// checksum of its JSON. The manifest is verified when converting the bundle back, so 
+    /// corrupted or tampered bundles are caught before anything is imported.
+    pub fn manifest(mut self, enable: bool) -> Self {
+        self.manifest = enable;
+        self
+    }
+}
+
+/// Convert a set of [`Document`]s and [`Entry`]s into a single JSON bundle.
+///
+/// The resulting JSON value will be an Object with the following key-value pairs:
+///
+/// - "documents": An Array of the documents, each converted as with [`doc_to_json`]
+/// - "entries": An Array of the entries, each converted as with [`entry_to_json`]
+///
+/// Both are in the order given, unless a different [`BundleOrder`] is set with 
+/// [`BundleOptions::order`]. If enabled with [`BundleOptions::manifest`], there's also:
+///
+/// - "manifest": An Object with "documents" and "entries" Arrays, listing each item in the same 
+///   order as an Object with its "hash" and a "checksum" of its JSON.
+///
+/// # Panics
+///
+/// Panics if any document's or entry's data can't be read, which can only happen if it was loaded
+/// without validation. Use [`try_bundle_to_json`] to get an error instead.
+pub fn bundle_to_json(docs: &[Document], entries: &[Entry]) -> JsonValue {
+    bundle_to_json_with_options(docs, entries, &EncodeOptions::default(), &BundleOptions::default())
+}
+
+/// Convert a set of [`Document`]s and [`Entry`]s into a single JSON bundle, as with
+/// [`bundle_to_json`], using the provided encoding options for each document and entry, and the 
+/// provided bundle options for the bundle as a whole.
+///
+/// # Panics
+///
+/// Panics if any document's or entry's data can't be read, or is nested deeper than
+/// [`EncodeOptions::max_depth`]. Use [`try_bundle_to_json`] to get an error instead.
+pub fn bundle_to_json_with_options(
+    docs: &[Document],
+    entries: &[Entry],
+    opts: &EncodeOptions,
+    bundle_opts: &BundleOptions
+) -> JsonValue {
+    try_bundle_to_json(docs, entries, opts, bundle_opts)
+        .unwrap_or_else(|err| panic!("{}; use try_bundle_to_json to handle this", err))
+}
+
+/// Convert a set of [`Document`]s and [`Entry`]s into a single JSON bundle, as with
+/// [`bundle_to_json_with_options`], failing instead of panicking.
+pub fn try_bundle_to_json(
+    docs: &[Document],
+    entries: &[Entry],
+    opts: &EncodeOptions,
+    bundle_opts: &BundleOptions
+) -> Result<JsonValue, EncodeError> {
+    let mut docs: Vec<&Document> = docs.iter().collect();
+    let mut entries: Vec<&Entry> = entries.iter().collect();
+    match bundle_opts.order {
+        BundleOrder::Given => (),
+        BundleOrder::Hash => {
+            docs.sort_by(|a, b| a.hash().cmp(b.hash()));
+            entries.sort_by(|a, b| {
+                (a.parent(), a.key(), a.hash()).cmp(&(b.parent(), b.key(), b.hash()))
+            });
+        },
+    }
+    let doc_hashes: Vec<&Hash> = docs.iter().map(|doc| doc.hash()).collect();
+    let entry_hashes: Vec<&Hash> = entries.iter().map(|entry| entry.hash()).collect();
+    let docs = docs.into_iter()
+        .map(|doc| try_doc_to_json(doc, opts))
+        .collect::<Result<Vec<_>, _>>()?;
+    let entries = entries.into_iter()
+        .map(|entry| try_entry_to_json(entry, opts))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut map = JsonMap::new();
+    if bundle_opts.manifest {
+        let list = |hashes: Vec<&Hash>, items: &[JsonValue]| -> Result<JsonValue, EncodeError> {
+            hashes.into_iter().zip(items).map(|(hash, item)| {
+                let mut obj = JsonMap::new();
+                obj.insert("hash".into(), try_fog_to_json(&FogValue::Hash(hash.to_owned()), opts)?);
+                obj.insert("checksum".into(), try_fog_to_json(&FogValue::Hash(checksum(item)), opts)?);
+                Ok(JsonValue::Object(obj))
+            }).collect()
+        };
+        let mut manifest = JsonMap::new();
+        manifest.insert("documents".into(), list(doc_hashes, &docs)?);
+        manifest.insert("entries".into(), list(entry_hashes, &entries)?);
+        map.insert("manifest".into(), JsonValue::Object(manifest));
+    }
+    map.insert("documents".into(), JsonValue::Array(docs));
+    map.insert("entries".into(), JsonValue::Array(entries));
+    Ok(JsonValue::Object(map))
+}
+
+/// The documents and entries from a JSON bundle, as converted by [`json_to_bundle`].
+pub struct Bundle {
+    /// The bundle's documents, in order. Some may still need signing.
+    pub documents: Vec<MaybeDocument>,
+    /// The entries whose parent was a completed document in the bundle, in order. Some may still
+    /// need signing.
+    pub entries: Vec<MaybeEntry>,
+    /// The entries whose parent wasn't available, in order. The parent may be outside the bundle,
+    /// still need signing, or use a schema that wasn't provided. Finish these with
+    /// [`JsonEntry::complete`] once the parent is at hand.
+    pub pending: Vec<JsonEntry>,
+    /// The hashes of the documents and entries left out by [`json_to_bundle_changed`] because 
+    /// they already exist, in order.
+    pub unchanged: Vec<Hash>,
+}
+
+/// Convert a JSON bundle of documents and entries, as made by [`bundle_to_json`].
+///
+/// The root JSON value should be an Object with the following key-value pairs, either of which
+/// may be left out if empty:
+///
+/// - "documents": An Array of documents, each as accepted by [`json_to_doc`]
+/// - "entries": An Array of entries, each as accepted by [`JsonEntry::from_json`]
+///
+/// Entries are completed against the documents in the same bundle. A document must be validated
+/// before its entries can be completed, so documents using a schema need that schema in `schemas`.
+/// Any entries that can't be completed yet are returned in [`Bundle::pending`].
+///
+/// If the bundle has a "manifest", as added by [`BundleOptions::manifest`], every item's checksum 
+/// is verified before anything is converted, and the hash of every document and entry that 
+/// doesn't need signing is checked once it's converted. A mismatch is reported as an 
+/// [`ObjectError::HashMismatch`].
+///
+/// Failures in individual documents or entries are reported as [`ObjectError::BundleItem`].
+pub fn json_to_bundle<S: SchemaSource + ?Sized>(json: &JsonValue, schemas: &S) -> Result<Bundle, ObjectError> {
+    json_to_bundle_with_options(json, schemas, &DecodeOptions::default())
+}
+
+/// Convert a JSON bundle of documents and entries, as with [`json_to_bundle`], using the provided
+/// decoding options.
+pub fn json_to_bundle_with_options<S: SchemaSource + ?Sized>(
+    json: &JsonValue,
+    schemas: &S,
+    opts: &DecodeOptions
+) -> Result<Bundle, ObjectError> {
+    bundle_from_json(json, schemas, &HashSet::new(), opts)
+}
+
+/// Convert a JSON bundle, as with [`json_to_bundle_with_options`], leaving out the documents and 
+/// entries whose hashes are in `existing`. This makes re-importing a mostly unchanged bundle 
+/// cheap, as only the changed documents and entries come back to be signed and stored.
+///
+/// Documents that need signing are matched by the "hash" recorded with 
+/// [`EncodeOptions::doc_hash`] when the bundle was made, so unchanged ones are skipped without 
+/// being signed again. Ones without a recorded hash, along with entries that need signing, are 
+/// always returned. The entries of a skipped signed document end up in [`Bundle::pending`], to be 
+/// completed against the existing document if they changed.
+///
+/// The hashes of everything left out are listed in [`Bundle::unchanged`].
+pub fn json_to_bundle_changed<S: SchemaSource + ?Sized>(
+    json: &JsonValue,
+    schemas: &S,
+    existing: &HashSet<Hash>,
+    opts: &DecodeOptions
+) -> Result<Bundle, ObjectError> {
+    bundle_from_json(json, schemas, existing, opts)
+}
+
+fn bundle_from_json<S: SchemaSource + ?Sized>(
+    json: &JsonValue,
+    schemas: &S,
+    existing: &HashSet<Hash>,
+    opts: &DecodeOptions
+) -> Result<Bundle, ObjectError> {
+    let obj = json.as_object().ok_or(ObjectError::NotAnObject)?;
+
+    // Make sure we only have fields we recognize
+    for k in obj.keys() {
+        match k.as_str() {
+            "documents" | "entries" | "manifest" | GENERATOR_KEY => (),
+            k if opts.skip_key(k) => (),
+            k => return Err(ObjectError::UnrecognizedKey(k.to_string())),
+        }
+    }
+
+    let items = |key: &'static str| match obj.get(key) {
+        None => Ok(&[][..]),
+        Some(JsonValue::Array(items)) => Ok(&items[..]),
+        Some(_) => Err(ObjectError::WrongDataType(key)),
+    };
+    let item_err = |key: &'static str, index: usize| {
+        move |src: ObjectError| ObjectError::BundleItem { key, index, src: Box::new(src) }
+    };
+
+    // Verify the manifest before converting anything
+    let (doc_hashes, entry_hashes) = match obj.get("manifest") {
+        Some(manifest) => {
+            let mut dec = Decoder::new(opts);
+            let docs = read_manifest(manifest, "documents", items("documents")?, &mut dec)?;
+            let entries = read_manifest(manifest, "entries", items("entries")?, &mut dec)?;
+            (Some(docs), Some(entries))
+        },
+        None => (None, None),
+    };
+    let check = |hashes: &Option<Vec<Hash>>, index: usize, actual: &Hash| match hashes {
+        Some(hashes) if &hashes[index] != actual => Err(ObjectError::HashMismatch {
+            expected: Box::new(hashes[index].clone()),
+            actual: Box::new(actual.clone()),
+        }),
+        _ => Ok(()),
+    };
+
+    let mut documents = Vec::new();
+    let mut unchanged = Vec::new();
+    let mut parents: HashMap<Hash, Document> = HashMap::new();
+    for (index, json) in items("documents")?.iter().enumerate() {
+        let doc = json_to_doc_with_options(json, opts).map_err(item_err("documents", index))?;
+        if let MaybeDocument::NewDocument(doc) = &doc {
+            if let Some(parent) = validate_parent(doc, schemas).map_err(item_err("documents", index))? {
+                parents.insert(parent.hash().to_owned(), parent);
+            }
+            check(&doc_hashes, index, doc.hash()).map_err(item_err("documents", index))?;
+        }
+        let hash = match &doc {
+            MaybeDocument::NewDocument(doc) => Some(doc.hash()),
+            MaybeDocument::SignDocument(sign) => sign.hash(),
+        };
+        match hash.filter(|hash| existing.contains(*hash)) {
+            Some(hash) => unchanged.push(hash.to_owned()),
+            None => documents.push(doc),
+        }
+    }
+
+    let mut entries = Vec::new();
+    let mut pending = Vec::new();
+    for (index, json) in items("entries")?.iter().enumerate() {
+        let entry = JsonEntry::from_json_with_options(json, opts).map_err(item_err("entries", index))?;
+        let Some(parent) = parents.get(entry.parent()) else {
+            pending.push(entry);
+            continue;
+        };
+        let entry = entry.complete(parent).map_err(item_err("entries", index))?;
+        if let MaybeEntry::NewEntry(entry) = &entry {
+            check(&entry_hashes, index, entry.hash()).map_err(item_err("entries", index))?;
+        }
+        match entry {
+            MaybeEntry::NewEntry(entry) if existing.contains(entry.hash()) => {
+                unchanged.push(entry.hash().to_owned());
+            },
+            entry => entries.push(entry),
+        }
+    }
+
+    Ok(Bundle { documents, entries, pending, unchanged })
+}
+
+/// Read one list from a bundle's manifest, checking it against the bundle's items and returning 
+/// the hash listed for each.
+fn read_manifest(
+    manifest: &JsonValue,
+    key: &'static str,
+    items: &[JsonValue],
+    dec: &mut Decoder
+) -> Result<Vec<Hash>, ObjectError> {
+    let listed = match manifest.get(key) {
+        None => &[][..],
+        Some(JsonValue::Array(listed)) => &listed[..],
+        Some(_) => return Err(ObjectError::WrongDataType("manifest")),
+    };
+    if listed.len() != items.len() {
+        return Err(ObjectError::ManifestMismatch { key, listed: listed.len(), actual: items.len() });
+    }
+    let mut hash = |val: Option<&JsonValue>| -> Result<Hash, ObjectError> {
+        let val = val.ok_or(ObjectError::WrongDataType("manifest"))?;
+        dec.value(val)
+            .map_err(|src| ObjectError::Decode { key: "manifest", src })?
+            .as_hash()
+            .cloned()
+            .ok_or(ObjectError::WrongDataType("manifest"))
+    };
+    listed.iter().zip(items).enumerate().map(|(index, (listed, item))| {
+        let expected = hash(listed.get("checksum"))?;
+        let actual = checksum(item);
+        if expected != actual {
+            let src = ObjectError::HashMismatch { expected: Box::new(expected), actual: Box::new(actual) };
+            return Err(ObjectError::BundleItem { key, index, src: Box::new(src) });
+        }
+        hash(listed.get("hash"))
+    }).collect()
+}
+
+/// Checksum an item's JSON as compact text with every object's keys sorted, so neither 
+/// reformatting the bundle nor reordering keys changes it.
+fn checksum(json: &JsonValue) -> Hash {
+    enum Step<'a> {
+        Value(&'a JsonValue),
+        Key(&'a str),
+        Text(&'static str),
+    }
+    let mut state = HashState::new();
+    let mut stack = vec![Step::Value(json)];
+    while let Some(step) = stack.pop() {
+        match step {
+            Step::Text(text) => state.update(text),
+            Step::Key(key) => {
+                // Serializing a string can't fail
+                state.update(serde_json::to_vec(key).unwrap());
+                state.update(":");
+            },
+            Step::Value(JsonValue::Array(array)) => {
+                state.update("[");
+                stack.push(Step::Text("]"));
+                for (i, v) in array.iter().enumerate().rev() {
+                    stack.push(Step::Value(v));
+                    if i > 0 { stack.push(Step::Text(",")); }
+                }
+            },
+            Step::Value(JsonValue::Object(obj)) => {
+                state.update("{");
+                stack.push(Step::Text("}"));
+                let mut entries: Vec<_> = obj.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                for (i, (k, v)) in entries.into_iter().enumerate().rev() {
+                    stack.push(Step::Value(v));
+                    stack.push(Step::Key(k));
+                    if i > 0 { stack.push(Step::Text(",")); }
+                }
+            },
+            Step::Value(v) => state.update(serde_json::to_vec(v).unwrap()),
+        }
+    }
+    state.finalize()
+}
+
+/// Validate a bundle document so its entries can be completed. Returns `None` if the document's
+/// schema isn't available.
+fn validate_parent<S: SchemaSource + ?Sized>(
+    doc: &NewDocument,
+    schemas: &S
+) -> Result<Option<Document>, ObjectError> {
+    let schema = match doc.schema_hash().map(|hash| schemas.schema(hash)) {
+        None => None,
+        Some(Some(schema)) => Some(schema),
+        Some(None) => return Ok(None),
+    };
+    doc::validate_doc(doc.clone(), schema).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fog_pack::{
+        entry::NewEntry,
+        schema::{Schema, SchemaBuilder},
+        validator::*,
+    };
+
+    #[test]
+    fn bundle_round_trip() {
+        let schema_doc = SchemaBuilder::new(StrValidator::new().build())
+            .entry_add("item", IntValidator::new().build(), None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let doc = |data: &str| {
+            let doc = NewDocument::new(Some(schema.hash()), data).unwrap();
+            schema.validate_new_doc(doc).unwrap()
+        };
+        let (known, unlisted) = (doc("known"), doc("unlisted"));
+        let entry = |parent: &Document, data: u8| {
+            let entry = NewEntry::new("item", parent, data).unwrap();
+            schema.validate_new_entry(entry).unwrap().complete().unwrap()
+        };
+        let docs = vec![known.clone()];
+        let entries = vec![entry(&known, 1), entry(&unlisted, 2), entry(&known, 3)];
+        let json = bundle_to_json(&docs, &entries);
+        assert_eq!(json["documents"][0], doc_to_json(&known));
+        assert_eq!(json["entries"][1], entry_to_json(&entries[1]));
+
+        let mut schemas = SchemaCache::new();
+        schemas.insert(schema.clone());
+        let bundle = json_to_bundle(&json, &schemas).unwrap();
+        assert_eq!(bundle.documents.len(), 1);
+        assert_eq!(bundle.entries.len(), 2);
+        assert!(bundle.entries.iter().all(|e| matches!(e, MaybeEntry::NewEntry(e) if e.parent() == known.hash())));
+        assert_eq!(bundle.pending.len(), 1);
+        assert_eq!(bundle.pending[0].parent(), unlisted.hash());
+
+        // Without the schema, no document can be validated, so every entry is left pending
+        let bundle = json_to_bundle(&json, &HashMap::new()).unwrap();
+        assert_eq!((bundle.entries.len(), bundle.pending.len()), (0, 3));
+
+        // Re-importing skips what's already there, including signed documents with a recorded hash
+        let key = fog_crypto::identity::IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let signed = NewDocument::new(Some(schema.hash()), "signed").unwrap().sign(&key).unwrap();
+        let signed = schema.validate_new_doc(signed).unwrap();
+        let opts = EncodeOptions::new().doc_hash(true);
+        let docs = vec![known.clone(), signed.clone()];
+        let changed = bundle_to_json_with_options(&docs, &entries, &opts, &BundleOptions::new());
+        let existing: HashSet<Hash> = [known.hash(), signed.hash(), entries[0].hash()]
+            .into_iter()
+            .cloned()
+            .collect();
+        let bundle = json_to_bundle_changed(&changed, &schemas, &existing, &DecodeOptions::new()).unwrap();
+        assert!(bundle.documents.is_empty());
+        assert_eq!(bundle.unchanged, vec![known.hash().clone(), signed.hash().clone(), entries[0].hash().clone()]);
+        assert_eq!(bundle.entries.len(), 1);
+        assert_eq!(bundle.pending.len(), 1);
+        let existing = HashSet::from([known.hash().clone()]);
+        let bundle = json_to_bundle_changed(&changed, &schemas, &existing, &DecodeOptions::new()).unwrap();
+        assert!(matches!(&bundle.documents[..], [MaybeDocument::SignDocument(_)]));
+        assert_eq!(bundle.entries.len(), 2);
+
+        // The manifest catches changed items before anything is converted
+        let docs = vec![known.clone(), unlisted.clone()];
+        let bundle_opts = BundleOptions::new().manifest(true);
+        let mut with_manifest = bundle_to_json_with_options(&docs, &entries, &EncodeOptions::new(), &bundle_opts);
+        assert_eq!(with_manifest["manifest"]["entries"][1]["hash"], fog_to_json(&FogValue::Hash(entries[1].hash().clone())));
+        let bundle = json_to_bundle(&with_manifest, &schemas).unwrap();
+        assert_eq!((bundle.documents.len(), bundle.entries.len()), (2, 3));
+        let mut wrong_hash = with_manifest.clone();
+        wrong_hash["manifest"]["documents"][0]["hash"] = with_manifest["manifest"]["documents"][1]["hash"].clone();
+        let err = json_to_bundle(&wrong_hash, &schemas).err().unwrap();
+        assert!(matches!(&err, ObjectError::BundleItem { key: "documents", index: 0, src }
+            if matches!(**src, ObjectError::HashMismatch { .. })));
+        with_manifest["entries"][1]["data"] = JsonValue::from(7);
+        let err = json_to_bundle(&with_manifest, &schemas).err().unwrap();
+        assert!(matches!(err, ObjectError::BundleItem { key: "entries", index: 1, .. }));
+        with_manifest["entries"].as_array_mut().unwrap().pop();
+        let err = json_to_bundle(&with_manifest, &schemas).err().unwrap();
+        assert!(matches!(err, ObjectError::ManifestMismatch { key: "entries", listed: 3, actual: 2 }));
+
+        // Sorted bundles don't depend on the order the items came in
+        let opts = EncodeOptions::new();
+        let bundle_opts = BundleOptions::new().order(BundleOrder::Hash);
+        let mut reversed = entries.clone();
+        reversed.reverse();
+        let sorted = bundle_to_json_with_options(&[unlisted.clone(), known.clone()], &entries, &opts, &bundle_opts);
+        assert_eq!(sorted, bundle_to_json_with_options(&[known.clone(), unlisted.clone()], &reversed, &opts, &bundle_opts));
+        let mut expected = vec![&known, &unlisted];
+        expected.sort_by_key(|doc| doc.hash());
+        assert_eq!(sorted["documents"], expected.into_iter().map(doc_to_json).collect::<JsonValue>());
+
+        let mut json = json;
+        json["entries"][2]["key"] = JsonValue::Null;
+        let err = json_to_bundle(&json, &schemas).err().unwrap();
+        assert!(matches!(err, ObjectError::BundleItem { key: "entries", index: 2, .. }));
+        assert_eq!(err.pointer().as_deref(), Some("/entries/2/key"));
+    }
+}
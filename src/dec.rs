@@ -1,4 +1,6 @@
 use super::*;
+use std::borrow::Cow;
+use std::sync::Arc;
 
 use thiserror::Error;
 
@@ -25,12 +27,18 @@ pub enum DecodeError {
     /// Hex encoding for a fog-pack value was expected, but the encoding was invalid
     #[error("Invalid hexadecimal")]
     Hex(#[from] hex::FromHexError),
+    /// A `$fog-IdentityName:` couldn't be resolved, or no resolver was provided
+    #[error("Unknown Identity name \"{0}\"")]
+    UnknownIdentityName(String),
     /// An unrecognized `$fog-TYPE:` was found
     #[error("Unrecognized fog-pack type \"{0}\"")]
     UnrecognizedType(String),
     /// The time format couldn't be parsed as RFC3339
     #[error("Invalid Time")]
     InvalidTime(#[from] chrono::format::ParseError),
+    /// The time couldn't be represented as a fog-pack Timestamp
+    #[error("Invalid Timestamp")]
+    InvalidTimestamp,
     /// The floating-point value was invalid
     #[error("Invalid floating-point value")]
     InvalidFloat,
@@ -46,135 +54,1263 @@ pub enum DecodeError {
     /// A lockbox's data was invalid in some way
     #[error("Invalid Lockbox")]
     InvalidLockbox,
+    /// A `{"$fog-Bin": ...}` object didn't hold a string, an array of byte values, or an array of 
+    /// Base64 strings
+    #[error("Invalid binary array")]
+    InvalidBinArray,
+    /// The strings and object keys in the JSON added up to more bytes than the limit set with 
+    /// [`DecodeOptions::max_string_bytes`]
+    #[error("Strings add up to more than the limit of {0} bytes")]
+    StringLimit(usize),
+    /// An array or object had more elements than the limit set with 
+    /// [`DecodeOptions::max_elements`]
+    #[error("Array or object has more than the limit of {0} elements")]
+    ElementLimit(usize),
+    /// The binary data and lockboxes in the JSON added up to more bytes than the limit set with 
+    /// [`DecodeOptions::max_bin_bytes`]
+    #[error("Binary data adds up to more than the limit of {0} bytes")]
+    BinLimit(usize),
+    /// The JSON text couldn't be parsed
+    #[error("Invalid JSON: {0}")]
+    InvalidJson(String),
+    /// Converting JSON text failed. Holds the line and column in the text where the error was 
+    /// found, along with a JSON Pointer to the value being converted.
+    #[error("Couldn't convert JSON text at line {line}, column {column} (\"{path}\")")]
+    Located {
+        line: usize,
+        column: usize,
+        path: String,
+        #[source]
+        err: Box<DecodeError>,
+    },
+    /// An object in the JSON text had the same key more than once. The path is a JSON Pointer to 
+    /// the object.
+    #[error("Duplicate key \"{key}\" in object at \"{path}\"")]
+    DuplicateKey {
+        key: String,
+        path: String,
+    },
+    /// The JSON had arrays or objects nested deeper than the limit set with 
+    /// [`DecodeOptions::max_depth`]
+    #[error("JSON is nested deeper than the limit of {0}")]
+    DepthLimit(usize),
+    /// Strict decoding was enabled and a value wasn't in its canonical form
+    #[error("Non-canonical encoding: {0}")]
+    NonCanonical(&'static str),
+    /// A `$fog-BinOmitted:` placeholder was found. The binary data it stands in for was left out 
+    /// when encoding, so the value can't be recovered.
+    #[error("Binary value of {len} bytes was omitted from the JSON and can't be recovered")]
+    OmittedBin {
+        len: usize,
+    },
+    /// A `$fog-Redacted:` marker was found. The lockbox it stands in for was redacted when 
+    /// encoding, so the value can't be recovered.
+    #[error("{ty} of {len} bytes was redacted from the JSON and can't be recovered")]
+    Redacted {
+        ty: String,
+        len: usize,
+    },
+}
+
+impl DecodeError {
+    /// Get an RFC 6901 JSON Pointer to the value that failed to convert, relative to the root 
+    /// value being converted. This is empty if the root value itself failed.
+    pub fn pointer(&self) -> String {
+        let mut pointer = String::new();
+        let mut err = self;
+        loop {
+            err = match err {
+                DecodeError::Array { loc, err } => {
+                    push_pointer_segment(&mut pointer, &loc.to_string());
+                    err
+                },
+                DecodeError::Map { key, err } => {
+                    push_pointer_segment(&mut pointer, key);
+                    err
+                },
+                DecodeError::Located { path, err, .. } => {
+                    pointer.push_str(path);
+                    err
+                },
+                _ => return pointer,
+            };
+        }
+    }
+
+    /// Get the error underneath any errors that only record where it happened.
+    pub fn innermost(&self) -> &DecodeError {
+        match self {
+            DecodeError::Array { err, .. }
+            | DecodeError::Map { err, .. }
+            | DecodeError::Located { err, .. } => err.innermost(),
+            err => err,
+        }
+    }
+}
+
+/// Add a segment to a JSON Pointer, escaping it as needed.
+pub(crate) fn push_pointer_segment(pointer: &mut String, segment: &str) {
+    pointer.push('/');
+    pointer.push_str(&segment.replace('~', "~0").replace('/', "~1"));
 }
 
-fn base64_decode<T: AsRef<[u8]>>(input: T) -> Result<Vec<u8>, DecodeError> {
+/// Decoding engines for both Base64 alphabets that accept input with or without padding.
+const BASE64_DECODE: base64::engine::GeneralPurpose = decode_engine(&base64::alphabet::STANDARD);
+const BASE64_URL_SAFE_DECODE: base64::engine::GeneralPurpose = decode_engine(&base64::alphabet::URL_SAFE);
+
+const fn decode_engine(alphabet: &base64::alphabet::Alphabet) -> base64::engine::GeneralPurpose {
+    use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+    let config = GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+    GeneralPurpose::new(alphabet, config)
+}
+
+/// Decode Base64 in either the standard or the URL-safe alphabet, or only the standard one if 
+/// `standard_only` is set.
+fn base64_decode<T: AsRef<[u8]>>(input: T, standard_only: bool) -> Result<Vec<u8>, DecodeError> {
     use base64::engine::Engine;
-    base64::engine::general_purpose::STANDARD_NO_PAD.decode(input).map_err(DecodeError::Base64)
+    let input = input.as_ref();
+    if !standard_only && input.iter().any(|b| *b == b'-' || *b == b'_') {
+        BASE64_URL_SAFE_DECODE.decode(input).map_err(DecodeError::Base64)
+    }
+    else {
+        BASE64_DECODE.decode(input).map_err(DecodeError::Base64)
+    }
+}
+
+/// Decode a base58 primitive, which may have a multibase base58btc prefix. The prefix is also a 
+/// valid base58 character, so the value is first tried without stripping it.
+fn base58<T, E>(val: &str, parse: impl Fn(&str) -> Result<T, E>) -> Result<T, DecodeError> {
+    parse(val)
+        .or_else(|_| {
+            let stripped = val.strip_prefix(crate::enc::MULTIBASE_BASE58BTC).ok_or(())?;
+            parse(stripped).map_err(|_| ())
+        })
+        .map_err(|_| DecodeError::InvalidBase58)
+}
+
+/// Every type name recognized in a `$fog-TYPE:` string.
+pub(crate) const TYPE_NAMES: [&str; 21] = [
+    "Str", "F32", "F64", "Int", "F32Hex", "F64Hex", "Bin", "Hex", "Redacted", "BinOmitted", "Hash",
+    "Identity", "IdentityName", "StreamId", "LockId", "DataLockbox", "IdentityLockbox",
+    "StreamLockbox", "LockLockbox", "Time", "TimeRaw",
+];
+
+/// Alternate type names that are accepted in place of the real ones.
+const TYPE_ALIASES: [(&str, &str); 7] = [
+    ("Binary", "Bin"),
+    ("Bytes", "Bin"),
+    ("Timestamp", "Time"),
+    ("Float32", "F32"),
+    ("Float64", "F64"),
+    ("Integer", "Int"),
+    ("String", "Str"),
+];
+
+/// Count the significant digits in a decimal number, ignoring leading and trailing zeros.
+fn significant_digits(val: &str) -> usize {
+    let mantissa = val.split(['e', 'E']).next().unwrap_or("");
+    let digits: String = mantissa.chars().filter(|c| c.is_ascii_digit()).collect();
+    digits.trim_start_matches('0').trim_end_matches('0').len()
+}
+
+/// Parse the `NaN`, `Infinity`, and `-Infinity` keywords for non-finite floats.
+fn float_keyword(val: &str) -> Option<f64> {
+    match val {
+        "NaN" => Some(f64::NAN),
+        "Infinity" => Some(f64::INFINITY),
+        "-Infinity" => Some(f64::NEG_INFINITY),
+        _ => None,
+    }
+}
+
+/// Remove `_` digit separators, like in `1_000_000`. Returns `None` if there are none, or if any 
+/// underscore isn't between two digits, leaving the number to fail parsing.
+fn strip_separators(val: &str) -> Option<String> {
+    let b = val.as_bytes();
+    let separated = (0..b.len()).all(|i| {
+        b[i] != b'_' || (i > 0 && i + 1 < b.len() && b[i - 1].is_ascii_digit() && b[i + 1].is_ascii_digit())
+    });
+    (separated && val.contains('_')).then(|| val.replace('_', ""))
+}
+
+/// Check if a float was written with more digits than its type can hold.
+fn excess_float_digits(val: &str, shortest: &str) -> bool {
+    significant_digits(val) > significant_digits(shortest)
+}
+
+/// Check if an RFC 3339 time has nonzero fractional digits past nanosecond resolution.
+fn excess_subsec_digits(val: &str) -> bool {
+    val.split_once('.').is_some_and(|(_, frac)| {
+        frac.chars().take_while(|c| c.is_ascii_digit()).skip(9).any(|c| c != '0')
+    })
+}
+
+/// Parse decimal seconds since the Unix epoch, with up to 9 fractional digits.
+fn parse_epoch(val: &str) -> Result<fog_pack::types::Timestamp, DecodeError> {
+    let (negative, digits) = match val.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, val),
+    };
+    let (sec, frac) = digits.split_once('.').unwrap_or((digits, ""));
+    if sec.is_empty() || frac.len() > 9 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(DecodeError::InvalidTimestamp);
+    }
+    let sec = sec.parse::<u64>().map_err(|_| DecodeError::InvalidTimestamp)?;
+    let frac = if frac.is_empty() { 0 } else {
+        frac.parse::<u64>().map_err(|_| DecodeError::InvalidTimestamp)? * 10u64.pow(9 - frac.len() as u32)
+    };
+    let total = sec as i128 * 1_000_000_000 + frac as i128;
+    let total = if negative { -total } else { total };
+    let sec = i64::try_from(total.div_euclid(1_000_000_000)).map_err(|_| DecodeError::InvalidTimestamp)?;
+    let nano = total.rem_euclid(1_000_000_000) as u32;
+    fog_pack::types::Timestamp::from_utc(sec, nano).ok_or(DecodeError::InvalidTimestamp)
+}
+
+/// Options for converting JSON values into fog-pack.
+///
+/// The default options accept the standard encoding described in the [crate 
+/// documentation][crate]. Options are set builder-style:
+///
+/// ```
+/// # use fog_human_json::*;
+/// let opts = DecodeOptions::new().ignore_editor_keys(true);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DecodeOptions {
+    ignore_editor_keys: bool,
+    lenient: bool,
+    strict: bool,
+    base64_standard_only: bool,
+    case_insensitive_types: bool,
+    bin_wrapper: bool,
+    max_depth: Option<usize>,
+    max_string_bytes: Option<usize>,
+    max_elements: Option<usize>,
+    max_bin_bytes: Option<usize>,
+    type_prefix: Option<String>,
+    schema_names: Option<Arc<dyn SchemaNameResolver>>,
+    identity_names: Option<Arc<dyn IdentityNameResolver>>,
+}
+
+impl DecodeOptions {
+    /// Create the default set of decoding options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Silently drop well-known editor metadata keys (`"$schema"` and `"$id"`) from the root of 
+    /// the data and from Document/Entry/Query objects, instead of failing or converting them. 
+    /// JSON-Schema-aware editors insert these automatically.
+    pub fn ignore_editor_keys(mut self, enable: bool) -> Self {
+        self.ignore_editor_keys = enable;
+        self
+    }
+
+    /// Keep strings with an unrecognized `$fog-TYPE:` as plain strings instead of failing, so 
+    /// data written by newer versions of this crate can still be imported.
+    pub fn lenient(mut self, enable: bool) -> Self {
+        self.lenient = enable;
+        self
+    }
+
+    /// Only accept values in their canonical form, failing with [`DecodeError::NonCanonical`] 
+    /// otherwise. This rejects whitespace around the data in `$fog-TYPE:` strings, padded Base64, 
+    /// floats written with more digits than their type can hold, and times with nonzero digits 
+    /// past nanosecond resolution.
+    pub fn strict(mut self, enable: bool) -> Self {
+        self.strict = enable;
+        self
+    }
+
+    /// Only accept Base64 in the standard alphabet (`+/`) for binary data and lockboxes. By 
+    /// default, the URL-safe alphabet (`-_`) is accepted too.
+    pub fn base64_standard_only(mut self, enable: bool) -> Self {
+        self.base64_standard_only = enable;
+        self
+    }
+
+    /// Match the `$fog-` prefix and the type names in `$fog-TYPE:` strings without regard to 
+    /// ASCII case, so `$FOG-hash:` is read as `$fog-Hash:`.
+    ///
+    /// Type aliases like `Binary` for `Bin`, `Timestamp` for `Time`, or `Float32` for `F32` are 
+    /// always accepted, unless [strict decoding][DecodeOptions::strict] is enabled.
+    pub fn case_insensitive_types(mut self, enable: bool) -> Self {
+        self.case_insensitive_types = enable;
+        self
+    }
+
+    /// Read an object with a single `$fog-Bin` key as binary data, holding a Base64 string, an 
+    /// array of Base64 strings that are joined together, or an array of byte values, like 
+    /// `{"$fog-Bin": [0, 1, 2, 3]}`. This matches [`EncodeOptions::bin_array_wrapped`].
+    ///
+    /// Off by default, as a fog-pack map with that single key would be read as binary data too.
+    pub fn bin_wrapper(mut self, enable: bool) -> Self {
+        self.bin_wrapper = enable;
+        self
+    }
+
+    /// Fail with [`DecodeError::DepthLimit`] if arrays and objects are nested more than 
+    /// `max_depth` levels deep. Defaults to fog-pack's own [`MAX_DEPTH`][fog_pack::MAX_DEPTH], 
+    /// as deeper values can't be encoded anyway.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Fail with [`DecodeError::StringLimit`] if the strings and object keys add up to more than 
+    /// `max_bytes` bytes.
+    pub fn max_string_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_string_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Fail with [`DecodeError::ElementLimit`] if any array or object has more than `max_elements` 
+    /// elements.
+    pub fn max_elements(mut self, max_elements: usize) -> Self {
+        self.max_elements = Some(max_elements);
+        self
+    }
+
+    /// Fail with [`DecodeError::BinLimit`] if the binary data and lockboxes add up to more than 
+    /// `max_bytes` bytes, after decoding them from Base64 or hex.
+    pub fn max_bin_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bin_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Expect a different prefix than `$fog-` for type-annotated strings and wrapped binary 
+    /// arrays, matching [`EncodeOptions::type_prefix`]. Strings starting with `$fog-` are then 
+    /// plain strings.
+    pub fn type_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.type_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Resolve the `"schema_name"` of a Document with the given resolver when it has no 
+    /// `"schema"` hash. See [`EncodeOptions::schema_names`].
+    pub fn schema_names(mut self, resolver: Arc<dyn SchemaNameResolver>) -> Self {
+        self.schema_names = Some(resolver);
+        self
+    }
+
+    /// Resolve `$fog-IdentityName:<name>` strings into Identities with the given resolver. See 
+    /// [`EncodeOptions::identity_names`].
+    pub fn identity_names(mut self, resolver: Arc<dyn IdentityNameResolver>) -> Self {
+        self.identity_names = Some(resolver);
+        self
+    }
+
+    pub(crate) fn schema_name_resolver(&self) -> Option<&dyn SchemaNameResolver> {
+        self.schema_names.as_deref()
+    }
+
+    pub(crate) fn prefix(&self) -> &str {
+        self.type_prefix.as_deref().unwrap_or(FOG_PREFIX)
+    }
+
+    /// Strip the type prefix from a string, if it has one.
+    pub(crate) fn strip_prefix<'s>(&self, val: &'s str) -> Option<&'s str> {
+        let prefix = self.prefix();
+        if !self.case_insensitive_types {
+            return val.strip_prefix(prefix);
+        }
+        val.get(..prefix.len())
+            .filter(|start| start.eq_ignore_ascii_case(prefix))
+            .map(|_| &val[prefix.len()..])
+    }
+
+    /// Get the real type name for a name from a `$fog-TYPE:` string, resolving aliases and 
+    /// ignoring case if enabled. Unrecognized names are returned as-is.
+    pub(crate) fn type_name<'s>(&self, ty: &'s str) -> &'s str {
+        let matches = |name: &str| {
+            if self.case_insensitive_types { name.eq_ignore_ascii_case(ty) } else { name == ty }
+        };
+        if let Some(name) = TYPE_NAMES.iter().find(|name| matches(name)) {
+            return name;
+        }
+        TYPE_ALIASES.iter().find(|(alias, _)| matches(alias)).map_or(ty, |(_, name)| name)
+    }
+
+    /// Check if an object key is a `$fog-Bin` wrapper key, if wrappers are enabled.
+    pub(crate) fn is_bin_key(&self, key: &str) -> bool {
+        self.bin_wrapper && self.strip_prefix(key).map(|ty| self.type_name(ty)) == Some("Bin")
+    }
+
+    /// Check if a key in a root object should be skipped.
+    pub(crate) fn skip_key(&self, key: &str) -> bool {
+        self.ignore_editor_keys && EDITOR_KEYS.contains(&key)
+    }
 }
 
+/// Keys inserted by JSON-Schema-aware editors
+const EDITOR_KEYS: [&str; 2] = ["$schema", "$id"];
+
 /// Convert a JSON Value to a fog-pack value.
 pub fn json_to_fog(val: &JsonValue) -> Result<FogValue, DecodeError> {
-    Ok(match val {
-        JsonValue::Null => FogValue::Null,
-        JsonValue::Bool(b) => FogValue::Bool(*b),
-        JsonValue::Array(a) => {
-            let mut new_a = Vec::with_capacity(a.len());
-            for (loc, v) in a.iter().enumerate() {
-                new_a.push(json_to_fog(v).map_err(|e| DecodeError::Array { loc, err: Box::new(e) })?);
-            }
-            FogValue::Array(new_a)
-        },
-        JsonValue::Object(o) => {
-            let mut map = std::collections::BTreeMap::new();
-            for (k, v) in o.iter() {
-                let new_v = json_to_fog(v).map_err(|e| DecodeError::Map { key: k.to_string(), err: Box::new(e) })?;
-                map.insert(k.to_string(), new_v);
-            }
-            FogValue::Map(map)
-        },
-        JsonValue::Number(n) => {
-            if let Some(v) = n.as_u64() {
-                FogValue::Int(fog_pack::types::Integer::from(v))
-            }
-            else if let Some(v) = n.as_i64() {
-                FogValue::Int(fog_pack::types::Integer::from(v))
-            }
-            else {
-                FogValue::F64(n.as_f64().unwrap())
-            }
-        },
-        JsonValue::String(s) => {
-            if let Some(s) = s.strip_prefix(FOG_PREFIX) {
-                let (ty, untrimmed_val) = s.split_once(':').ok_or(DecodeError::BadFogType)?;
-                let val = untrimmed_val.trim();
-                match ty {
-                    "Str" => FogValue::Str(untrimmed_val.to_owned()),
-                    "F32" => {
-                        let f = val.parse::<f32>().map_err(|_| DecodeError::InvalidFloat)?;
-                        FogValue::F32(f)
-                    }
-                    "F64" => {
-                        let f = val.parse::<f64>().map_err(|_| DecodeError::InvalidFloat)?;
-                        FogValue::F64(f)
-                    }
-                    "Int" => {
-                        if val.starts_with('-') {
-                            let v = val.parse::<i64>().map_err(|_| DecodeError::InvalidInteger)?;
-                            FogValue::Int(fog_pack::types::Integer::from(v))
-                        }
-                        else {
-                            let v = val.parse::<u64>().map_err(|_| DecodeError::InvalidInteger)?;
-                            FogValue::Int(fog_pack::types::Integer::from(v))
+    json_to_fog_with_options(val, &DecodeOptions::default())
+}
+
+/// Convert a JSON Value to a fog-pack value, using the provided decoding options.
+pub fn json_to_fog_with_options(val: &JsonValue, opts: &DecodeOptions) -> Result<FogValue, DecodeError> {
+    Decoder::new(opts).root(val)
+}
+
+/// Convert a JSON Value to a fog-pack value, also returning warnings for any values that were 
+/// accepted but normalized, like typed values with whitespace around them.
+pub fn json_to_fog_with_warnings(
+    val: &JsonValue,
+    opts: &DecodeOptions
+) -> Result<(FogValue, Vec<Warning>), DecodeError> {
+    let mut dec = Decoder::new(opts);
+    dec.warnings = Warnings::collect();
+    let fog = dec.root(val)?;
+    Ok((fog, dec.warnings.into_vec()))
+}
+
+/// Convert a JSON Value to a fog-pack value, consuming it.
+///
+/// This gives the same result as [`json_to_fog`], but moves strings and object keys into the 
+/// result instead of cloning them.
+pub fn json_into_fog(val: JsonValue) -> Result<FogValue, DecodeError> {
+    json_into_fog_with_options(val, &DecodeOptions::default())
+}
+
+/// Convert a JSON Value to a fog-pack value, consuming it, as with [`json_into_fog`], using the 
+/// provided decoding options.
+pub fn json_into_fog_with_options(val: JsonValue, opts: &DecodeOptions) -> Result<FogValue, DecodeError> {
+    Decoder::new(opts).root_owned(val)
+}
+
+/// Convert a JSON Value to a fog-pack value, also returning statistics about the conversion.
+pub fn json_to_fog_with_stats(
+    val: &JsonValue,
+    opts: &DecodeOptions
+) -> Result<(FogValue, ConvertStats), DecodeError> {
+    let start = std::time::Instant::now();
+    let mut dec = Decoder::new(opts);
+    let fog = dec.root(val)?;
+    dec.stats.elapsed = start.elapsed();
+    Ok((fog, dec.stats))
+}
+
+/// An array or object partway through conversion, for converting without recursion.
+enum Frame<'j> {
+    Array(std::slice::Iter<'j, JsonValue>, Vec<FogValue>),
+    /// Also holds the key of the value currently being converted, and whether to skip editor keys
+    Object(serde_json::map::Iter<'j>, &'j str, BTreeMap<String, FogValue>, bool),
+}
+
+impl<'j> Frame<'j> {
+    fn next(&mut self, opts: &DecodeOptions) -> Option<&'j JsonValue> {
+        match self {
+            Frame::Array(iter, _) => iter.next(),
+            Frame::Object(iter, key, _, is_root) => {
+                let (k, v) = iter.find(|(k, _)| !(*is_root && opts.skip_key(k)))?;
+                *key = k;
+                Some(v)
+            },
+        }
+    }
+
+    fn push(&mut self, val: FogValue) {
+        match self {
+            Frame::Array(_, out) => out.push(val),
+            Frame::Object(_, key, out, _) => { out.insert(key.to_string(), val); },
+        }
+    }
+
+    /// Wrap an error from the value currently being converted with its location.
+    fn wrap(self, err: DecodeError) -> DecodeError {
+        match self {
+            Frame::Array(_, out) => DecodeError::Array { loc: out.len(), err: Box::new(err) },
+            Frame::Object(_, key, _, _) => DecodeError::Map { key: key.to_string(), err: Box::new(err) },
+        }
+    }
+
+    /// The JSON Pointer segment for the value currently being converted.
+    fn segment(&self) -> String {
+        match self {
+            Frame::Array(_, out) => out.len().to_string(),
+            Frame::Object(_, key, _, _) => key.to_string(),
+        }
+    }
+
+    fn finish(self) -> FogValue {
+        match self {
+            Frame::Array(_, out) => FogValue::Array(out),
+            Frame::Object(_, _, out, _) => FogValue::Map(out),
+        }
+    }
+}
+
+/// Get a JSON Pointer to the value currently being converted.
+fn frames_pointer(stack: &[Frame]) -> String {
+    let mut pointer = String::new();
+    for frame in stack {
+        push_pointer_segment(&mut pointer, &frame.segment());
+    }
+    pointer
+}
+
+/// An owned array or object partway through conversion.
+enum OwnedFrame {
+    Array(std::vec::IntoIter<JsonValue>, Vec<FogValue>),
+    /// Also holds the key of the value currently being converted, and whether to skip editor keys
+    Object(serde_json::map::IntoIter, String, BTreeMap<String, FogValue>, bool),
+}
+
+impl OwnedFrame {
+    fn next(&mut self, opts: &DecodeOptions) -> Option<JsonValue> {
+        match self {
+            OwnedFrame::Array(iter, _) => iter.next(),
+            OwnedFrame::Object(iter, key, _, is_root) => {
+                let (k, v) = iter.find(|(k, _)| !(*is_root && opts.skip_key(k)))?;
+                *key = k;
+                Some(v)
+            },
+        }
+    }
+
+    fn push(&mut self, val: FogValue) {
+        match self {
+            OwnedFrame::Array(_, out) => out.push(val),
+            OwnedFrame::Object(_, key, out, _) => { out.insert(std::mem::take(key), val); },
+        }
+    }
+
+    /// Wrap an error from the value currently being converted with its location.
+    fn wrap(self, err: DecodeError) -> DecodeError {
+        match self {
+            OwnedFrame::Array(_, out) => DecodeError::Array { loc: out.len(), err: Box::new(err) },
+            OwnedFrame::Object(_, key, _, _) => DecodeError::Map { key, err: Box::new(err) },
+        }
+    }
+
+    fn finish(self) -> FogValue {
+        match self {
+            OwnedFrame::Array(_, out) => FogValue::Array(out),
+            OwnedFrame::Object(_, _, out, _) => FogValue::Map(out),
+        }
+    }
+}
+
+/// Converts JSON values to fog-pack while tracking conversion statistics.
+pub(crate) struct Decoder<'a> {
+    pub(crate) opts: &'a DecodeOptions,
+    pub(crate) stats: ConvertStats,
+    pub(crate) warnings: Warnings,
+    pub(crate) depth: usize,
+    string_bytes: usize,
+    bin_bytes: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub(crate) fn new(opts: &'a DecodeOptions) -> Self {
+        Self {
+            opts,
+            stats: ConvertStats::default(),
+            warnings: Warnings::default(),
+            depth: 0,
+            string_bytes: 0,
+            bin_bytes: 0,
+        }
+    }
+
+    /// Whether non-canonical forms need to be looked for, either to reject them or to warn about 
+    /// them.
+    fn checking(&self) -> bool {
+        self.opts.strict || self.warnings.enabled()
+    }
+
+    /// Accept a non-canonical form with a warning, unless strict decoding is enabled.
+    fn tolerate(&mut self, kind: WarningKind) -> Result<(), DecodeError> {
+        if self.opts.strict {
+            return Err(DecodeError::NonCanonical(kind.message()));
+        }
+        self.warnings.push(kind);
+        Ok(())
+    }
+
+    /// Remove `_` digit separators from a number, as a non-canonical form.
+    fn digits<'v>(&mut self, val: &'v str) -> Result<Cow<'v, str>, DecodeError> {
+        match strip_separators(val) {
+            Some(stripped) => {
+                self.tolerate(WarningKind::DigitSeparators)?;
+                Ok(Cow::Owned(stripped))
+            },
+            None => Ok(Cow::Borrowed(val)),
+        }
+    }
+
+    /// Check that going one array or object deeper stays within the depth limit.
+    pub(crate) fn check_depth(&self) -> Result<(), DecodeError> {
+        let max_depth = self.opts.max_depth.unwrap_or(fog_pack::MAX_DEPTH);
+        if self.depth >= max_depth {
+            return Err(DecodeError::DepthLimit(max_depth));
+        }
+        Ok(())
+    }
+
+    /// Check an array or object's length against the element limit.
+    pub(crate) fn check_len(&self, len: usize) -> Result<(), DecodeError> {
+        match self.opts.max_elements {
+            Some(max) if len > max => Err(DecodeError::ElementLimit(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Count string bytes against the limit.
+    pub(crate) fn add_string(&mut self, len: usize) -> Result<(), DecodeError> {
+        self.string_bytes = self.string_bytes.saturating_add(len);
+        match self.opts.max_string_bytes {
+            Some(max) if self.string_bytes > max => Err(DecodeError::StringLimit(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Count binary bytes against the limit.
+    fn add_bin(&mut self, len: usize) -> Result<(), DecodeError> {
+        self.bin_bytes = self.bin_bytes.saturating_add(len);
+        match self.opts.max_bin_bytes {
+            Some(max) if self.bin_bytes > max => Err(DecodeError::BinLimit(max)),
+            _ => Ok(()),
+        }
+    }
+
+    fn base64<T: AsRef<[u8]>>(&mut self, input: T) -> Result<Vec<u8>, DecodeError> {
+        if input.as_ref().ends_with(b"=") {
+            self.tolerate(WarningKind::PaddedBase64)?;
+        }
+        let bytes = base64_decode(input, self.opts.base64_standard_only)?;
+        self.add_bin(bytes.len())?;
+        self.stats.base64_bytes += bytes.len() as u64;
+        Ok(bytes)
+    }
+
+    /// Decode the contents of a `{"$fog-Bin": ...}` wrapper object. This can be a single Base64 
+    /// string, an array of byte values, or an array of Base64 strings that are joined together 
+    /// before decoding.
+    pub(crate) fn bin_wrapper(&mut self, val: &JsonValue) -> Result<Vec<u8>, DecodeError> {
+        match val {
+            JsonValue::String(s) => self.base64(s.trim()),
+            JsonValue::Array(a) if a.iter().all(|v| v.is_string()) => {
+                let joined: String = a.iter()
+                    .filter_map(|v| v.as_str())
+                    .map(|v| v.trim())
+                    .collect();
+                self.base64(joined)
+            },
+            JsonValue::Array(a) => {
+                self.add_bin(a.len())?;
+                a.iter()
+                    .map(|v| {
+                        v.as_u64()
+                            .and_then(|v| u8::try_from(v).ok())
+                            .ok_or(DecodeError::InvalidBinArray)
+                    })
+                    .collect()
+            },
+            _ => Err(DecodeError::InvalidBinArray),
+        }
+    }
+
+    /// Convert the root of a data value, which may have editor keys to skip.
+    pub(crate) fn root(&mut self, val: &JsonValue) -> Result<FogValue, DecodeError> {
+        self.convert(val, true)
+    }
+
+    pub(crate) fn value(&mut self, val: &JsonValue) -> Result<FogValue, DecodeError> {
+        self.convert(val, false)
+    }
+
+    /// Convert a value without recursing, keeping the arrays and objects partway through 
+    /// conversion on an explicit stack.
+    fn convert(&mut self, val: &JsonValue, is_root: bool) -> Result<FogValue, DecodeError> {
+        let mut stack: Vec<Frame> = Vec::new();
+        let mut current = val;
+        loop {
+            let warned = self.warnings.len();
+            let mut finished = self.start(current, is_root && stack.is_empty(), &mut stack);
+            self.warnings.locate(warned, || frames_pointer(&stack));
+            // Finish arrays and objects until one has another value to convert
+            loop {
+                let Some(frame) = stack.last_mut() else {
+                    return finished.map(|v| v.expect("Root value should be finished once the stack is empty"));
+                };
+                match finished {
+                    Ok(Some(v)) => frame.push(v),
+                    Ok(None) => (),
+                    Err(mut err) => {
+                        // Record where the error happened in every enclosing array and object
+                        while let Some(frame) = stack.pop() {
+                            err = frame.wrap(err);
+                            self.depth -= 1;
                         }
+                        return Err(err);
                     },
-                    "F32Hex" => {
-                        use hex::FromHex;
-                        let bytes = <[u8;4]>::from_hex(val)?;
-                        FogValue::F32(f32::from_be_bytes(bytes))
-                    },
-                    "F64Hex" => {
-                        use hex::FromHex;
-                        let bytes = <[u8;8]>::from_hex(val)?;
-                        FogValue::F64(f64::from_be_bytes(bytes))
-                    },
-                    "Bin" => FogValue::Bin(base64_decode(val)?),
-                    "Hash" => {
-                        let v = fog_pack::types::Hash::from_base58(val).map_err(|_| DecodeError::InvalidBase58)?;
-                        FogValue::Hash(v)
-                    },
-                    "Identity" => {
-                        let v = fog_pack::types::Identity::from_base58(val).map_err(|_| DecodeError::InvalidBase58)?;
-                        FogValue::Identity(v)
-                    },
-                    "StreamId" => {
-                        let v = fog_pack::types::StreamId::from_base58(val).map_err(|_| DecodeError::InvalidBase58)?;
-                        FogValue::StreamId(v)
-                    },
-                    "LockId" => {
-                        let v = fog_pack::types::LockId::from_base58(val).map_err(|_| DecodeError::InvalidBase58)?;
-                        FogValue::LockId(v)
-                    },
-                    "DataLockbox" => {
-                        let bytes = base64_decode(val)?;
-                        let lockbox = fog_pack::types::DataLockboxRef::from_bytes(&bytes)
-                            .map_err(|_| DecodeError::InvalidLockbox)?
-                            .to_owned();
-                        FogValue::DataLockbox(lockbox)
+                }
+                match frame.next(self.opts) {
+                    Some(child) => {
+                        current = child;
+                        break;
                     },
-                    "IdentityLockbox" => {
-                        let bytes = base64_decode(val)?;
-                        let lockbox = fog_pack::types::IdentityLockboxRef::from_bytes(&bytes)
-                            .map_err(|_| DecodeError::InvalidLockbox)?
-                            .to_owned();
-                        FogValue::IdentityLockbox(lockbox)
+                    None => {
+                        finished = Ok(stack.pop().map(Frame::finish));
+                        self.depth -= 1;
                     },
-                    "StreamLockbox" => {
-                        let bytes = base64_decode(val)?;
-                        let lockbox = fog_pack::types::StreamLockboxRef::from_bytes(&bytes)
-                            .map_err(|_| DecodeError::InvalidLockbox)?
-                            .to_owned();
-                        FogValue::StreamLockbox(lockbox)
+                }
+            }
+        }
+    }
+
+    /// Start converting a value, returning it if it isn't an array or object. Arrays and objects 
+    /// are pushed onto the stack instead.
+    fn start<'j>(
+        &mut self,
+        val: &'j JsonValue,
+        is_root: bool,
+        stack: &mut Vec<Frame<'j>>
+    ) -> Result<Option<FogValue>, DecodeError> {
+        self.stats.nodes += 1;
+        if !matches!(val, JsonValue::Array(_) | JsonValue::Object(_)) {
+            return self.value_inner(val).map(Some);
+        }
+        self.check_depth()?;
+        self.check_len(match val {
+            JsonValue::Array(a) => a.len(),
+            JsonValue::Object(o) => o.len(),
+            _ => unreachable!(),
+        })?;
+        match val {
+            JsonValue::Object(o) => {
+                if let (1, Some((k, v))) = (o.len(), o.iter().next()) {
+                    if self.opts.is_bin_key(k) {
+                        return Ok(Some(FogValue::Bin(self.bin_wrapper(v)?)));
+                    }
+                }
+                self.add_string(o.keys().map(|k| k.len()).sum())?;
+                stack.push(Frame::Object(o.iter(), "", BTreeMap::new(), is_root));
+            },
+            JsonValue::Array(a) => stack.push(Frame::Array(a.iter(), Vec::with_capacity(a.len()))),
+            _ => unreachable!(),
+        }
+        self.depth += 1;
+        Ok(None)
+    }
+
+    /// Convert the root of a data value, consuming it. Like [`convert`][Self::convert], this keeps 
+    /// the arrays and objects partway through conversion on an explicit stack.
+    pub(crate) fn root_owned(&mut self, val: JsonValue) -> Result<FogValue, DecodeError> {
+        let mut stack: Vec<OwnedFrame> = Vec::new();
+        let mut current = val;
+        loop {
+            let is_root = stack.is_empty();
+            let mut finished = self.start_owned(current, is_root, &mut stack);
+            loop {
+                let Some(frame) = stack.last_mut() else {
+                    return finished.map(|v| v.expect("Root value should be finished once the stack is empty"));
+                };
+                match finished {
+                    Ok(Some(v)) => frame.push(v),
+                    Ok(None) => (),
+                    Err(mut err) => {
+                        while let Some(frame) = stack.pop() {
+                            err = frame.wrap(err);
+                            self.depth -= 1;
+                        }
+                        return Err(err);
                     },
-                    "LockLockbox" => {
-                        let bytes = base64_decode(val)?;
-                        let lockbox = fog_pack::types::LockLockboxRef::from_bytes(&bytes)
-                            .map_err(|_| DecodeError::InvalidLockbox)?
-                            .to_owned();
-                        FogValue::LockLockbox(lockbox)
+                }
+                match frame.next(self.opts) {
+                    Some(child) => {
+                        current = child;
+                        break;
                     },
-                    "Time" => {
-                        let time = chrono::DateTime::parse_from_rfc3339(val)?;
-                        let sec = time.timestamp();
-                        let nano = time.timestamp_subsec_nanos();
-                        FogValue::Timestamp(fog_pack::types::Timestamp::from_utc(sec, nano).unwrap())
+                    None => {
+                        finished = Ok(stack.pop().map(OwnedFrame::finish));
+                        self.depth -= 1;
                     },
-                    _ => return Err(DecodeError::UnrecognizedType(ty.to_owned())),
                 }
             }
-            else {
-                FogValue::Str(s.to_owned())
+        }
+    }
+
+    /// Start converting an owned value, as with [`start`][Self::start].
+    fn start_owned(
+        &mut self,
+        val: JsonValue,
+        is_root: bool,
+        stack: &mut Vec<OwnedFrame>
+    ) -> Result<Option<FogValue>, DecodeError> {
+        self.stats.nodes += 1;
+        match val {
+            JsonValue::String(s) => return self.string_owned(s).map(Some),
+            JsonValue::Array(a) => {
+                self.check_depth()?;
+                self.check_len(a.len())?;
+                let len = a.len();
+                stack.push(OwnedFrame::Array(a.into_iter(), Vec::with_capacity(len)));
+            },
+            JsonValue::Object(o) => {
+                self.check_depth()?;
+                self.check_len(o.len())?;
+                if let (1, Some((k, v))) = (o.len(), o.iter().next()) {
+                    if self.opts.is_bin_key(k) {
+                        return Ok(Some(FogValue::Bin(self.bin_wrapper(v)?)));
+                    }
+                }
+                self.add_string(o.keys().map(|k| k.len()).sum())?;
+                stack.push(OwnedFrame::Object(o.into_iter(), String::new(), BTreeMap::new(), is_root));
+            },
+            val => return self.value_inner(&val).map(Some),
+        }
+        self.depth += 1;
+        Ok(None)
+    }
+
+    /// Convert a value that isn't an array or object.
+    pub(crate) fn value_inner(&mut self, val: &JsonValue) -> Result<FogValue, DecodeError> {
+        Ok(match val {
+            JsonValue::Null => FogValue::Null,
+            JsonValue::Bool(b) => FogValue::Bool(*b),
+            JsonValue::Array(_) | JsonValue::Object(_) => unreachable!("Containers are converted by convert()"),
+            JsonValue::Number(n) => {
+                if let Some(v) = n.as_u64() {
+                    FogValue::Int(fog_pack::types::Integer::from(v))
+                }
+                else if let Some(v) = n.as_i64() {
+                    FogValue::Int(fog_pack::types::Integer::from(v))
+                }
+                else {
+                    // Integers too big for fog-pack would otherwise be rounded to the nearest F64
+                    #[cfg(feature = "arbitrary_precision")]
+                    if !n.as_str().contains(['.', 'e', 'E']) {
+                        return Err(DecodeError::InvalidInteger);
+                    }
+                    FogValue::F64(n.as_f64().ok_or(DecodeError::InvalidFloat)?)
+                }
+            },
+            JsonValue::String(original) => self.string(original)?,
+        })
+    }
+
+    /// Convert an owned string, moving it into the result if it isn't a `$fog-TYPE:` string.
+    fn string_owned(&mut self, original: String) -> Result<FogValue, DecodeError> {
+        if self.opts.strip_prefix(&original).is_some() {
+            return self.string(&original);
+        }
+        self.add_string(original.len())?;
+        Ok(FogValue::Str(original))
+    }
+
+    /// Convert a string, which may be a `$fog-TYPE:` string.
+    pub(crate) fn string(&mut self, original: &str) -> Result<FogValue, DecodeError> {
+        let Some(s) = self.opts.strip_prefix(original) else {
+            self.add_string(original.len())?;
+            return Ok(FogValue::Str(original.to_owned()));
+        };
+        self.stats.annotations += 1;
+        let (written_ty, untrimmed_val) = s.split_once(':').ok_or(DecodeError::BadFogType)?;
+        let ty = self.opts.type_name(written_ty);
+        if ty != written_ty {
+            self.tolerate(WarningKind::TypeAlias)?;
+        }
+        let val = untrimmed_val.trim();
+        if ty != "Str" && val.len() != untrimmed_val.len() {
+            self.tolerate(WarningKind::TrimmedWhitespace)?;
+        }
+        Ok(match ty {
+            "Str" => {
+                self.add_string(untrimmed_val.len())?;
+                FogValue::Str(untrimmed_val.to_owned())
+            },
+            "F32" => {
+                if let Some(f) = float_keyword(val) {
+                    return Ok(FogValue::F32(f as f32));
+                }
+                let val = self.digits(val)?;
+                let f = val.parse::<f32>().map_err(|_| DecodeError::InvalidFloat)?;
+                if self.checking() && excess_float_digits(&val, ryu::Buffer::new().format(f)) {
+                    self.tolerate(WarningKind::RoundedF32)?;
+                }
+                FogValue::F32(f)
+            }
+            "F64" => {
+                if let Some(f) = float_keyword(val) {
+                    return Ok(FogValue::F64(f));
+                }
+                let val = self.digits(val)?;
+                let f = val.parse::<f64>().map_err(|_| DecodeError::InvalidFloat)?;
+                if self.checking() && excess_float_digits(&val, ryu::Buffer::new().format(f)) {
+                    self.tolerate(WarningKind::RoundedF64)?;
+                }
+                FogValue::F64(f)
             }
+            "Int" => {
+                let val = self.digits(val)?;
+                if val.starts_with('-') {
+                    let v = val.parse::<i64>().map_err(|_| DecodeError::InvalidInteger)?;
+                    FogValue::Int(fog_pack::types::Integer::from(v))
+                }
+                else {
+                    let v = val.parse::<u64>().map_err(|_| DecodeError::InvalidInteger)?;
+                    FogValue::Int(fog_pack::types::Integer::from(v))
+                }
+            },
+            "F32Hex" => {
+                use hex::FromHex;
+                let bytes = <[u8;4]>::from_hex(val)?;
+                FogValue::F32(f32::from_be_bytes(bytes))
+            },
+            "F64Hex" => {
+                use hex::FromHex;
+                let bytes = <[u8;8]>::from_hex(val)?;
+                FogValue::F64(f64::from_be_bytes(bytes))
+            },
+            "Bin" => FogValue::Bin(self.base64(val)?),
+            "Hex" => {
+                self.add_bin(val.len() / 2)?;
+                FogValue::Bin(hex::decode(val)?)
+            },
+            "Redacted" => {
+                let (ty, len) = val.split_once(',').ok_or(DecodeError::BadFogType)?;
+                let len = len.trim().parse::<usize>().map_err(|_| DecodeError::InvalidInteger)?;
+                return Err(DecodeError::Redacted { ty: ty.trim().to_owned(), len });
+            },
+            "BinOmitted" => {
+                let len = val.split(',').next().unwrap_or("").trim();
+                let len = len.parse::<usize>().map_err(|_| DecodeError::InvalidInteger)?;
+                return Err(DecodeError::OmittedBin { len });
+            },
+            "Hash" => {
+                let v = base58(val, fog_pack::types::Hash::from_base58)?;
+                FogValue::Hash(v)
+            },
+            "Identity" => {
+                // Skip over any name appended in parentheses
+                let val = match val.split_once(char::is_whitespace) {
+                    Some((id, name)) if name.trim().starts_with('(') && name.ends_with(')') => id,
+                    _ => val,
+                };
+                let v = base58(val, fog_pack::types::Identity::from_base58)?;
+                FogValue::Identity(v)
+            },
+            "IdentityName" => {
+                let v = self.opts.identity_names.as_ref()
+                    .and_then(|r| r.identity(val))
+                    .ok_or_else(|| DecodeError::UnknownIdentityName(val.to_owned()))?;
+                FogValue::Identity(v)
+            },
+            "StreamId" => {
+                let v = base58(val, fog_pack::types::StreamId::from_base58)?;
+                FogValue::StreamId(v)
+            },
+            "LockId" => {
+                let v = base58(val, fog_pack::types::LockId::from_base58)?;
+                FogValue::LockId(v)
+            },
+            "DataLockbox" => {
+                let bytes = self.base64(val)?;
+                let lockbox = fog_pack::types::DataLockboxRef::from_bytes(&bytes)
+                    .map_err(|_| DecodeError::InvalidLockbox)?
+                    .to_owned();
+                FogValue::DataLockbox(lockbox)
+            },
+            "IdentityLockbox" => {
+                let bytes = self.base64(val)?;
+                let lockbox = fog_pack::types::IdentityLockboxRef::from_bytes(&bytes)
+                    .map_err(|_| DecodeError::InvalidLockbox)?
+                    .to_owned();
+                FogValue::IdentityLockbox(lockbox)
+            },
+            "StreamLockbox" => {
+                let bytes = self.base64(val)?;
+                let lockbox = fog_pack::types::StreamLockboxRef::from_bytes(&bytes)
+                    .map_err(|_| DecodeError::InvalidLockbox)?
+                    .to_owned();
+                FogValue::StreamLockbox(lockbox)
+            },
+            "LockLockbox" => {
+                let bytes = self.base64(val)?;
+                let lockbox = fog_pack::types::LockLockboxRef::from_bytes(&bytes)
+                    .map_err(|_| DecodeError::InvalidLockbox)?
+                    .to_owned();
+                FogValue::LockLockbox(lockbox)
+            },
+            "Time" if val.starts_with(|c: char| c == '-' || c.is_ascii_digit())
+                && val.bytes().all(|b| b == b'-' || b == b'.' || b.is_ascii_digit()) =>
+            {
+                FogValue::Timestamp(parse_epoch(val)?)
+            },
+            "Time" => {
+                if self.checking() && excess_subsec_digits(val) {
+                    self.tolerate(WarningKind::TruncatedTime)?;
+                }
+                let time = chrono::DateTime::parse_from_rfc3339(val)?;
+                let sec = time.timestamp();
+                let nano = time.timestamp_subsec_nanos();
+                let time = fog_pack::types::Timestamp::from_utc(sec, nano)
+                    .ok_or(DecodeError::InvalidTimestamp)?;
+                FogValue::Timestamp(time)
+            },
+            "TimeRaw" => {
+                let (sec, nano) = val.split_once(',').ok_or(DecodeError::InvalidTimestamp)?;
+                let sec = sec.trim().parse::<i64>().map_err(|_| DecodeError::InvalidTimestamp)?;
+                let nano = nano.trim().parse::<u32>().map_err(|_| DecodeError::InvalidTimestamp)?;
+                let time = fog_pack::types::Timestamp::from_utc(sec, nano)
+                    .ok_or(DecodeError::InvalidTimestamp)?;
+                FogValue::Timestamp(time)
+            },
+            _ if self.opts.lenient => {
+                self.warnings.push(WarningKind::UnrecognizedType(ty.to_owned()));
+                self.add_string(original.len())?;
+                FogValue::Str(original.to_owned())
+            },
+            _ => return Err(DecodeError::UnrecognizedType(ty.to_owned())),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn bin_wrapper() {
+        let opts = DecodeOptions::new().bin_wrapper(true);
+        let expected = FogValue::Bin(vec![0, 1, 2, 3, 4]);
+        let forms = [
+            json!({"$fog-Bin": "AAECAwQ"}),
+            json!({"$fog-Bin": ["AAEC", "AwQ"]}),
+            json!({"$fog-Bin": [0, 1, 2, 3, 4]}),
+        ];
+        for form in forms.iter() {
+            assert_eq!(json_to_fog_with_options(form, &opts).unwrap(), expected);
         }
-    })
+        assert!(json_to_fog_with_options(&json!({"$fog-Bin": [0, 256]}), &opts).is_err());
+        assert!(json_to_fog_with_options(&json!({"$fog-Bin": [0, "AAEC"]}), &opts).is_err());
+    }
+
+    #[test]
+    fn bin_key_round_trip() {
+        // Without wrappers enabled, maps that look like one still come back as maps
+        for key in ["$fog-Bin", "$fog-Binary"] {
+            let val = FogValue::Map(BTreeMap::from([(key.to_owned(), FogValue::from("AAEC"))]));
+            assert_eq!(json_to_fog(&fog_to_json(&val)).unwrap(), val);
+        }
+    }
+
+    #[test]
+    fn lenient() {
+        let json = json!(["$fog-Future:abc", "$fog-Int:5"]);
+        assert!(matches!(json_to_fog(&json), Err(DecodeError::Array { loc: 0, .. })));
+        let opts = DecodeOptions::new().lenient(true);
+        let expected = FogValue::Array(vec![FogValue::from("$fog-Future:abc"), FogValue::from(5u8)]);
+        assert_eq!(json_to_fog_with_options(&json, &opts).unwrap(), expected);
+        assert!(json_to_fog_with_options(&json!("$fog-Int:x"), &opts).is_err());
+    }
+
+    #[test]
+    fn strict() {
+        let opts = DecodeOptions::new().strict(true);
+        let canonical = [
+            "$fog-F32:0.1", "$fog-F32:1e-7", "$fog-F64:0.30000000000000004", "$fog-Bin:AAECAwQ",
+            "$fog-Time:2023-07-12T17:33:13.123456789Z", "$fog-Str: padded ",
+        ];
+        for s in canonical.iter() {
+            let json = json!(s);
+            assert_eq!(json_to_fog_with_options(&json, &opts).unwrap(), json_to_fog(&json).unwrap());
+        }
+        let non_canonical = [
+            "$fog-Int: 5", "$fog-F32:0.100000001", "$fog-F64:0.300000000000000001",
+            "$fog-Bin:AAECAwQ=", "$fog-Time:2023-07-12T17:33:13.1234567891Z", "$fog-Int:1_000",
+        ];
+        for s in non_canonical.iter() {
+            let json = json!(s);
+            assert!(json_to_fog(&json).is_ok());
+            assert!(matches!(json_to_fog_with_options(&json, &opts), Err(DecodeError::NonCanonical(_))), "{}", s);
+        }
+    }
+
+    #[test]
+    fn digit_separators() {
+        assert_eq!(json_to_fog(&json!("$fog-Int:1_000_000_000")).unwrap(), FogValue::from(1_000_000_000u32));
+        assert_eq!(json_to_fog(&json!("$fog-Int:-1_000")).unwrap(), FogValue::from(-1000i32));
+        assert_eq!(json_to_fog(&json!("$fog-F64:1_234.5")).unwrap(), FogValue::F64(1234.5));
+        assert_eq!(json_to_fog(&json!("$fog-F32:0.000_1")).unwrap(), FogValue::F32(0.0001));
+        for s in ["$fog-Int:_1", "$fog-Int:1_", "$fog-Int:1__0", "$fog-F64:1_.5", "$fog-Hex:00_01"] {
+            assert!(json_to_fog(&json!(s)).is_err(), "{}", s);
+        }
+        let (_, warnings) = json_to_fog_with_warnings(&json!("$fog-Int:1_0"), &DecodeOptions::new()).unwrap();
+        assert_eq!(warnings[0].kind, WarningKind::DigitSeparators);
+        // Numbers are still written without separators
+        assert_eq!(fog_to_json(&FogValue::from(u64::MAX)), json!(u64::MAX));
+    }
+
+    #[test]
+    fn float_keywords() {
+        assert!(matches!(json_to_fog(&json!("$fog-F64:NaN")), Ok(FogValue::F64(f)) if f.is_nan()));
+        assert!(matches!(json_to_fog(&json!("$fog-F32:NaN")), Ok(FogValue::F32(f)) if f.to_bits() == f32::NAN.to_bits()));
+        assert_eq!(json_to_fog(&json!("$fog-F32:Infinity")).unwrap(), FogValue::F32(f32::INFINITY));
+        assert_eq!(json_to_fog(&json!("$fog-F64: -Infinity")).unwrap(), FogValue::F64(f64::NEG_INFINITY));
+        let opts = DecodeOptions::new().strict(true);
+        assert!(json_to_fog_with_options(&json!("$fog-F64:Infinity"), &opts).is_ok());
+    }
+
+    #[test]
+    fn base64_variants() {
+        let expected = FogValue::Bin(vec![0xfb, 0xff, 0xbf, 0x01]);
+        for s in ["$fog-Bin:+/+/AQ", "$fog-Bin:+/+/AQ==", "$fog-Bin:-_-_AQ", "$fog-Bin:-_-_AQ=="] {
+            assert_eq!(json_to_fog(&json!(s)).unwrap(), expected);
+        }
+        let opts = DecodeOptions::new().base64_standard_only(true);
+        assert_eq!(json_to_fog_with_options(&json!("$fog-Bin:+/+/AQ=="), &opts).unwrap(), expected);
+        assert!(json_to_fog_with_options(&json!("$fog-Bin:-_-_AQ"), &opts).is_err());
+    }
+
+    #[test]
+    fn type_aliases() {
+        assert_eq!(json_to_fog(&json!("$fog-Integer:5")).unwrap(), FogValue::from(5u8));
+        assert_eq!(json_to_fog(&json!("$fog-Binary:AAEC")).unwrap(), FogValue::Bin(vec![0, 1, 2]));
+        let opts = DecodeOptions::new().bin_wrapper(true);
+        assert_eq!(json_to_fog_with_options(&json!({"$fog-Bytes": [1]}), &opts).unwrap(), FogValue::Bin(vec![1]));
+        assert!(json_to_fog(&json!("$fog-int:5")).is_err());
+        assert_eq!(json_to_fog(&json!("$FOG-int:5")).unwrap(), FogValue::from("$FOG-int:5"));
+
+        let opts = DecodeOptions::new().case_insensitive_types(true);
+        for s in ["$fog-int:5", "$FOG-INT:5", "$Fog-integer:5"] {
+            assert_eq!(json_to_fog_with_options(&json!(s), &opts).unwrap(), FogValue::from(5u8));
+        }
+        let opts = DecodeOptions::new().strict(true);
+        assert!(matches!(
+            json_to_fog_with_options(&json!("$fog-Integer:5"), &opts),
+            Err(DecodeError::NonCanonical(_))
+        ));
+    }
+
+    #[test]
+    fn max_depth() {
+        let nested = |depth: usize| (0..depth).fold(json!(0), |inner, _| json!([inner]));
+        assert!(json_to_fog(&nested(fog_pack::MAX_DEPTH)).is_ok());
+        let mut err = json_to_fog(&nested(fog_pack::MAX_DEPTH + 1)).unwrap_err();
+        while let DecodeError::Array { err: inner, .. } = err { err = *inner; }
+        assert!(matches!(err, DecodeError::DepthLimit(fog_pack::MAX_DEPTH)));
+
+        let opts = DecodeOptions::new().max_depth(2);
+        assert!(json_to_fog_with_options(&json!({"a": [1]}), &opts).is_ok());
+        assert!(json_to_fog_with_options(&json!({"a": [[1]]}), &opts).is_err());
+    }
+
+    #[test]
+    fn no_recursion() {
+        // Far deeper than the stack could handle if conversion recursed
+        let depth = 100_000;
+        let json = (0..depth).fold(json!("$fog-Int:1"), |inner, _| JsonValue::Array(vec![inner]));
+        let fog = json_to_fog_with_options(&json, &DecodeOptions::new().max_depth(usize::MAX)).unwrap();
+        let back = fog_into_json_with_options(fog, &EncodeOptions::new().max_depth(usize::MAX));
+        let mut inner = &back;
+        for _ in 0..depth {
+            inner = &inner[0];
+        }
+        assert_eq!(inner, &json!(1));
+        crate::tests::drop_json(json);
+        crate::tests::drop_json(back);
+    }
+
+    #[test]
+    fn limits() {
+        let json = json!({"ab": ["cd", "$fog-Str:ef", "$fog-Bin:AAECAw", "$fog-Hex:0001"], "g": {"$fog-Bin": [1]}});
+        assert!(json_to_fog(&json).is_ok());
+        let ok = DecodeOptions::new().bin_wrapper(true).max_string_bytes(7).max_elements(4).max_bin_bytes(7);
+        assert!(json_to_fog_with_options(&json, &ok).is_ok());
+
+        let root_cause = |mut err: DecodeError| loop {
+            match err {
+                DecodeError::Array { err: inner, .. } | DecodeError::Map { err: inner, .. } => err = *inner,
+                err => return err,
+            }
+        };
+        let opts = DecodeOptions::new().bin_wrapper(true).max_string_bytes(6);
+        let err = root_cause(json_to_fog_with_options(&json, &opts).unwrap_err());
+        assert!(matches!(err, DecodeError::StringLimit(6)));
+        let opts = DecodeOptions::new().bin_wrapper(true).max_elements(3);
+        let err = root_cause(json_to_fog_with_options(&json, &opts).unwrap_err());
+        assert!(matches!(err, DecodeError::ElementLimit(3)));
+        let opts = DecodeOptions::new().bin_wrapper(true).max_bin_bytes(6);
+        let err = root_cause(json_to_fog_with_options(&json, &opts).unwrap_err());
+        assert!(matches!(err, DecodeError::BinLimit(6)));
+    }
+
+    #[test]
+    fn warnings() {
+        let json = json!({
+            "a": ["$fog-Int: 5", "$fog-Binary:AAEC", "plain"],
+            "b": "$fog-Bin:AAE=",
+            "c": "$fog-F32:0.100000001",
+            "d": "$fog-Time:2023-07-12T17:33:13.1234567891Z",
+        });
+        let (val, warnings) = json_to_fog_with_warnings(&json, &DecodeOptions::new()).unwrap();
+        assert_eq!(val, json_to_fog(&json).unwrap());
+        let warnings: Vec<(&str, &WarningKind)> = warnings.iter().map(|w| (w.pointer.as_str(), &w.kind)).collect();
+        assert_eq!(warnings, vec![
+            ("/a/0", &WarningKind::TrimmedWhitespace),
+            ("/a/1", &WarningKind::TypeAlias),
+            ("/b", &WarningKind::PaddedBase64),
+            ("/c", &WarningKind::RoundedF32),
+            ("/d", &WarningKind::TruncatedTime),
+        ]);
+        let opts = DecodeOptions::new().lenient(true);
+        let (_, warnings) = json_to_fog_with_warnings(&json!("$fog-Future:x"), &opts).unwrap();
+        assert_eq!(warnings[0].to_string(), "unrecognized type \"Future\" kept as a string at \"\"");
+    }
+
+    #[test]
+    fn pointer() {
+        let err = json_to_fog(&json!({"a/b": [0, {"c~": "$fog-Int:x"}]})).unwrap_err();
+        assert_eq!(err.pointer(), "/a~1b/1/c~0");
+        assert!(matches!(err.innermost(), DecodeError::InvalidInteger));
+        assert_eq!(json_to_fog(&json!("$fog-Int:x")).unwrap_err().pointer(), "");
+    }
+
+    #[test]
+    fn json_into_fog() {
+        let json = json!({
+            "$schema": "x",
+            "list": [1, "$fog-Str:$fog-Int:5", "plain", {"inner": "$fog-F32:1.5"}],
+            "bin": {"$fog-Bin": [0, 1, 2]},
+        });
+        let opts = DecodeOptions::new().ignore_editor_keys(true).bin_wrapper(true);
+        let expected = json_to_fog_with_options(&json, &opts).unwrap();
+        assert_eq!(super::json_into_fog_with_options(json, &opts).unwrap(), expected);
+        let err = super::json_into_fog(json!({"a": [1, "$fog-Int:x"]}));
+        assert!(matches!(err, Err(DecodeError::Map { key, err }) if key == "a" && matches!(*err, DecodeError::Array { loc: 1, .. })));
+    }
+
+    #[test]
+    fn identity_names() {
+        use std::collections::HashMap;
+        let id = fog_crypto::identity::IdentityKey::new_temp(&mut rand::rngs::OsRng).id().clone();
+        let mut names = HashMap::new();
+        names.insert(id.clone(), "alice".to_string());
+        let names = Arc::new(names);
+        let enc = EncodeOptions::new().identity_names(names.clone());
+        let dec = DecodeOptions::new().identity_names(names);
+
+        let json = fog_to_json_with_options(&FogValue::Identity(id.clone()), &enc);
+        assert_eq!(json, JsonValue::String(format!("$fog-Identity:{} (alice)", id.to_base58())));
+        assert_eq!(json_to_fog(&json).unwrap(), FogValue::Identity(id.clone()));
+        let json = json!("$fog-IdentityName:alice");
+        assert_eq!(json_to_fog_with_options(&json, &dec).unwrap(), FogValue::Identity(id));
+        assert!(matches!(json_to_fog(&json), Err(DecodeError::UnknownIdentityName(_))));
+    }
+
+    #[test]
+    fn stats() {
+        let json = json!({"a": ["$fog-Bin:AAECAwQ", 1, "$fog-Int:5"], "b": "plain"});
+        let (_, stats) = json_to_fog_with_stats(&json, &DecodeOptions::default()).unwrap();
+        assert_eq!(stats.nodes, 6);
+        assert_eq!(stats.annotations, 2);
+        assert_eq!(stats.base64_bytes, 5);
+    }
 }
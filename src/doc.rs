@@ -1,5 +1,10 @@
 use fog_crypto::identity::{Identity, IdentityKey};
-use fog_pack::document::{Document, NewDocument};
+use std::collections::HashMap;
+use fog_pack::{
+    document::{Document, NewDocument},
+    schema::{NoSchema, Schema},
+    types::Hash,
+};
 
 use super::*;
 
@@ -10,19 +15,139 @@ use super::*;
 ///
 /// - "schema": A fog-pack Hash of the schema used by the document.
 /// - "signer": A fog-pack Identity that signed the document.
+/// - "hash": The document's own hash, if enabled with [`EncodeOptions::doc_hash`].
+/// - "signature": Details about the signature, if enabled with 
+///   [`EncodeOptions::signature_details`].
+/// - "schema_name": The schema's name, if a resolver was set with 
+///   [`EncodeOptions::schema_names`] and it knew the schema.
+///
+/// # Panics
+///
+/// Panics if the document's data can't be read, which can only happen if it was loaded without 
+/// validation, as with `trusted_decode_doc`. Use [`try_doc_to_json`] for documents from 
+/// untrusted storage.
 pub fn doc_to_json(doc: &Document) -> JsonValue {
-    // Deserializing to a fog-pack ValueRef should never fail
-    let data: FogValueRef = doc.deserialize().unwrap();
+    doc_to_json_with_options(doc, &EncodeOptions::default())
+}
+
+/// Convert a [Document] into a JSON Value, as with [`doc_to_json`], using the provided encoding 
+/// options.
+///
+/// # Panics
+///
+/// Panics if the document's data can't be read, or is nested deeper than 
+/// [`EncodeOptions::max_depth`]. Use [`try_doc_to_json`] to get an error instead.
+pub fn doc_to_json_with_options(doc: &Document, opts: &EncodeOptions) -> JsonValue {
+    doc_envelope(&mut Encoder::new(opts), doc)
+}
+
+/// Convert a [Document] into a JSON Value, as with [`doc_to_json_with_options`]. Fails instead of 
+/// panicking if the document's data can't be read, or is nested deeper than 
+/// [`EncodeOptions::max_depth`].
+pub fn try_doc_to_json(doc: &Document, opts: &EncodeOptions) -> Result<JsonValue, EncodeError> {
+    let mut enc = Encoder::new(opts);
+    enc.try_envelope(&doc_to_fogref(doc, opts)?, doc_extra(doc, opts))
+}
+
+/// Decode encoded fog-pack document bytes, as stored or sent over the wire, and convert the 
+/// document into a JSON Value, as with [`doc_to_json`].
+///
+/// The document is decompressed and validated with its schema, which must be in `schemas`, like a 
+/// [`SchemaCache`]; 
+/// documents without a schema are validated with [`NoSchema`]. Fails with 
+/// [`EncodeError::SchemaNotFound`] if the schema isn't available, or [`EncodeError::Decode`] if 
+/// the bytes don't hold a valid document.
+pub fn doc_bytes_to_json<S: SchemaSource + ?Sized>(
+    bytes: Vec<u8>,
+    schemas: &S
+) -> Result<JsonValue, EncodeError> {
+    doc_bytes_to_json_with_options(bytes, schemas, &EncodeOptions::default())
+}
+
+/// Decode encoded fog-pack document bytes and convert the document into a JSON Value, as with 
+/// [`doc_bytes_to_json`], using the provided encoding options.
+pub fn doc_bytes_to_json_with_options<S: SchemaSource + ?Sized>(
+    bytes: Vec<u8>,
+    schemas: &S,
+    opts: &EncodeOptions
+) -> Result<JsonValue, EncodeError> {
+    let schema_hash = fog_pack::document::get_doc_schema(&bytes).map_err(EncodeError::Decode)?;
+    let doc = match schema_hash {
+        None => NoSchema::decode_doc(bytes),
+        Some(hash) => schemas.schema(&hash)
+            .ok_or_else(|| EncodeError::SchemaNotFound(Box::new(hash)))?
+            .decode_doc(bytes),
+    };
+    try_doc_to_json(&doc.map_err(EncodeError::Decode)?, opts)
+}
+
+/// Convert a [Document] into a JSON Value, as with [`doc_to_json`], also returning statistics 
+/// about the conversion.
+pub fn doc_to_json_with_stats(doc: &Document, opts: &EncodeOptions) -> (JsonValue, ConvertStats) {
+    let start = std::time::Instant::now();
+    let mut enc = Encoder::new(opts);
+    let json = doc_envelope(&mut enc, doc);
+    enc.stats.elapsed = start.elapsed();
+    (json, enc.stats)
+}
+
+/// Convert a [Document] into compact JSON text, as with [`doc_to_json_with_options`], writing it 
+/// straight to `writer` without building the JSON value first.
+///
+/// Data that can't be read, or is nested deeper than [`EncodeOptions::max_depth`], is reported as 
+/// an [`InvalidData`][std::io::ErrorKind::InvalidData] error. The depth limit is only found while 
+/// writing, so the output may be left incomplete.
+pub fn doc_to_json_writer<W: std::io::Write>(
+    doc: &Document,
+    opts: &EncodeOptions,
+    mut writer: W
+) -> std::io::Result<()> {
+    let data = doc_to_fogref(doc, opts).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+    Encoder::new(opts).write_envelope(&mut writer, &data, doc_extra(doc, opts))
+}
+
+fn doc_envelope(enc: &mut Encoder, doc: &Document) -> JsonValue {
+    let opts = enc.opts;
+    let data = doc_to_fogref(doc, opts).unwrap_or_else(|err| panic!("{}; use try_doc_to_json to handle this", err));
+    enc.envelope(&data, doc_extra(doc, opts))
+}
+
+/// Get the envelope entries that don't come from the document itself.
+pub(crate) fn doc_extra(doc: &Document, opts: &EncodeOptions) -> JsonMap {
+    let mut extra = JsonMap::new();
+    let name = opts.schema_name_resolver()
+        .zip(doc.schema_hash())
+        .and_then(|(resolver, schema)| resolver.schema_name(schema));
+    if let Some(name) = name {
+        extra.insert("schema_name".into(), JsonValue::String(name));
+    }
+    extra
+}
+
+pub(crate) fn doc_to_fogref<'a>(doc: &'a Document, opts: &EncodeOptions) -> Result<FogValueRef<'a>, EncodeError> {
+    let data = doc_data(doc)?;
     let mut map: BTreeMap<&str, FogValueRef> = BTreeMap::new();
     map.insert("data", data);
     if let Some(signer) = doc.signer() {
         map.insert("signer", FogValueRef::Identity(signer.to_owned()));
+        if opts.signature_details_enabled() {
+            // fog-pack verifies the signature before any Document exists
+            map.insert("signature", enc::signature_details(signer, Some(true)));
+        }
     }
     if let Some(schema) = doc.schema_hash() {
         map.insert("schema", FogValueRef::Hash(schema.to_owned()));
     }
-    let doc = FogValueRef::Map(map);
-    fogref_to_json(&doc)
+    if opts.doc_hash_enabled() {
+        map.insert("hash", FogValueRef::Hash(doc.hash().to_owned()));
+    }
+    Ok(FogValueRef::Map(map))
+}
+
+/// Read a Document's data. Validated documents always deserialize, but trusted decoding skips 
+/// validation, so this can fail.
+pub(crate) fn doc_data(doc: &Document) -> Result<FogValueRef<'_>, EncodeError> {
+    doc.deserialize().map_err(EncodeError::Deserialize)
 }
 
 /// A [`NewDocument`] that may still require signing.
@@ -33,11 +158,33 @@ pub enum MaybeDocument {
     SignDocument(SignDocument),
 }
 
+impl MaybeDocument {
+    /// Complete the [`NewDocument`], signing it first if needed with the matching key from 
+    /// `keys`. Fails with [`ObjectError::KeyNotFound`] if the key isn't available.
+    pub fn complete_with<K: KeySource + ?Sized>(self, keys: &K) -> Result<NewDocument, ObjectError> {
+        match self {
+            MaybeDocument::NewDocument(doc) => Ok(doc),
+            MaybeDocument::SignDocument(sign) => {
+                let key = find_key(keys, sign.signer())?;
+                sign.complete(&key)
+            },
+        }
+    }
+
+    /// Complete the [`NewDocument`], signing it first if needed with whichever of `keys` matches 
+    /// the signer. Fails with [`ObjectError::KeyNotFound`], naming the required Identity, if none 
+    /// of them do.
+    pub fn complete_with_keys(self, keys: &[IdentityKey]) -> Result<NewDocument, ObjectError> {
+        self.complete_with(keys)
+    }
+}
+
 /// An almost completed [`NewDocument`]. Complete it by finding the appropriate 
 /// [`IdentityKey`][IdentityKey] and calling [`complete`][SignDocument::complete].
 pub struct SignDocument {
     doc: NewDocument,
     signer: Identity,
+    hash: Option<Box<Hash>>,
 }
 
 impl SignDocument {
@@ -47,15 +194,99 @@ impl SignDocument {
         &self.signer
     }
 
+    /// Get the hash the signed Document is expected to have, if the JSON recorded one with 
+    /// [`EncodeOptions::doc_hash`].
+    pub fn hash(&self) -> Option<&Hash> {
+        self.hash.as_deref()
+    }
+
     /// Attempt to sign the Document and complete it.
     pub fn complete(self, key: &IdentityKey) -> Result<NewDocument, ObjectError> {
         if key.id() != &self.signer {
             Err(ObjectError::IncorrectIdentityKey(Box::new(self.signer)))
         }
         else {
-            Ok(self.doc.sign(key)?)
+            let doc = self.doc.sign(key)?;
+            check_hash(&doc, self.hash.as_deref())?;
+            Ok(doc)
         }
     }
+
+    /// Attempt to sign the Document with an [`AsyncSigner`] and complete it, as with 
+    /// [`complete`][Self::complete].
+    #[cfg(feature = "async_signing")]
+    pub async fn complete_async<S: AsyncSigner + ?Sized>(self, signer: &S) -> Result<NewDocument, ObjectError> {
+        let key = sign::presigned_key(signer, &self.signer, self.doc.hash()).await?;
+        self.complete(&key)
+    }
+}
+
+/// A vault of [`IdentityKey`]s, used to sign converted documents and entries with 
+/// [`MaybeDocument::complete_with`] and [`MaybeEntry::complete_with`].
+///
+/// Implemented for maps from Identities to keys, for slices of keys, and for closures that take 
+/// an Identity.
+pub trait KeySource {
+    /// Get the key for an Identity, if it's available.
+    fn key_for(&self, id: &Identity) -> Option<IdentityKey>;
+}
+
+impl KeySource for HashMap<Identity, IdentityKey> {
+    fn key_for(&self, id: &Identity) -> Option<IdentityKey> {
+        self.get(id).cloned()
+    }
+}
+
+impl KeySource for [IdentityKey] {
+    fn key_for(&self, id: &Identity) -> Option<IdentityKey> {
+        self.iter().find(|key| key.id() == id).cloned()
+    }
+}
+
+impl KeySource for Vec<IdentityKey> {
+    fn key_for(&self, id: &Identity) -> Option<IdentityKey> {
+        self.as_slice().key_for(id)
+    }
+}
+
+impl<F> KeySource for F
+where
+    F: Fn(&Identity) -> Option<IdentityKey>,
+{
+    fn key_for(&self, id: &Identity) -> Option<IdentityKey> {
+        self(id)
+    }
+}
+
+/// Sign a batch of [`SignDocument`]s with keys from `keys`, completing each as with 
+/// [`SignDocument::complete`].
+///
+/// Results are returned in the same order as the documents, with failures reported per-item. 
+/// Documents whose key isn't available fail with [`ObjectError::KeyNotFound`].
+pub fn sign_documents<I, K>(docs: I, keys: &K) -> Vec<Result<NewDocument, ObjectError>>
+where
+    I: IntoIterator<Item = SignDocument>,
+    K: KeySource + ?Sized,
+{
+    docs.into_iter()
+        .map(|sign| MaybeDocument::SignDocument(sign).complete_with(keys))
+        .collect()
+}
+
+/// Get the key for a signer from a [`KeySource`].
+pub(crate) fn find_key<K: KeySource + ?Sized>(keys: &K, signer: &Identity) -> Result<IdentityKey, ObjectError> {
+    keys.key_for(signer).ok_or_else(|| ObjectError::KeyNotFound(Box::new(signer.clone())))
+}
+
+/// Make sure a converted document matches the hash recorded in the JSON, if there was one.
+fn check_hash(doc: &NewDocument, expected: Option<&Hash>) -> Result<(), ObjectError> {
+    match expected {
+        Some(expected) if expected != doc.hash() => Err(ObjectError::HashMismatch {
+            expected: Box::new(expected.clone()),
+            actual: Box::new(doc.hash().clone()),
+        }),
+        _ => Ok(()),
+    }
 }
 
 /// Convert a JSON value into a [`NewDocument`].
@@ -66,31 +297,248 @@ impl SignDocument {
 /// - "schema": A fog-pack Hash of the schema to use for the document.
 /// - "signer": A fog-pack Identity to use for signing the document.
 /// - "compression": Overrides the default compression settings for the document. Can be Null or 
-///     0-255.
+///   0-255.
+/// - "schema_name": The name of the schema, as added by [`EncodeOptions::schema_names`]. If 
+///   there's no "schema" and a resolver was set with [`DecodeOptions::schema_names`], the name is 
+///   resolved to get the schema. Otherwise it's ignored.
+/// - "hash": The expected hash of the document, as added by [`EncodeOptions::doc_hash`]. 
+///   Conversion fails if the converted document doesn't match it. For signed documents, this is 
+///   checked when [signing][SignDocument::complete].
+///
+/// A `"$fog-generator"` entry, as added by [`EncodeOptions::generator`], and a `"signature"` 
+/// entry, as added by [`EncodeOptions::signature_details`], are ignored.
 ///
 /// If signing is required, this returns a [`SignDocument`] in an enum, which must first be signed 
 /// before completion.
 pub fn json_to_doc(json: &JsonValue) -> Result<MaybeDocument, ObjectError> {
+    json_to_doc_with_options(json, &DecodeOptions::default())
+}
+
+/// Convert a JSON value into a [`NewDocument`], as with [`json_to_doc`], using the provided 
+/// decoding options.
+pub fn json_to_doc_with_options(
+    json: &JsonValue,
+    opts: &DecodeOptions
+) -> Result<MaybeDocument, ObjectError> {
+    json_to_doc_inner(json, &mut Decoder::new(opts))
+}
+
+/// Convert a JSON value into a [`NewDocument`], as with [`json_to_doc`], also returning 
+/// statistics about the conversion.
+pub fn json_to_doc_with_stats(
+    json: &JsonValue,
+    opts: &DecodeOptions
+) -> Result<(MaybeDocument, ConvertStats), ObjectError> {
+    let start = std::time::Instant::now();
+    let mut dec = Decoder::new(opts);
+    let doc = json_to_doc_inner(json, &mut dec)?;
+    dec.stats.elapsed = start.elapsed();
+    Ok((doc, dec.stats))
+}
+
+/// Convert a JSON value into a [`Document`], as with [`json_to_doc`], then validate it against 
+/// `schema`, or against [`NoSchema`] if `schema` is `None`.
+///
+/// Validation failures are reported as [`ObjectError::Validation`], under the "data" key, or the 
+/// "schema" key if the document doesn't use the given schema. Documents that must be signed are 
+/// checked right away, and validated again once signed by 
+/// [`SignValidDocument::complete`].
+pub fn json_to_validated_doc<'s>(
+    json: &JsonValue,
+    schema: Option<&'s Schema>
+) -> Result<MaybeValidDocument<'s>, ObjectError> {
+    json_to_validated_doc_with_options(json, schema, &DecodeOptions::default())
+}
+
+/// Convert a JSON value into a validated [`Document`], as with [`json_to_validated_doc`], using 
+/// the provided decoding options.
+pub fn json_to_validated_doc_with_options<'s>(
+    json: &JsonValue,
+    schema: Option<&'s Schema>,
+    opts: &DecodeOptions
+) -> Result<MaybeValidDocument<'s>, ObjectError> {
+    match json_to_doc_with_options(json, opts)? {
+        MaybeDocument::NewDocument(doc) => Ok(MaybeValidDocument::Document(validate_doc(doc, schema)?)),
+        MaybeDocument::SignDocument(sign) => {
+            validate_doc(sign.doc.clone(), schema)?;
+            Ok(MaybeValidDocument::SignDocument(SignValidDocument { sign, schema }))
+        },
+    }
+}
+
+/// Convert a JSON value all the way into encoded fog-pack document bytes, ready for storage or 
+/// sending, returning the document's hash along with them.
+///
+/// The document is converted as with [`json_to_doc`], signed with the matching key from `keys` if 
+/// needed, then validated and encoded with its schema, which must be in `schemas`. Documents 
+/// without a schema use [`NoSchema`]. Fails with [`ObjectError::SchemaNotFound`] if the schema 
+/// isn't available, [`ObjectError::KeyNotFound`] if the signing key isn't, and 
+/// [`ObjectError::Validation`] if validation fails.
+pub fn json_to_doc_bytes<S: SchemaSource + ?Sized, K: KeySource + ?Sized>(
+    json: &JsonValue,
+    schemas: &S,
+    keys: &K
+) -> Result<(Hash, Vec<u8>), ObjectError> {
+    json_to_doc_bytes_with_options(json, schemas, keys, &DecodeOptions::default())
+}
+
+/// Convert a JSON value into encoded fog-pack document bytes, as with [`json_to_doc_bytes`], using 
+/// the provided decoding options.
+pub fn json_to_doc_bytes_with_options<S: SchemaSource + ?Sized, K: KeySource + ?Sized>(
+    json: &JsonValue,
+    schemas: &S,
+    keys: &K,
+    opts: &DecodeOptions
+) -> Result<(Hash, Vec<u8>), ObjectError> {
+    let doc = json_to_doc_with_options(json, opts)?.complete_with(keys)?;
+    let schema = doc.schema_hash()
+        .map(|hash| schemas.schema(hash).ok_or_else(|| ObjectError::SchemaNotFound(Box::new(hash.to_owned()))))
+        .transpose()?;
+    let doc = validate_doc(doc, schema)?;
+    let encoded = match schema {
+        Some(schema) => schema.encode_doc(doc),
+        None => NoSchema::encode_doc(doc),
+    };
+    Ok(encoded?)
+}
+
+/// Validate a converted document against a schema, or [`NoSchema`] if there isn't one.
+pub(crate) fn validate_doc(doc: NewDocument, schema: Option<&Schema>) -> Result<Document, ObjectError> {
+    let key = if doc.schema_hash() == schema.map(|s| s.hash()) { "data" } else { "schema" };
+    match schema {
+        Some(schema) => schema.validate_new_doc(doc),
+        None => NoSchema::validate_new_doc(doc),
+    }
+    .map_err(|src| ObjectError::Validation { key, src })
+}
+
+/// A [`Document`] that has been validated, or that must be signed before it can be.
+// Both variants hold a whole document, so boxing either one wouldn't save anything
+#[allow(clippy::large_enum_variant)]
+pub enum MaybeValidDocument<'s> {
+    /// A validated [`Document`]
+    Document(Document),
+    /// A document that must first be signed
+    SignDocument(SignValidDocument<'s>),
+}
+
+/// A [`SignDocument`] that is validated once it's signed. Complete it by finding the appropriate 
+/// [`IdentityKey`][IdentityKey] and calling [`complete`][SignValidDocument::complete].
+pub struct SignValidDocument<'s> {
+    sign: SignDocument,
+    schema: Option<&'s Schema>,
+}
+
+impl<'s> SignValidDocument<'s> {
+    /// Get the Identity that should sign this.
+    pub fn signer(&self) -> &Identity {
+        self.sign.signer()
+    }
+
+    /// Attempt to sign the Document, then validate it and complete it.
+    pub fn complete(self, key: &IdentityKey) -> Result<Document, ObjectError> {
+        validate_doc(self.sign.complete(key)?, self.schema)
+    }
+}
+
+/// Read JSON text from a reader and convert it into a [`NewDocument`], as with [`json_to_doc`].
+///
+/// The document data is converted as it's read, so large documents never need to be held in 
+/// memory as text or as a JSON Value. Reads are unbuffered, so wrap the reader in a 
+/// [`BufReader`][std::io::BufReader] if it isn't already buffered. Errors in the JSON text are 
+/// reported with their location, as with [`json_str_to_fog`].
+pub fn json_reader_to_doc<R: std::io::Read>(reader: R) -> Result<MaybeDocument, ObjectError> {
+    json_reader_to_doc_with_options(reader, &DecodeOptions::default())
+}
+
+/// Read JSON text from a reader and convert it into a [`NewDocument`], as with 
+/// [`json_reader_to_doc`], using the provided decoding options.
+pub fn json_reader_to_doc_with_options<R: std::io::Read>(
+    reader: R,
+    opts: &DecodeOptions
+) -> Result<MaybeDocument, ObjectError> {
+    let mut dec = Decoder::new(opts);
+    let (obj, data) = text::read_envelope(reader, &mut dec, "data")?;
+    check_doc_keys(&obj, opts)?;
+    let data = data.ok_or(ObjectError::MissingKey("data"))?;
+    doc_from_parts(&obj, data, &mut dec)
+}
+
+/// Convert JSON text with comments (JSONC) into a [`NewDocument`], as with [`json_to_doc`].
+///
+/// Comments are removed with [`strip_json_comments`], so annotated exports can be read back in 
+/// as-is.
+pub fn jsonc_to_doc(jsonc: &str) -> Result<MaybeDocument, ObjectError> {
+    jsonc_to_doc_with_options(jsonc, &DecodeOptions::default())
+}
+
+/// Convert JSON text with comments into a [`NewDocument`], as with [`jsonc_to_doc`], using the 
+/// provided decoding options.
+pub fn jsonc_to_doc_with_options(jsonc: &str, opts: &DecodeOptions) -> Result<MaybeDocument, ObjectError> {
+    json_reader_to_doc_with_options(text::strip_json_comments(jsonc).as_bytes(), opts)
+}
+
+/// Convert JSON5 text into a [`NewDocument`], as with [`json_to_doc`].
+///
+/// JSON5 allows comments, trailing commas, unquoted keys, and more, which makes hand-written 
+/// documents easier to maintain. The document data is converted as with [`json5_to_fog`].
+#[cfg(feature = "json5")]
+pub fn json5_to_doc(json5: &str) -> Result<MaybeDocument, ObjectError> {
+    json5_to_doc_with_options(json5, &DecodeOptions::default())
+}
+
+/// Convert JSON5 text into a [`NewDocument`], as with [`json5_to_doc`], using the provided 
+/// decoding options.
+#[cfg(feature = "json5")]
+pub fn json5_to_doc_with_options(json5: &str, opts: &DecodeOptions) -> Result<MaybeDocument, ObjectError> {
+    let mut dec = Decoder::new(opts);
+    let (obj, data) = text::read_json5_envelope(json5, &mut dec, "data")?;
+    check_doc_keys(&obj, opts)?;
+    let data = data.ok_or(ObjectError::MissingKey("data"))?;
+    doc_from_parts(&obj, data, &mut dec)
+}
+
+fn json_to_doc_inner(json: &JsonValue, dec: &mut Decoder) -> Result<MaybeDocument, ObjectError> {
     let obj = json.as_object().ok_or(ObjectError::NotAnObject)?;
+    check_doc_keys(obj, dec.opts)?;
+    let data = obj.get("data").ok_or(ObjectError::MissingKey("data"))?;
+    let data = dec.root(data).map_err(|e| ObjectError::Decode { key: "data", src: e })?;
+    doc_from_parts(obj, data, dec)
+}
 
-    // Make sure we only have fields we recognize
+/// Make sure a document's JSON envelope only has fields we recognize.
+fn check_doc_keys(obj: &JsonMap, opts: &DecodeOptions) -> Result<(), ObjectError> {
     for k in obj.keys() {
         match k.as_str() {
-            "data" | "signer" | "schema" | "compression" => (),
+            "data" | "signer" | "schema" | "schema_name" | "compression" | "hash" | "signature"
+            | GENERATOR_KEY => (),
+            k if opts.skip_key(k) => (),
             k => return Err(ObjectError::UnrecognizedKey(k.to_string())),
         }
     }
+    Ok(())
+}
 
-    // Fetch & convert fields for making the document
-    let data = obj.get("data").ok_or_else(|| ObjectError::MissingKey("data"))?;
-    let data = json_to_fog(data).map_err(|e| ObjectError::Decode { key: "data", src: e })?;
+/// Make a document from its converted data and the rest of its JSON envelope.
+pub(crate) fn doc_from_parts(obj: &JsonMap, data: FogValue, dec: &mut Decoder) -> Result<MaybeDocument, ObjectError> {
     let schema = if let Some(s) = obj.get("schema") {
-        let s = json_to_fog(s).map_err(|e| ObjectError::Decode { key: "schema", src: e })?
+        let s = dec.value(s).map_err(|e| ObjectError::Decode { key: "schema", src: e })?
             .as_hash()
             .ok_or(ObjectError::WrongDataType("schema"))?
             .to_owned();
         Some(s)
     }
+    else if let Some(name) = obj.get("schema_name") {
+        let name = name.as_str().ok_or(ObjectError::WrongDataType("schema_name"))?;
+        match dec.opts.schema_name_resolver() {
+            Some(resolver) => {
+                let s = resolver.schema_hash(name)
+                    .ok_or_else(|| ObjectError::UnknownSchemaName(name.to_owned()))?;
+                Some(s)
+            },
+            None => None,
+        }
+    }
     else { 
         None
     };
@@ -114,15 +562,329 @@ pub fn json_to_doc(json: &JsonValue) -> Result<MaybeDocument, ObjectError> {
     }
     else { new_doc };
 
+    // Check the optional hash field
+    let hash = if let Some(h) = obj.get("hash") {
+        let h = dec.value(h).map_err(|e| ObjectError::Decode { key: "hash", src: e })?
+            .as_hash()
+            .ok_or(ObjectError::WrongDataType("hash"))?
+            .to_owned();
+        Some(h)
+    }
+    else {
+        None
+    };
+
     // Check the optional signer field
     if let Some(s) = obj.get("signer") {
-        let s = json_to_fog(s).map_err(|e| ObjectError::Decode { key: "signer", src: e })?
+        let s = dec.value(s).map_err(|e| ObjectError::Decode { key: "signer", src: e })?
             .as_identity()
             .ok_or(ObjectError::WrongDataType("signer"))?
             .to_owned();
-        Ok(MaybeDocument::SignDocument(SignDocument { doc: new_doc, signer: s }))
+        Ok(MaybeDocument::SignDocument(SignDocument { doc: new_doc, signer: s, hash: hash.map(Box::new) }))
     }
     else {
+        check_hash(&new_doc, hash.as_ref())?;
         Ok(MaybeDocument::NewDocument(new_doc))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fog_pack::schema::NoSchema;
+
+    #[test]
+    fn schema_names() {
+        use fog_pack::schema::{Schema, SchemaBuilder};
+        use std::{collections::HashMap, sync::Arc};
+        let schema_doc = SchemaBuilder::new(fog_pack::validator::StrValidator::new().build()).build().unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let doc = schema.validate_new_doc(NewDocument::new(Some(schema.hash()), "named").unwrap()).unwrap();
+        let mut names = HashMap::new();
+        names.insert(schema.hash().clone(), "greeting".to_string());
+        let names = Arc::new(names);
+
+        let json = doc_to_json_with_options(&doc, &EncodeOptions::new().schema_names(names.clone()));
+        assert_eq!(json["schema_name"], "greeting");
+
+        let mut json = json.as_object().unwrap().clone();
+        json.remove("schema");
+        let json = JsonValue::Object(json);
+        let opts = DecodeOptions::new().schema_names(names);
+        let MaybeDocument::NewDocument(parsed) = json_to_doc_with_options(&json, &opts).unwrap() else {
+            panic!("Document shouldn't have needed signing")
+        };
+        assert_eq!(parsed.hash(), doc.hash());
+        assert!(matches!(json_to_doc(&json), Ok(MaybeDocument::NewDocument(d)) if d.schema_hash().is_none()));
+    }
+
+    #[test]
+    fn signature_details() {
+        let key = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let new_doc = NewDocument::new(None, "signed").unwrap().sign(&key).unwrap();
+        let doc = NoSchema::validate_new_doc(new_doc).unwrap();
+        let opts = EncodeOptions::new().signature_details(true);
+        let json = doc_to_json_with_options(&doc, &opts);
+        let expected = serde_json::json!({
+            "signer": fog_to_json(&FogValue::Identity(key.id().clone())),
+            "valid": true,
+            "algorithm": "Ed25519",
+        });
+        assert_eq!(json["signature"], expected);
+        assert!(matches!(json_to_doc(&json), Ok(MaybeDocument::SignDocument(_))));
+    }
+
+    #[test]
+    fn doc_hash() {
+        let key = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let new_doc = NewDocument::new(None, vec![1u8, 2, 3]).unwrap().sign(&key).unwrap();
+        let doc = NoSchema::validate_new_doc(new_doc).unwrap();
+        let opts = EncodeOptions::new().doc_hash(true);
+        let mut json = doc_to_json_with_options(&doc, &opts);
+        assert_eq!(json["hash"], fog_to_json(&FogValue::Hash(doc.hash().clone())));
+
+        let MaybeDocument::SignDocument(sign) = json_to_doc(&json).unwrap() else {
+            panic!("Document should have needed signing")
+        };
+        assert_eq!(sign.complete(&key).unwrap().hash(), doc.hash());
+
+        json["data"] = serde_json::json!([1, 2, 4]);
+        let MaybeDocument::SignDocument(sign) = json_to_doc(&json).unwrap() else {
+            panic!("Document should have needed signing")
+        };
+        assert!(matches!(sign.complete(&key), Err(ObjectError::HashMismatch { .. })));
+    }
+
+    #[test]
+    fn complete_with() {
+        let key = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let other = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let new_doc = NewDocument::new(None, "signed").unwrap().sign(&key).unwrap();
+        let doc = NoSchema::validate_new_doc(new_doc).unwrap();
+        let json = doc_to_json(&doc);
+
+        let vault: HashMap<Identity, IdentityKey> = [(key.id().clone(), key.clone())].into();
+        assert_eq!(json_to_doc(&json).unwrap().complete_with(&vault).unwrap().hash(), doc.hash());
+        let keys = vec![other.clone(), key.clone()];
+        assert_eq!(json_to_doc(&json).unwrap().complete_with(&keys).unwrap().hash(), doc.hash());
+        let lookup = |_: &Identity| None;
+        assert!(matches!(
+            json_to_doc(&json).unwrap().complete_with(&lookup),
+            Err(ObjectError::KeyNotFound(id)) if *id == *key.id()
+        ));
+        let unsigned = json_to_doc(&serde_json::json!({"data": 1})).unwrap();
+        assert!(unsigned.complete_with(&[other][..]).is_ok());
+    }
+
+    #[test]
+    fn sign_documents() {
+        let keys: Vec<IdentityKey> = (0..2).map(|_| IdentityKey::new_temp(&mut rand::rngs::OsRng)).collect();
+        let missing = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let docs: Vec<Document> = keys.iter().chain([&missing])
+            .map(|key| {
+                let new_doc = NewDocument::new(None, key.id().to_base58()).unwrap().sign(key).unwrap();
+                NoSchema::validate_new_doc(new_doc).unwrap()
+            })
+            .collect();
+        let pending = docs.iter().map(|doc| match json_to_doc(&doc_to_json(doc)).unwrap() {
+            MaybeDocument::SignDocument(sign) => sign,
+            MaybeDocument::NewDocument(_) => panic!("Document should have needed signing"),
+        });
+
+        let results = super::sign_documents(pending, &keys);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().hash(), docs[0].hash());
+        assert_eq!(results[1].as_ref().unwrap().hash(), docs[1].hash());
+        assert!(matches!(&results[2], Err(ObjectError::KeyNotFound(id)) if **id == *missing.id()));
+    }
+
+    #[test]
+    fn json_to_validated_doc() {
+        use fog_pack::{schema::SchemaBuilder, validator::IntValidator};
+        let schema_doc = SchemaBuilder::new(IntValidator::new().max(10).build()).build().unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let schema_json = fog_to_json(&FogValue::Hash(schema.hash().clone()));
+
+        let json = serde_json::json!({"data": 5, "schema": schema_json});
+        let Ok(MaybeValidDocument::Document(doc)) = super::json_to_validated_doc(&json, Some(&schema)) else {
+            panic!("Document should have validated")
+        };
+        assert_eq!(doc.schema_hash(), Some(schema.hash()));
+        let err = super::json_to_validated_doc(&serde_json::json!({"data": 50, "schema": schema_json}), Some(&schema));
+        assert_eq!(err.err().unwrap().pointer().as_deref(), Some("/data"));
+        let err = super::json_to_validated_doc(&json, None);
+        assert!(matches!(err, Err(ObjectError::Validation { key: "schema", .. })));
+        assert!(super::json_to_validated_doc(&serde_json::json!({"data": 50}), None).is_ok());
+
+        let key = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let signed = serde_json::json!({
+            "data": 5,
+            "schema": schema_json,
+            "signer": fog_to_json(&FogValue::Identity(key.id().clone())),
+        });
+        let Ok(MaybeValidDocument::SignDocument(sign)) = super::json_to_validated_doc(&signed, Some(&schema)) else {
+            panic!("Document should have needed signing")
+        };
+        assert_eq!(sign.complete(&key).unwrap().signer(), Some(key.id()));
+    }
+
+    #[test]
+    fn complete_with_keys() {
+        let key = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let other = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let new_doc = NewDocument::new(None, "signed").unwrap().sign(&key).unwrap();
+        let doc = NoSchema::validate_new_doc(new_doc).unwrap();
+        let json = doc_to_json(&doc);
+
+        let keys = [other.clone(), key.clone()];
+        assert_eq!(json_to_doc(&json).unwrap().complete_with_keys(&keys).unwrap().hash(), doc.hash());
+        let err = json_to_doc(&json).unwrap().complete_with_keys(&[other]).err().unwrap();
+        assert_eq!(err.to_string(), format!("No IdentityKey available for signer {}", key.id()));
+    }
+
+    #[test]
+    fn doc_to_json_writer() {
+        use fog_pack::schema::{Schema, SchemaBuilder};
+        use std::{collections::HashMap, sync::Arc};
+        let schema_doc = SchemaBuilder::new(fog_pack::validator::Validator::new_any()).build().unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let key = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let data = serde_json::json!({"list": [1, "$fog-F32:2.5", {"z": null}], "bin": "$fog-Bin:AAEC"});
+        let new_doc = NewDocument::new(Some(schema.hash()), json_to_fog(&data).unwrap()).unwrap();
+        let doc = schema.validate_new_doc(new_doc.sign(&key).unwrap()).unwrap();
+        let mut names = HashMap::new();
+        names.insert(schema.hash().clone(), "anything".to_string());
+        let opts = EncodeOptions::new()
+            .schema_names(Arc::new(names))
+            .signature_details(true)
+            .generator(true);
+
+        let mut out = Vec::new();
+        super::doc_to_json_writer(&doc, &opts, &mut out).unwrap();
+        let expected = serde_json::to_vec(&doc_to_json_with_options(&doc, &opts)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), String::from_utf8(expected).unwrap());
+    }
+
+    #[test]
+    fn json_reader_to_doc() {
+        let key = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let data = serde_json::json!({"list": [1, "$fog-F32:2.5", {"z": null}], "bin": "$fog-Bin:AAEC"});
+        let new_doc = NewDocument::new(None, json_to_fog(&data).unwrap()).unwrap().sign(&key).unwrap();
+        let doc = NoSchema::validate_new_doc(new_doc).unwrap();
+        let text = serde_json::to_vec(&doc_to_json(&doc)).unwrap();
+        let MaybeDocument::SignDocument(sign) = super::json_reader_to_doc(text.as_slice()).unwrap() else {
+            panic!("Document should have needed signing")
+        };
+        assert_eq!(sign.complete(&key).unwrap().hash(), doc.hash());
+
+        let text = br#"{"data": {"a": ["$fog-Int:x"]}}"#;
+        let err = super::json_reader_to_doc(&text[..]);
+        assert!(matches!(err, Err(ObjectError::Decode { key: "data", src: DecodeError::Located { path, .. } } ) if path == "/data/a/0"));
+        assert!(matches!(super::json_reader_to_doc(&b"[1]"[..]), Err(ObjectError::Parse(_))));
+        assert!(matches!(super::json_reader_to_doc(&b"{\"data\": 1, \"extra\": 2}"[..]), Err(ObjectError::UnrecognizedKey(_))));
+        assert!(matches!(super::json_reader_to_doc(&b"{}"[..]), Err(ObjectError::MissingKey("data"))));
+    }
+
+    #[test]
+    fn try_doc_to_json() {
+        let new_doc = NewDocument::new(None, "hello").unwrap().compression(None);
+        let doc = NoSchema::validate_new_doc(new_doc).unwrap();
+        let opts = EncodeOptions::default();
+        assert_eq!(super::try_doc_to_json(&doc, &opts).unwrap(), doc_to_json(&doc));
+
+        // Corrupt the data's first marker, past the compression byte, hash length, and data length
+        let (_, mut raw) = NoSchema::encode_doc(doc).unwrap();
+        raw[5] = 0xc1;
+        let doc = NoSchema::trusted_decode_doc(raw).unwrap();
+        assert!(matches!(super::try_doc_to_json(&doc, &opts), Err(EncodeError::Deserialize(_))));
+        assert!(super::doc_to_json_writer(&doc, &opts, Vec::new()).is_err());
+        let doc = NoSchema::validate_new_doc(NewDocument::new(None, [[[1]]]).unwrap()).unwrap();
+        let opts = EncodeOptions::new().max_depth(2);
+        assert!(matches!(super::try_doc_to_json(&doc, &opts), Err(EncodeError::TooDeep(2))));
+        let err = super::doc_to_json_writer(&doc, &opts, Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn doc_bytes_to_json() {
+        use fog_pack::{schema::{Compress, SchemaBuilder}, validator::*};
+        let schema_doc = SchemaBuilder::new(StrValidator::new().build())
+            .doc_compress(Compress::new_zstd_general(3))
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let new_doc = NewDocument::new(Some(schema.hash()), "compressed ".repeat(20)).unwrap();
+        let doc = schema.validate_new_doc(new_doc).unwrap();
+        let (_, raw) = schema.encode_doc(doc.clone()).unwrap();
+
+        let mut schemas = SchemaCache::new();
+        assert!(matches!(
+            super::doc_bytes_to_json(raw.clone(), &schemas),
+            Err(EncodeError::SchemaNotFound(hash)) if &*hash == schema.hash()
+        ));
+        schemas.insert(schema);
+        assert_eq!(super::doc_bytes_to_json(raw, &schemas).unwrap(), doc_to_json(&doc));
+
+        let doc = NoSchema::validate_new_doc(NewDocument::new(None, 5u8).unwrap()).unwrap();
+        let (_, mut raw) = NoSchema::encode_doc(doc.clone()).unwrap();
+        assert_eq!(super::doc_bytes_to_json(raw.clone(), &schemas).unwrap(), doc_to_json(&doc));
+        raw.truncate(3);
+        assert!(matches!(super::doc_bytes_to_json(raw, &schemas), Err(EncodeError::Decode(_))));
+    }
+
+    #[test]
+    fn json_to_doc_bytes() {
+        use fog_pack::{schema::SchemaBuilder, validator::*};
+        let schema_doc = SchemaBuilder::new(IntValidator::new().max(10).build()).build().unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let key = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let new_doc = NewDocument::new(Some(schema.hash()), 5u8).unwrap().sign(&key).unwrap();
+        let doc = schema.validate_new_doc(new_doc).unwrap();
+        let json = doc_to_json(&doc);
+
+        let mut schemas = SchemaCache::new();
+        let no_keys: &[IdentityKey] = &[];
+        let err = super::json_to_doc_bytes(&json, &schemas, no_keys);
+        assert!(matches!(err, Err(ObjectError::KeyNotFound(_))));
+        let keys = vec![key];
+        let err = super::json_to_doc_bytes(&json, &schemas, &keys);
+        assert!(matches!(err, Err(ObjectError::SchemaNotFound(_))));
+        schemas.insert(schema.clone());
+        let (hash, raw) = super::json_to_doc_bytes(&json, &schemas, &keys).unwrap();
+        assert_eq!(&hash, doc.hash());
+        assert_eq!(schema.decode_doc(raw).unwrap().signer(), doc.signer());
+
+        let mut json = json;
+        json["data"] = 50.into();
+        let err = super::json_to_doc_bytes(&json, &schemas, &keys).err().unwrap();
+        assert_eq!(err.pointer().as_deref(), Some("/data"));
+        let json = serde_json::json!({"data": "plain"});
+        let (hash, raw) = super::json_to_doc_bytes(&json, &schemas, no_keys).unwrap();
+        assert_eq!(NoSchema::decode_doc(raw).unwrap().hash(), &hash);
+    }
+
+    #[test]
+    fn jsonc_to_doc() {
+        let text = "{\n  // Exported for review\n  \"data\": {\"a\": 1 /* checked */}\n}";
+        let MaybeDocument::NewDocument(doc) = super::jsonc_to_doc(text).unwrap() else {
+            panic!("Document shouldn't need signing")
+        };
+        assert_eq!(doc.hash(), NewDocument::new(None, json_str_to_fog(r#"{"a": 1}"#).unwrap()).unwrap().hash());
+        assert!(matches!(super::jsonc_to_doc("{\"data\": 1} /*"), Err(ObjectError::Parse(_))));
+    }
+
+    #[cfg(feature = "json5")]
+    #[test]
+    fn json5_to_doc() {
+        let text = "{\n  // Hand-written\n  data: {list: [1, '$fog-F32:2.5',], bin: '$fog-Bin:AAEC'},\n}";
+        let MaybeDocument::NewDocument(doc) = super::json5_to_doc(text).unwrap() else {
+            panic!("Document shouldn't need signing")
+        };
+        let data = json_str_to_fog(r#"{"list": [1, "$fog-F32:2.5"], "bin": "$fog-Bin:AAEC"}"#).unwrap();
+        assert_eq!(doc.hash(), NewDocument::new(None, data).unwrap().hash());
+
+        let err = super::json5_to_doc("{data: {a: ['$fog-Int:x']}}");
+        assert!(matches!(err, Err(ObjectError::Decode { key: "data", src: DecodeError::Located { path, .. } } ) if path == "/data/a/0"));
+        assert!(matches!(super::json5_to_doc("{data: 1,, }"), Err(ObjectError::Parse(_))));
+    }
+}
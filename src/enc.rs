@@ -1,246 +1,1854 @@
 use super::*;
+use std::sync::Arc;
+use thiserror::Error;
 
-fn base64_encode<T: AsRef<[u8]>>(input: T, output_buf: &mut String) {
-    use base64::engine::Engine;
-    base64::engine::general_purpose::STANDARD_NO_PAD.encode_string(input, output_buf)
+/// An error that occurred while converting a fog-pack value to JSON.
+#[derive(Clone, Debug, Error)]
+pub enum EncodeError {
+    /// The value had arrays or maps nested deeper than the limit set with 
+    /// [`EncodeOptions::max_depth`]
+    #[error("Value is nested deeper than the limit of {0}")]
+    TooDeep(usize),
+    /// The data in a Document or Entry couldn't be read, as can happen if it was corrupted and 
+    /// loaded without validation
+    #[error("Couldn't read the fog-pack data")]
+    Deserialize(#[source] fog_pack::error::Error),
+    /// A query's validator couldn't be converted into fog-pack data
+    #[error("Couldn't convert the query validator")]
+    Validator(#[source] fog_pack::error::Error),
+    /// Encoded fog-pack bytes couldn't be decoded, or failed validation against their schema
+    #[error("Couldn't decode the fog-pack bytes")]
+    Decode(#[source] fog_pack::error::Error),
+    /// The schema needed to decode encoded fog-pack bytes wasn't available
+    #[error("Schema {0} wasn't found")]
+    SchemaNotFound(Box<fog_pack::types::Hash>),
+    /// The document isn't a schema
+    #[error("Document isn't a valid schema")]
+    NotSchema(#[source] fog_pack::error::Error),
+}
+
+fn base64_encode<T: AsRef<[u8]>>(input: T, url_safe: bool, pad: bool, output_buf: &mut String) {
+    use base64::engine::{general_purpose, Engine};
+    match (url_safe, pad) {
+        (false, false) => general_purpose::STANDARD_NO_PAD.encode_string(input, output_buf),
+        (false, true) => general_purpose::STANDARD.encode_string(input, output_buf),
+        (true, false) => general_purpose::URL_SAFE_NO_PAD.encode_string(input, output_buf),
+        (true, true) => general_purpose::URL_SAFE.encode_string(input, output_buf),
+    }
+}
+
+/// Render binary data as a classic `hexdump -C` style listing, one string per 16-byte line.
+fn hexdump(data: &[u8]) -> Vec<JsonValue> {
+    use std::fmt::Write;
+    data.chunks(16)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut line = String::with_capacity(78);
+            write!(line, "{:08x} ", i * 16).unwrap();
+            for j in 0..16 {
+                if j == 8 { line.push(' '); }
+                match chunk.get(j) {
+                    Some(b) => write!(line, " {:02x}", b).unwrap(),
+                    None => line.push_str("   "),
+                }
+            }
+            line.push_str("  |");
+            line.extend(chunk.iter().map(|b| {
+                if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' }
+            }));
+            line.push('|');
+            JsonValue::String(line)
+        })
+        .collect()
+}
+
+/// Largest integer magnitude a JavaScript number can hold exactly, `Number.MAX_SAFE_INTEGER`.
+const JS_MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// Check if an integer survives a round trip through a JavaScript number.
+fn is_js_safe(i: &fog_pack::types::Integer) -> bool {
+    match i.as_u64() {
+        Some(u) => u <= JS_MAX_SAFE_INTEGER,
+        None => i.as_i64().is_some_and(|i| i.unsigned_abs() <= JS_MAX_SAFE_INTEGER),
+    }
+}
+
+/// The multibase prefix for Bitcoin-style base58.
+pub(crate) const MULTIBASE_BASE58BTC: char = 'z';
+
+/// How Timestamps are written in `$fog-Time:` strings.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TimeFormat {
+    /// An RFC 3339 string, like `2023-07-12T17:33:13.5Z`.
+    #[default]
+    Rfc3339,
+    /// Seconds since the Unix epoch, with a 9-digit fractional part if there are any 
+    /// nanoseconds, like `1689183193.500000000`.
+    Epoch,
+    /// A [chrono format string][chrono::format::strftime], like `"%Y-%m-%d %H:%M:%S"`, applied 
+    /// to the time in UTC. This is meant for reports; unless the format produces RFC 3339, the 
+    /// result won't convert back into a Timestamp.
+    Custom(String),
+}
+
+/// The keyword for a non-finite float. NaN only gets one if it's the standard NaN, which is what 
+/// the keyword parses back to.
+fn float_keyword(f: f64, standard_nan: bool) -> Option<&'static str> {
+    if f.is_nan() {
+        standard_nan.then_some("NaN")
+    }
+    else if f.is_infinite() {
+        Some(if f > 0.0 { "Infinity" } else { "-Infinity" })
+    }
+    else {
+        None
+    }
+}
+
+/// Format a timestamp as decimal seconds since the Unix epoch. Returns `None` for leap seconds, 
+/// which can't be written this way.
+fn epoch_time(sec: i64, nano: u32, fixed: bool) -> Option<String> {
+    if nano >= 1_000_000_000 { return None; }
+    let total = sec as i128 * 1_000_000_000 + nano as i128;
+    let sign = if total < 0 { "-" } else { "" };
+    let (sec, nano) = (total.unsigned_abs() / 1_000_000_000, total.unsigned_abs() % 1_000_000_000);
+    if nano == 0 && !fixed {
+        Some(format!("{}{}", sign, sec))
+    }
+    else {
+        Some(format!("{}{}.{:09}", sign, sec, nano))
+    }
+}
+
+/// Options for converting fog-pack values into JSON.
+///
+/// The default options produce the standard encoding described in the [crate
+/// documentation][crate], which can always be converted back into the original fog-pack value.
+/// The same options are accepted by the value, Document, Entry, and Query converters. Options are
+/// set builder-style:
+///
+/// ```
+/// # use fog_human_json::*;
+/// let opts = EncodeOptions::new().bin_as_array(8);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct EncodeOptions {
+    bin_array_max: Option<usize>,
+    bin_array_wrapped: bool,
+    bin_hex_max: Option<usize>,
+    bin_hexdump: bool,
+    bin_omit_max: Option<usize>,
+    base64_url_safe: bool,
+    base64_padding: bool,
+    int_annotate: bool,
+    int_annotate_unsafe: bool,
+    float_hex: bool,
+    float_keywords: bool,
+    f32_plain: bool,
+    float_precision: Option<u8>,
+    float_precision_lossless: bool,
+    time_format: TimeFormat,
+    time_fixed_precision: bool,
+    multibase: bool,
+    type_prefix: Option<String>,
+    redact_lockboxes: bool,
+    doc_hash: bool,
+    signature_details: bool,
+    schema_names: Option<Arc<dyn SchemaNameResolver>>,
+    identity_names: Option<Arc<dyn IdentityNameResolver>>,
+    max_depth: Option<usize>,
+    generator: bool,
+}
+
+impl EncodeOptions {
+    /// Create the default set of encoding options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Render binary values of up to `max_len` bytes as a JSON array of byte values (like
+    /// `[0,1,2,3]`) instead of as `$fog-Bin:` Base64. This is much easier to read and edit for
+    /// small fields like flags or magic numbers, but the result is indistinguishable from an
+    /// array of integers, so it won't convert back into a Bin value unless 
+    /// [`bin_array_wrapped`][Self::bin_array_wrapped] is also set.
+    pub fn bin_as_array(mut self, max_len: usize) -> Self {
+        self.bin_array_max = Some(max_len);
+        self
+    }
+
+    /// Wrap the byte arrays produced by [`bin_as_array`][Self::bin_as_array] in an object with a 
+    /// single `$fog-Bin` key (like `{"$fog-Bin": [0,1,2,3]}`), which marks the array as binary 
+    /// data so it converts back into the same Bin value when decoding with 
+    /// [`DecodeOptions::bin_wrapper`].
+    pub fn bin_array_wrapped(mut self, enable: bool) -> Self {
+        self.bin_array_wrapped = enable;
+        self
+    }
+
+    /// Render binary values of up to `max_len` bytes as `$fog-Hex:` strings instead of as 
+    /// `$fog-Bin:` Base64. Use `usize::MAX` to render all binary values as hex. Unlike the other 
+    /// binary display options, this converts back into the same Bin value.
+    pub fn bin_as_hex(mut self, max_len: usize) -> Self {
+        self.bin_hex_max = Some(max_len);
+        self
+    }
+
+    /// Render binary values as a hexdump: an array of strings, each holding the offset, hex 
+    /// values, and ASCII representation of 16 bytes. This is for display only, and won't convert 
+    /// back into a Bin value. If [`bin_as_array`][Self::bin_as_array] is also set, short binary 
+    /// values are still rendered as byte arrays.
+    pub fn bin_hexdump(mut self, enable: bool) -> Self {
+        self.bin_hexdump = enable;
+        self
+    }
+
+    /// Replace binary values longer than `max_len` bytes with a `$fog-BinOmitted:<length>,<hash>` 
+    /// placeholder, where the hash is the fog-pack Hash of the omitted bytes. This keeps JSON 
+    /// readable when documents hold large binary fields, but the placeholder can't be converted 
+    /// back: decoding fails with [`DecodeError::OmittedBin`]. Together with 
+    /// [`redact_lockboxes`][Self::redact_lockboxes], this is suited to exports meant only for 
+    /// human review.
+    pub fn bin_omit_over(mut self, max_len: usize) -> Self {
+        self.bin_omit_max = Some(max_len);
+        self
+    }
+
+    /// Use the URL-safe Base64 alphabet (`-_` instead of `+/`) for Bin and lockbox values, so the 
+    /// output can be embedded in URLs without further escaping. The decoder accepts either 
+    /// alphabet.
+    pub fn base64_url_safe(mut self, enable: bool) -> Self {
+        self.base64_url_safe = enable;
+        self
+    }
+
+    /// Emit canonical padded Base64 (ending in `=` or `==` as needed) for Bin and lockbox values. 
+    /// The decoder accepts Base64 with or without padding.
+    pub fn base64_padding(mut self, enable: bool) -> Self {
+        self.base64_padding = enable;
+        self
+    }
+
+    /// Write every integer as a `$fog-Int:<n>` string instead of a bare JSON number. This keeps 
+    /// integers unambiguously typed even if other tools rewrite JSON numbers as floats.
+    pub fn int_annotate(mut self, enable: bool) -> Self {
+        self.int_annotate = enable;
+        self
+    }
+
+    /// Write integers whose magnitude exceeds JavaScript's `Number.MAX_SAFE_INTEGER` (2^53 - 1) 
+    /// as `$fog-Int:<n>` strings, and all other integers as plain JSON numbers. JavaScript-based 
+    /// editors silently round larger numbers, which this avoids without annotating everything.
+    pub fn int_annotate_unsafe(mut self, enable: bool) -> Self {
+        self.int_annotate_unsafe = enable;
+        self
+    }
+
+    /// Always write F32 and F64 values as `$fog-F32Hex:`/`$fog-F64Hex:` strings, which preserve 
+    /// every bit of the value no matter what a JSON editor does to decimal numbers.
+    pub fn float_hex(mut self, enable: bool) -> Self {
+        self.float_hex = enable;
+        self
+    }
+
+    /// Write infinite F32 and F64 values as `$fog-F32:Infinity`/`$fog-F64:-Infinity` strings, and 
+    /// NaN as `$fog-F64:NaN`, instead of in hex. NaNs with an unusual sign or payload are still 
+    /// written in hex, as the keyword would lose those bits. Ignored if 
+    /// [`float_hex`][Self::float_hex] is set.
+    pub fn float_keywords(mut self, enable: bool) -> Self {
+        self.float_keywords = enable;
+        self
+    }
+
+    /// Write finite F32 values as plain JSON numbers instead of `$fog-F32:` strings. **This is 
+    /// lossy**: the values convert back as F64, so only use it for JSON meant purely for 
+    /// reading. The numbers use the shortest decimal form of the F32 value, so `1.1` stays `1.1` 
+    /// rather than becoming `1.100000023841858`.
+    pub fn f32_plain(mut self, enable: bool) -> Self {
+        self.f32_plain = enable;
+        self
+    }
+
+    /// Round F32 and F64 values to at most `digits` significant digits, keeping artifacts like 
+    /// `0.30000000000000004` out of the output. **This is lossy** unless 
+    /// [`float_precision_lossless`][Self::float_precision_lossless] is also set; each rounded 
+    /// value is reported as a [`WarningKind::RoundedFloat`] warning. `digits` is clamped to 1-17.
+    pub fn float_precision(mut self, digits: u8) -> Self {
+        self.float_precision = Some(digits.clamp(1, 17));
+        self
+    }
+
+    /// With [`float_precision`][Self::float_precision], write values that can't be written 
+    /// exactly with that many digits as `$fog-F32Hex:`/`$fog-F64Hex:` strings instead of 
+    /// rounding them, so no precision is lost.
+    pub fn float_precision_lossless(mut self, enable: bool) -> Self {
+        self.float_precision_lossless = enable;
+        self
+    }
+
+    /// Choose how Timestamps are written. See [`TimeFormat`] for the options.
+    pub fn time_format(mut self, format: TimeFormat) -> Self {
+        self.time_format = format;
+        self
+    }
+
+    /// Always write all 9 digits of nanoseconds for [`Rfc3339`][TimeFormat::Rfc3339] and 
+    /// [`Epoch`][TimeFormat::Epoch] times, even when they're zero, so timestamps have a stable 
+    /// width and line up in column-aligned diffs.
+    pub fn time_fixed_precision(mut self, enable: bool) -> Self {
+        self.time_fixed_precision = enable;
+        self
+    }
+
+    /// Prefix the base58 encoding of Hash, Identity, StreamId, and LockId values with the 
+    /// multibase code for base58btc (`z`), for interoperating with multibase-aware tools. The 
+    /// decoder accepts values with or without the prefix.
+    pub fn multibase(mut self, enable: bool) -> Self {
+        self.multibase = enable;
+        self
+    }
+
+    /// Use a different prefix than `$fog-` for type-annotated strings, like `@fog-`, in case 
+    /// `$fog-` collides with sentinel strings from another system. This also changes the key of 
+    /// wrapped binary arrays, but not the `"$fog-generator"` key. The JSON must be decoded with the 
+    /// same prefix set in [`DecodeOptions::type_prefix`]. The prefix should be non-empty.
+    pub fn type_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.type_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Replace all lockbox values with a `$fog-Redacted:<type>,<length>` marker, recording the 
+    /// lockbox type and its length in bytes, so exported JSON never holds the ciphertext. The 
+    /// marker can't be converted back: decoding fails with [`DecodeError::Redacted`]. See 
+    /// [`bin_omit_over`][Self::bin_omit_over] for summarizing large binary values in the same way.
+    pub fn redact_lockboxes(mut self, enable: bool) -> Self {
+        self.redact_lockboxes = enable;
+        self
+    }
+
+    /// Include a `"hash"` entry holding the Document's own hash when converting Documents. When 
+    /// converting back, the hash is checked against the re-encoded Document.
+    pub fn doc_hash(mut self, enable: bool) -> Self {
+        self.doc_hash = enable;
+        self
+    }
+
+    pub(crate) fn doc_hash_enabled(&self) -> bool {
+        self.doc_hash
+    }
+
+    /// Include a `"signature"` entry describing the signature when converting signed Documents and 
+    /// Entries, holding the signer's Identity and the signing algorithm. The entry is ignored when 
+    /// converting back from JSON.
+    ///
+    /// For Documents, it also holds `"valid"`. fog-pack checks a Document's signature against its 
+    /// hash whenever the Document is created, including with trusted decoding, so this is always 
+    /// true. Entries loaded with trusted decoding never had their signature checked, and fog-pack 
+    /// doesn't expose the signature to check it afterwards, so Entries don't include `"valid"`.
+    pub fn signature_details(mut self, enable: bool) -> Self {
+        self.signature_details = enable;
+        self
+    }
+
+    pub(crate) fn signature_details_enabled(&self) -> bool {
+        self.signature_details
+    }
+
+    /// Look up a name for each Document's schema with the given resolver, and include it as a 
+    /// `"schema_name"` entry when converting Documents.
+    pub fn schema_names(mut self, resolver: Arc<dyn SchemaNameResolver>) -> Self {
+        self.schema_names = Some(resolver);
+        self
+    }
+
+    pub(crate) fn schema_name_resolver(&self) -> Option<&dyn SchemaNameResolver> {
+        self.schema_names.as_deref()
+    }
+
+    /// Look up a name for each Identity with the given resolver, and append it to the Identity 
+    /// string in parentheses, like `$fog-Identity:<base58> (alice)`. The name is ignored when 
+    /// decoding.
+    pub fn identity_names(mut self, resolver: Arc<dyn IdentityNameResolver>) -> Self {
+        self.identity_names = Some(resolver);
+        self
+    }
+
+    /// Limit how deeply arrays and maps may be nested. There's no limit by default, as conversion 
+    /// doesn't recurse, and fog-pack never decodes values deeper than its own 
+    /// [`MAX_DEPTH`][fog_pack::MAX_DEPTH]. Going over the limit makes the `try_` conversion 
+    /// functions return [`EncodeError::TooDeep`], and the others panic. The outer object of a 
+    /// Document, Entry, or Query doesn't count against the limit.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Include a `"$fog-generator"` entry when converting Documents, Entries, and Queries, 
+    /// recording this crate's version and the encoding options used. This makes archived JSON 
+    /// self-describing. The entry is ignored when converting back from JSON.
+    pub fn generator(mut self, enable: bool) -> Self {
+        self.generator = enable;
+        self
+    }
+
+    fn prefix(&self) -> &str {
+        self.type_prefix.as_deref().unwrap_or(FOG_PREFIX)
+    }
+
+    /// Start a type-annotated string, like `$fog-Bin:`.
+    fn tag(&self, ty: &str) -> String {
+        let mut s = String::with_capacity(self.prefix().len() + ty.len() + 1);
+        self.push_tag(&mut s, ty);
+        s
+    }
+
+    /// Add a type annotation, like `$fog-Bin:`, to a string.
+    fn push_tag(&self, s: &mut String, ty: &str) {
+        s.push_str(self.prefix());
+        s.push_str(ty);
+        s.push(':');
+    }
+
+    fn generator_json(&self) -> JsonValue {
+        let mut obj = JsonMap::new();
+        obj.insert("name".into(), JsonValue::from(env!("CARGO_PKG_NAME")));
+        obj.insert("version".into(), JsonValue::from(env!("CARGO_PKG_VERSION")));
+        obj.insert("options".into(), self.options_json());
+        JsonValue::Object(obj)
+    }
+
+    /// Record the options as a JSON Object, keyed by the name of the method that sets each one. 
+    /// Unset limits are null, and name resolvers are only recorded as being set or not.
+    fn options_json(&self) -> JsonValue {
+        let time_format = match &self.time_format {
+            TimeFormat::Rfc3339 => JsonValue::from("Rfc3339"),
+            TimeFormat::Epoch => JsonValue::from("Epoch"),
+            TimeFormat::Custom(format) => {
+                let mut obj = JsonMap::new();
+                obj.insert("Custom".into(), JsonValue::from(format.as_str()));
+                JsonValue::Object(obj)
+            },
+        };
+        let options: [(&str, JsonValue); 25] = [
+            ("bin_as_array", self.bin_array_max.into()),
+            ("bin_array_wrapped", self.bin_array_wrapped.into()),
+            ("bin_as_hex", self.bin_hex_max.into()),
+            ("bin_hexdump", self.bin_hexdump.into()),
+            ("bin_omit_over", self.bin_omit_max.into()),
+            ("base64_url_safe", self.base64_url_safe.into()),
+            ("base64_padding", self.base64_padding.into()),
+            ("int_annotate", self.int_annotate.into()),
+            ("int_annotate_unsafe", self.int_annotate_unsafe.into()),
+            ("float_hex", self.float_hex.into()),
+            ("float_keywords", self.float_keywords.into()),
+            ("f32_plain", self.f32_plain.into()),
+            ("float_precision", self.float_precision.into()),
+            ("float_precision_lossless", self.float_precision_lossless.into()),
+            ("time_format", time_format),
+            ("time_fixed_precision", self.time_fixed_precision.into()),
+            ("multibase", self.multibase.into()),
+            ("type_prefix", self.prefix().into()),
+            ("redact_lockboxes", self.redact_lockboxes.into()),
+            ("doc_hash", self.doc_hash.into()),
+            ("signature_details", self.signature_details.into()),
+            ("schema_names", self.schema_names.is_some().into()),
+            ("identity_names", self.identity_names.is_some().into()),
+            ("max_depth", self.max_depth.into()),
+            ("generator", self.generator.into()),
+        ];
+        JsonValue::Object(options.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+}
+
+/// Describe the signature made by an Identity, for [`EncodeOptions::signature_details`]. `valid` 
+/// is only included if it's known whether the signature was verified.
+pub(crate) fn signature_details(
+    signer: &fog_pack::types::Identity,
+    valid: Option<bool>
+) -> FogValueRef<'static> {
+    let algorithm = match signer.version() {
+        1 => "Ed25519",
+        _ => "Unknown",
+    };
+    let mut map: BTreeMap<&str, FogValueRef> = BTreeMap::new();
+    map.insert("signer", FogValueRef::Identity(signer.to_owned()));
+    if let Some(valid) = valid {
+        map.insert("valid", FogValueRef::Bool(valid));
+    }
+    map.insert("algorithm", FogValueRef::Str(algorithm));
+    FogValueRef::Map(map)
 }
 
 /// Convert a fog-pack value to a JSON Value.
+///
+/// # Panics
+///
+/// Never panics, as the default options don't limit how deeply values are nested.
 pub fn fog_to_json (val: &FogValue) -> JsonValue {
-    match val {
-        FogValue::Null => JsonValue::Null,
-        FogValue::Bool(b) => JsonValue::Bool(*b),
-        FogValue::Int(i) => if let Some(i) = i.as_u64() {
-            JsonValue::Number(JsonNumber::from(i))
-        } else {
-            JsonValue::Number(JsonNumber::from(i.as_i64().unwrap()))
-        },
-        FogValue::Str(s) => if s.starts_with(FOG_PREFIX) {
-            const STR_PREFIX: &str = "$fog-Str:";
-            let mut new_s = String::with_capacity(s.len() + STR_PREFIX.len());
-            new_s.push_str(STR_PREFIX);
-            new_s.push_str(s);
-            JsonValue::String(new_s)
-        } else {
-            JsonValue::String(s.clone())
-        },
-        FogValue::F32(f) => {
-            const F32_PREFIX: &str = "$fog-F32:";
-            const F32HEX_PREFIX: &str = "$fog-F32Hex:";
-            if f.is_finite() {
-                let mut s = String::from(F32_PREFIX);
-                let mut buf = ryu::Buffer::new();
-                s.push_str(buf.format_finite(*f));
-                JsonValue::String(s)
-            }
-            else {
-                let mut s = String::from(F32HEX_PREFIX);
-                let v = hex::encode(f.to_be_bytes());
-                s.push_str(&v);
-                JsonValue::String(s)
-            }
-        },
-        FogValue::F64(f) => {
-            const F64HEX_PREFIX: &str = "$fog-F64Hex:";
-            if let Some(n) = JsonNumber::from_f64(*f) {
-                JsonValue::Number(n)
+    fog_to_json_with_options(val, &EncodeOptions::default())
+}
+
+/// Convert a fog-pack ValueRef to a JSON Value.
+///
+/// # Panics
+///
+/// Never panics, as the default options don't limit how deeply values are nested.
+pub fn fogref_to_json (val: &FogValueRef) -> JsonValue {
+    fogref_to_json_with_options(val, &EncodeOptions::default())
+}
+
+/// Convert a fog-pack value to a JSON Value, using the provided encoding options.
+///
+/// # Panics
+///
+/// Panics if the value is nested deeper than a limit set with [`EncodeOptions::max_depth`]. Use 
+/// [`try_fog_to_json`] to get an error instead.
+pub fn fog_to_json_with_options(val: &FogValue, opts: &EncodeOptions) -> JsonValue {
+    try_fog_to_json(val, opts).unwrap_or_else(|err| panic!("{}; use try_fog_to_json to handle this", err))
+}
+
+/// Convert a fog-pack value to a JSON Value, consuming it.
+///
+/// This gives the same result as [`fog_to_json`], but moves strings and map keys into the result 
+/// instead of cloning them.
+///
+/// # Panics
+///
+/// Never panics, as the default options don't limit how deeply values are nested.
+pub fn fog_into_json(val: FogValue) -> JsonValue {
+    fog_into_json_with_options(val, &EncodeOptions::default())
+}
+
+/// Convert a fog-pack value to a JSON Value, consuming it, as with [`fog_into_json`], using the 
+/// provided encoding options.
+///
+/// # Panics
+///
+/// Panics if the value is nested deeper than a limit set with [`EncodeOptions::max_depth`].
+pub fn fog_into_json_with_options(val: FogValue, opts: &EncodeOptions) -> JsonValue {
+    let mut enc = Encoder::new(opts);
+    let json = enc.value_owned(val);
+    enc.check();
+    json
+}
+
+/// Convert a fog-pack ValueRef to a JSON Value, using the provided encoding options.
+///
+/// # Panics
+///
+/// Panics if the value is nested deeper than a limit set with [`EncodeOptions::max_depth`]. Use 
+/// [`try_fogref_to_json`] to get an error instead.
+pub fn fogref_to_json_with_options(val: &FogValueRef, opts: &EncodeOptions) -> JsonValue {
+    let mut enc = Encoder::new(opts);
+    let json = enc.value(val);
+    enc.check();
+    json
+}
+
+/// Convert a fog-pack value to a JSON Value, using the provided encoding options. Fails instead 
+/// of panicking if the value is nested deeper than [`EncodeOptions::max_depth`].
+pub fn try_fog_to_json(val: &FogValue, opts: &EncodeOptions) -> Result<JsonValue, EncodeError> {
+    let mut enc = Encoder::new(opts);
+    let json = enc.value_fog(val);
+    enc.error.map_or(Ok(json), Err)
+}
+
+/// Convert a fog-pack ValueRef to a JSON Value, as with [`try_fog_to_json`].
+pub fn try_fogref_to_json(val: &FogValueRef, opts: &EncodeOptions) -> Result<JsonValue, EncodeError> {
+    let mut enc = Encoder::new(opts);
+    let json = enc.value(val);
+    enc.error.map_or(Ok(json), Err)
+}
+
+/// Convert a fog-pack value to a JSON Value, also returning statistics about the conversion.
+pub fn fog_to_json_with_stats(val: &FogValue, opts: &EncodeOptions) -> (JsonValue, ConvertStats) {
+    json_with_stats(Node::Val(val), opts)
+}
+
+/// Convert a fog-pack ValueRef to a JSON Value, also returning statistics about the conversion.
+pub fn fogref_to_json_with_stats(val: &FogValueRef, opts: &EncodeOptions) -> (JsonValue, ConvertStats) {
+    json_with_stats(Node::Ref(val), opts)
+}
+
+fn json_with_stats(val: Node, opts: &EncodeOptions) -> (JsonValue, ConvertStats) {
+    let start = std::time::Instant::now();
+    let mut enc = Encoder::new(opts);
+    let json = enc.node(val);
+    enc.check();
+    enc.stats.elapsed = start.elapsed();
+    (json, enc.stats)
+}
+
+/// Convert a fog-pack value to a JSON Value, also returning warnings for any values that couldn't 
+/// be written exactly as requested, like NaN floats that had to be written in hex.
+///
+/// # Panics
+///
+/// Panics if the value is nested deeper than [`EncodeOptions::max_depth`].
+pub fn fog_to_json_with_warnings(val: &FogValue, opts: &EncodeOptions) -> (JsonValue, Vec<Warning>) {
+    json_with_warnings(Node::Val(val), opts)
+}
+
+/// Convert a fog-pack ValueRef to a JSON Value, as with [`fog_to_json_with_warnings`].
+pub fn fogref_to_json_with_warnings(val: &FogValueRef, opts: &EncodeOptions) -> (JsonValue, Vec<Warning>) {
+    json_with_warnings(Node::Ref(val), opts)
+}
+
+fn json_with_warnings(val: Node, opts: &EncodeOptions) -> (JsonValue, Vec<Warning>) {
+    let mut enc = Encoder::new(opts);
+    enc.warnings = Warnings::collect();
+    let json = enc.node(val);
+    enc.check();
+    (json, enc.warnings.into_vec())
+}
+
+/// Minimum length of a top-level array or map before it's converted in parallel.
+#[cfg(feature = "rayon")]
+const PAR_MIN_LEN: usize = 1024;
+
+/// Number of elements converted together by each parallel task.
+#[cfg(feature = "rayon")]
+const PAR_CHUNK_LEN: usize = 256;
+
+/// A value to convert, borrowed either as a ValueRef or straight from a Value.
+#[derive(Clone, Copy)]
+pub(crate) enum Node<'v, 'b> {
+    Ref(&'v FogValueRef<'b>),
+    Val(&'v FogValue),
+}
+
+impl Node<'_, '_> {
+    fn is_container(&self) -> bool {
+        matches!(
+            self,
+            Node::Ref(FogValueRef::Array(_) | FogValueRef::Map(_)) | Node::Val(FogValue::Array(_) | FogValue::Map(_))
+        )
+    }
+}
+
+/// An array or map partway through conversion, for converting without recursion.
+enum Frame<'v, 'b> {
+    Array(std::slice::Iter<'v, FogValueRef<'b>>, Vec<JsonValue>),
+    /// Also holds the key of the value currently being converted
+    Map(std::collections::btree_map::Iter<'v, &'b str, FogValueRef<'b>>, &'v str, JsonMap),
+    ValArray(std::slice::Iter<'v, FogValue>, Vec<JsonValue>),
+    ValMap(std::collections::btree_map::Iter<'v, String, FogValue>, &'v str, JsonMap),
+}
+
+impl<'v, 'b> Frame<'v, 'b> {
+    fn new(val: Node<'v, 'b>) -> Self {
+        match val {
+            Node::Ref(FogValueRef::Array(a)) => Frame::Array(a.iter(), Vec::with_capacity(a.len())),
+            Node::Ref(FogValueRef::Map(m)) => Frame::Map(m.iter(), "", JsonMap::new()),
+            Node::Val(FogValue::Array(a)) => Frame::ValArray(a.iter(), Vec::with_capacity(a.len())),
+            Node::Val(FogValue::Map(m)) => Frame::ValMap(m.iter(), "", JsonMap::new()),
+            _ => unreachable!("Only arrays and maps get a frame"),
+        }
+    }
+
+    fn next(&mut self) -> Option<Node<'v, 'b>> {
+        match self {
+            Frame::Array(iter, _) => iter.next().map(Node::Ref),
+            Frame::Map(iter, key, _) => iter.next().map(|(k, v)| {
+                *key = k;
+                Node::Ref(v)
+            }),
+            Frame::ValArray(iter, _) => iter.next().map(Node::Val),
+            Frame::ValMap(iter, key, _) => iter.next().map(|(k, v)| {
+                *key = k;
+                Node::Val(v)
+            }),
+        }
+    }
+
+    fn push(&mut self, json: JsonValue) {
+        match self {
+            Frame::Array(_, out) | Frame::ValArray(_, out) => out.push(json),
+            Frame::Map(_, key, out) | Frame::ValMap(_, key, out) => { out.insert(key.to_string(), json); },
+        }
+    }
+
+    fn finish(self) -> JsonValue {
+        match self {
+            Frame::Array(_, out) | Frame::ValArray(_, out) => JsonValue::Array(out),
+            Frame::Map(_, _, out) | Frame::ValMap(_, _, out) => JsonValue::Object(out),
+        }
+    }
+}
+
+/// Get a JSON Pointer to the value currently being converted.
+fn frames_pointer(stack: &[Frame]) -> String {
+    let mut pointer = String::new();
+    for frame in stack {
+        match frame {
+            Frame::Array(_, out) | Frame::ValArray(_, out) => {
+                dec::push_pointer_segment(&mut pointer, &out.len().to_string())
+            },
+            Frame::Map(_, key, _) | Frame::ValMap(_, key, _) => dec::push_pointer_segment(&mut pointer, key),
+        }
+    }
+    pointer
+}
+
+/// An owned array or map partway through conversion, as with [`Frame`].
+enum OwnedFrame {
+    Array(std::vec::IntoIter<FogValue>, Vec<JsonValue>),
+    Map(std::collections::btree_map::IntoIter<String, FogValue>, String, JsonMap),
+}
+
+impl OwnedFrame {
+    fn next(&mut self) -> Option<FogValue> {
+        match self {
+            OwnedFrame::Array(iter, _) => iter.next(),
+            OwnedFrame::Map(iter, key, _) => iter.next().map(|(k, v)| {
+                *key = k;
+                v
+            }),
+        }
+    }
+
+    fn push(&mut self, json: JsonValue) {
+        match self {
+            OwnedFrame::Array(_, out) => out.push(json),
+            OwnedFrame::Map(_, key, out) => { out.insert(std::mem::take(key), json); },
+        }
+    }
+
+    fn finish(self) -> JsonValue {
+        match self {
+            OwnedFrame::Array(_, out) => JsonValue::Array(out),
+            OwnedFrame::Map(_, _, out) => JsonValue::Object(out),
+        }
+    }
+}
+
+/// An array or map partway through being written out, along with whether it's still empty.
+enum WriteFrame<'v, 'b> {
+    Array(std::slice::Iter<'v, FogValueRef<'b>>, bool),
+    Map(std::collections::btree_map::Iter<'v, &'b str, FogValueRef<'b>>, bool),
+    ValArray(std::slice::Iter<'v, FogValue>, bool),
+    ValMap(std::collections::btree_map::Iter<'v, String, FogValue>, bool),
+}
+
+impl<'v, 'b> WriteFrame<'v, 'b> {
+    /// Get the next value to write, writing out the separator and key that go before it.
+    fn next<W: std::io::Write>(&mut self, w: &mut W) -> std::io::Result<Option<Node<'v, 'b>>> {
+        let (key, val, first) = match self {
+            WriteFrame::Array(iter, first) => match iter.next() {
+                Some(val) => (None, Node::Ref(val), first),
+                None => return Ok(None),
+            },
+            WriteFrame::Map(iter, first) => match iter.next() {
+                Some((key, val)) => (Some(*key), Node::Ref(val), first),
+                None => return Ok(None),
+            },
+            WriteFrame::ValArray(iter, first) => match iter.next() {
+                Some(val) => (None, Node::Val(val), first),
+                None => return Ok(None),
+            },
+            WriteFrame::ValMap(iter, first) => match iter.next() {
+                Some((key, val)) => (Some(key.as_str()), Node::Val(val), first),
+                None => return Ok(None),
+            },
+        };
+        if !*first { w.write_all(b",")?; }
+        if let Some(key) = key {
+            serde_json::to_writer(&mut *w, key)?;
+            w.write_all(b":")?;
+        }
+        *first = false;
+        Ok(Some(val))
+    }
+
+    fn close(&self) -> &'static [u8] {
+        match self {
+            WriteFrame::Array(..) | WriteFrame::ValArray(..) => b"]",
+            WriteFrame::Map(..) | WriteFrame::ValMap(..) => b"}",
+        }
+    }
+}
+
+/// Buffers reused while converting values, so writing out JSON text doesn't allocate for each 
+/// string that's built up.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Scratch {
+    /// The string for the value being written, including any Base64 text
+    text: String,
+    /// The encoded bytes of an Identity, StreamId, or LockId
+    bytes: Vec<u8>,
+    /// Base58 text, before it's added to a string
+    base58: String,
+    /// A float formatted to some number of significant digits
+    float: String,
+}
+
+impl Scratch {
+    /// Base58-encode `data` onto a string.
+    fn push_base58(&mut self, s: &mut String, data: &[u8]) {
+        // Writing to a String can't fail
+        bs58::encode(data).into(&mut self.base58).unwrap();
+        s.push_str(&self.base58);
+    }
+
+    /// Base58-encode the bytes written by `encode` onto a string.
+    fn push_base58_with(&mut self, s: &mut String, encode: impl FnOnce(&mut Vec<u8>)) {
+        let mut bytes = std::mem::take(&mut self.bytes);
+        bytes.clear();
+        encode(&mut bytes);
+        self.push_base58(s, &bytes);
+        self.bytes = bytes;
+    }
+
+    /// Format a float in scientific notation with `digits` significant digits.
+    fn float_digits(&mut self, f: impl std::fmt::LowerExp, digits: u8) -> &str {
+        use std::fmt::Write;
+        self.float.clear();
+        write!(self.float, "{:.*e}", digits as usize - 1, f).unwrap();
+        &self.float
+    }
+}
+
+/// Add the lowercase hex form of some bytes to a string.
+fn push_hex(s: &mut String, bytes: &[u8]) {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    for b in bytes {
+        s.push(DIGITS[(b >> 4) as usize] as char);
+        s.push(DIGITS[(b & 0xf) as usize] as char);
+    }
+}
+
+/// Converts fog-pack values to JSON while tracking conversion statistics.
+pub(crate) struct Encoder<'a> {
+    pub(crate) opts: &'a EncodeOptions,
+    pub(crate) stats: ConvertStats,
+    warnings: Warnings,
+    depth: usize,
+    error: Option<EncodeError>,
+    scratch: Scratch,
+}
+
+impl<'a> Encoder<'a> {
+    pub(crate) fn new(opts: &'a EncodeOptions) -> Self {
+        Self::with_scratch(opts, Scratch::default())
+    }
+
+    /// Make an encoder that reuses buffers from an earlier one, as taken with 
+    /// [`into_scratch`][Self::into_scratch].
+    pub(crate) fn with_scratch(opts: &'a EncodeOptions, scratch: Scratch) -> Self {
+        Self {
+            opts,
+            stats: ConvertStats::default(),
+            warnings: Warnings::default(),
+            depth: 0,
+            error: None,
+            scratch,
+        }
+    }
+
+    /// Take the error from going over the depth limit, if there was one.
+    pub(crate) fn take_error(&mut self) -> Option<EncodeError> {
+        self.error.take()
+    }
+
+    /// Take back the buffers this encoder used, for reuse by a later one.
+    pub(crate) fn into_scratch(self) -> Scratch {
+        self.scratch
+    }
+
+    /// Panic if conversion went over the depth limit.
+    pub(crate) fn check(&self) {
+        if let Some(err) = &self.error {
+            panic!("{}; use the try_ conversion functions to handle this", err);
+        }
+    }
+
+    fn base64(&mut self, input: &[u8], output_buf: &mut String) {
+        self.stats.base64_bytes += input.len() as u64;
+        base64_encode(input, self.opts.base64_url_safe, self.opts.base64_padding, output_buf)
+    }
+
+    /// Convert the envelope of a Document, Entry, or Query, adding any envelope-only entries along 
+    /// with the `extra` ones.
+    pub(crate) fn envelope(&mut self, val: &FogValueRef, extra: JsonMap) -> JsonValue {
+        let json = self.try_envelope(val, extra);
+        json.unwrap_or_else(|err| panic!("{}; use the try_ conversion functions to handle this", err))
+    }
+
+    /// Convert the envelope of a Document, Entry, or Query, as with [`envelope`][Self::envelope], 
+    /// failing instead of panicking if the depth limit was exceeded.
+    pub(crate) fn try_envelope(&mut self, val: &FogValueRef, extra: JsonMap) -> Result<JsonValue, EncodeError> {
+        // The envelope's own map doesn't count against the depth limit
+        let mut json = match val {
+            FogValueRef::Map(map) => {
+                self.stats.nodes += 1;
+                self.map(map)
+            },
+            _ => self.value(val),
+        };
+        if let JsonValue::Object(obj) = &mut json {
+            obj.extend(extra);
+            if self.opts.generator {
+                obj.insert(GENERATOR_KEY.into(), self.opts.generator_json());
             }
-            else {
-                let mut s = String::from(F64HEX_PREFIX);
-                let v = hex::encode(f.to_be_bytes());
-                s.push_str(&v);
-                JsonValue::String(s)
+            // With `preserve_order`, the added entries land at the end instead of in sorted order
+            let mut entries: Vec<(String, JsonValue)> = std::mem::take(obj).into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            *obj = entries.into_iter().collect();
+        }
+        self.error.take().map_or(Ok(json), Err)
+    }
+
+    /// Write the envelope of a Document, Entry, or Query as compact JSON text, adding any 
+    /// envelope-only entries along with the `extra` ones. Going over the depth limit is reported 
+    /// as an [`InvalidData`][std::io::ErrorKind::InvalidData] error.
+    pub(crate) fn write_envelope<W: std::io::Write>(
+        &mut self,
+        w: &mut W,
+        val: &FogValueRef,
+        extra: JsonMap
+    ) -> std::io::Result<()> {
+        self.try_write_envelope(w, val, extra)?;
+        match self.take_error() {
+            Some(err) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, err)),
+            None => Ok(()),
+        }
+    }
+
+    /// Write the envelope of a Document, Entry, or Query as compact JSON text, as with 
+    /// [`write_envelope`][Self::write_envelope], leaving any depth limit error for 
+    /// [`take_error`][Self::take_error].
+    pub(crate) fn try_write_envelope<W: std::io::Write>(
+        &mut self,
+        w: &mut W,
+        val: &FogValueRef,
+        mut extra: JsonMap
+    ) -> std::io::Result<()> {
+        enum Item<'a, 'b> {
+            Fog(&'a FogValueRef<'b>),
+            Json(&'a JsonValue),
+        }
+        let FogValueRef::Map(map) = val else {
+            return self.write(w, val);
+        };
+        if self.opts.generator {
+            extra.insert(GENERATOR_KEY.into(), self.opts.generator_json());
+        }
+        // The envelope's own map doesn't count against the depth limit
+        self.stats.nodes += 1;
+        let mut items: BTreeMap<&str, Item> = map.iter().map(|(k, v)| (*k, Item::Fog(v))).collect();
+        items.extend(extra.iter().map(|(k, v)| (k.as_str(), Item::Json(v))));
+        w.write_all(b"{")?;
+        for (i, (k, v)) in items.into_iter().enumerate() {
+            if i > 0 { w.write_all(b",")?; }
+            serde_json::to_writer(&mut *w, k)?;
+            w.write_all(b":")?;
+            match v {
+                Item::Fog(v) => self.write(w, v)?,
+                Item::Json(v) => serde_json::to_writer(&mut *w, v)?,
             }
-        },
-        FogValue::Bin(b) => {
-            let mut s = String::from("$fog-Bin:");
-            base64_encode(b, &mut s);
-            JsonValue::String(s)
-        },
-        FogValue::Map(map) => {
-            let mut obj = JsonMap::new();
-            for (k, v) in map.iter() {
-                obj.insert(k.clone(), fog_to_json(v));
+        }
+        w.write_all(b"}")
+    }
+
+    fn lockbox(&mut self, ty: &str, bytes: &[u8], s: &mut String) {
+        if self.opts.redact_lockboxes {
+            use std::fmt::Write;
+            self.opts.push_tag(s, "Redacted");
+            write!(s, "{},{}", ty, bytes.len()).unwrap();
+            return;
+        }
+        self.opts.push_tag(s, ty);
+        self.base64(bytes, s);
+    }
+
+    fn map(&mut self, map: &BTreeMap<&str, FogValueRef>) -> JsonValue {
+        let mut obj = JsonMap::new();
+        for (k, v) in map.iter() {
+            obj.insert(k.to_string(), self.value(v));
+        }
+        JsonValue::Object(obj)
+    }
+
+    /// Go one level deeper into an array or map, recording an error and returning false if that 
+    /// would go past the depth limit.
+    fn enter(&mut self) -> bool {
+        if let Some(max_depth) = self.opts.max_depth.filter(|max| self.depth >= *max) {
+            self.error.get_or_insert(EncodeError::TooDeep(max_depth));
+            return false;
+        }
+        self.depth += 1;
+        true
+    }
+
+    /// Write a value out as compact JSON text, without building the JSON value for any array or 
+    /// map along the way.
+    pub(crate) fn write<W: std::io::Write>(&mut self, w: &mut W, val: &FogValueRef) -> std::io::Result<()> {
+        self.write_node(w, Node::Ref(val))
+    }
+
+    /// Write a value out as compact JSON text, as with [`write`][Self::write].
+    pub(crate) fn write_fog<W: std::io::Write>(&mut self, w: &mut W, val: &FogValue) -> std::io::Result<()> {
+        self.write_node(w, Node::Val(val))
+    }
+
+    fn write_node<W: std::io::Write>(&mut self, w: &mut W, val: Node) -> std::io::Result<()> {
+        let mut stack: Vec<WriteFrame> = Vec::new();
+        let mut current = val;
+        loop {
+            self.write_start(w, current, &mut stack)?;
+            // Close out finished arrays and maps until one has another value to write
+            loop {
+                let Some(frame) = stack.last_mut() else { return Ok(()) };
+                match frame.next(w)? {
+                    Some(child) => {
+                        current = child;
+                        break;
+                    },
+                    None => {
+                        w.write_all(frame.close())?;
+                        stack.pop();
+                        self.depth -= 1;
+                    },
+                }
             }
-            JsonValue::Object(obj)
-        }
-        FogValue::Array(array) => {
-            let array: Vec<JsonValue> = array.iter().map(fog_to_json).collect();
-            JsonValue::Array(array)
-        },
-        FogValue::Hash(v) => {
-            let mut s = String::from("$fog-Hash:");
-            let v = v.to_base58();
-            s.push_str(&v);
-            JsonValue::String(s)
-        },
-        FogValue::Identity(v) => {
-            let mut s = String::from("$fog-Identity:");
-            let v = v.to_base58();
-            s.push_str(&v);
-            JsonValue::String(s)
-        },
-        FogValue::StreamId(v) => {
-            let mut s = String::from("$fog-StreamId:");
-            let v = v.to_base58();
-            s.push_str(&v);
-            JsonValue::String(s)
-        },
-        FogValue::LockId(v) => {
-            let mut s = String::from("$fog-LockId:");
-            let v = v.to_base58();
-            s.push_str(&v);
-            JsonValue::String(s)
-        },
-        FogValue::DataLockbox(v) => {
-            let mut s = String::from("$fog-DataLockbox:");
-            base64_encode(v.as_bytes(), &mut s);
-            JsonValue::String(s)
-        },
-        FogValue::IdentityLockbox(v) => {
-            let mut s = String::from("$fog-IdentityLockbox:");
-            base64_encode(v.as_bytes(), &mut s);
-            JsonValue::String(s)
-        },
-        FogValue::StreamLockbox(v) => {
-            let mut s = String::from("$fog-StreamLockbox:");
-            base64_encode(v.as_bytes(), &mut s);
-            JsonValue::String(s)
-        },
-        FogValue::LockLockbox(v) => {
-            let mut s = String::from("$fog-LockLockbox:");
-            base64_encode(v.as_bytes(), &mut s);
-            JsonValue::String(s)
-        },
-        FogValue::Timestamp(t) => {
-            use chrono::offset::TimeZone;
-            let mut s = String::from("$fog-Time:");
-            let time = chrono::Utc.timestamp_opt(
-                t.timestamp_utc(), t.timestamp_subsec_nanos()
-            ).unwrap();
-            let t = time.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true);
-            s.push_str(&t);
-            JsonValue::String(s)
         }
     }
-}
 
-/// Convert a fog-pack ValueRef to a JSON Value.
-pub fn fogref_to_json (val: &FogValueRef) -> JsonValue {
-    match val {
-        FogValueRef::Null => JsonValue::Null,
-        FogValueRef::Bool(b) => JsonValue::Bool(*b),
-        FogValueRef::Int(i) => if let Some(i) = i.as_u64() {
-            JsonValue::Number(JsonNumber::from(i))
-        } else {
-            JsonValue::Number(JsonNumber::from(i.as_i64().unwrap()))
-        },
-        FogValueRef::Str(s) => if s.starts_with(FOG_PREFIX) {
-            const STR_PREFIX: &str = "$fog-Str:";
-            let mut new_s = String::with_capacity(s.len() + STR_PREFIX.len());
-            new_s.push_str(STR_PREFIX);
-            new_s.push_str(s);
-            JsonValue::String(new_s)
-        } else {
-            JsonValue::String(s.to_string())
-        },
-        FogValueRef::F32(f) => {
-            const F32_PREFIX: &str = "$fog-F32:";
-            const F32HEX_PREFIX: &str = "$fog-F32Hex:";
-            if f.is_finite() {
-                let mut s = String::from(F32_PREFIX);
-                let mut buf = ryu::Buffer::new();
-                s.push_str(buf.format_finite(*f));
-                JsonValue::String(s)
+    /// Start writing a value. Non-container values are written out completely, while arrays and 
+    /// maps are opened and pushed onto the stack.
+    fn write_start<'v, 'b, W: std::io::Write>(
+        &mut self,
+        w: &mut W,
+        val: Node<'v, 'b>,
+        stack: &mut Vec<WriteFrame<'v, 'b>>
+    ) -> std::io::Result<()> {
+        match val {
+            Node::Ref(FogValueRef::Array(array)) => self.write_open(w, stack, WriteFrame::Array(array.iter(), true)),
+            Node::Ref(FogValueRef::Map(map)) => self.write_open(w, stack, WriteFrame::Map(map.iter(), true)),
+            Node::Val(FogValue::Array(array)) => self.write_open(w, stack, WriteFrame::ValArray(array.iter(), true)),
+            Node::Val(FogValue::Map(map)) => self.write_open(w, stack, WriteFrame::ValMap(map.iter(), true)),
+            Node::Ref(val) => self.write_leaf(w, val),
+            Node::Val(val) => self.write_leaf(w, &val.as_ref()),
+        }
+    }
+
+    /// Write out a value that isn't an array or map.
+    fn write_leaf<W: std::io::Write>(&mut self, w: &mut W, val: &FogValueRef) -> std::io::Result<()> {
+        // Write out the common plain values directly, without allocating for them
+        let opts = self.opts;
+        match val {
+            FogValueRef::Null => self.write_plain(w, &()),
+            FogValueRef::Bool(b) => self.write_plain(w, b),
+            FogValueRef::Str(s) if !s.starts_with(opts.prefix()) => self.write_plain(w, s),
+            FogValueRef::Int(i) if !(opts.int_annotate || (opts.int_annotate_unsafe && !is_js_safe(i))) => {
+                match i.as_u64() {
+                    Some(i) => self.write_plain(w, &i),
+                    None => self.write_plain(w, &i.as_i64().unwrap()),
+                }
+            },
+            _ => {
+                self.stats.nodes += 1;
+                let mut s = std::mem::take(&mut self.scratch.text);
+                s.clear();
+                let result = match self.leaf(val, &mut s) {
+                    Some(json) => serde_json::to_writer(w, &json),
+                    None => {
+                        if s.starts_with(opts.prefix()) { self.stats.annotations += 1; }
+                        serde_json::to_writer(w, s.as_str())
+                    },
+                };
+                self.scratch.text = s;
+                Ok(result?)
+            },
+        }
+    }
+
+    /// Open an array or map being written, pushing it onto the stack.
+    fn write_open<'v, 'b, W: std::io::Write>(
+        &mut self,
+        w: &mut W,
+        stack: &mut Vec<WriteFrame<'v, 'b>>,
+        frame: WriteFrame<'v, 'b>
+    ) -> std::io::Result<()> {
+        self.stats.nodes += 1;
+        if !self.enter() {
+            return w.write_all(b"null");
+        }
+        w.write_all(match frame {
+            WriteFrame::Array(..) | WriteFrame::ValArray(..) => b"[",
+            WriteFrame::Map(..) | WriteFrame::ValMap(..) => b"{",
+        })?;
+        stack.push(frame);
+        Ok(())
+    }
+
+    /// Make an encoder for converting part of this one's current value on another thread.
+    #[cfg(feature = "rayon")]
+    fn fork(&self) -> Self {
+        Self {
+            opts: self.opts,
+            stats: ConvertStats::default(),
+            warnings: Warnings::default(),
+            depth: self.depth,
+            error: None,
+            scratch: Scratch::default(),
+        }
+    }
+
+    /// Fold in the statistics and any error from a forked encoder.
+    #[cfg(feature = "rayon")]
+    fn join(&mut self, other: Encoder) {
+        self.stats.nodes += other.stats.nodes;
+        self.stats.base64_bytes += other.stats.base64_bytes;
+        self.stats.annotations += other.stats.annotations;
+        if let Some(err) = other.error {
+            self.error.get_or_insert(err);
+        }
+    }
+
+    /// Convert a large top-level array or map in parallel, returning `None` if it's too small to 
+    /// be worth it.
+    #[cfg(feature = "rayon")]
+    fn par_container(&mut self, val: Node) -> Option<JsonValue> {
+        match val {
+            Node::Ref(FogValueRef::Array(a)) if a.len() >= PAR_MIN_LEN => Some(self.par_array(a, Node::Ref)),
+            Node::Val(FogValue::Array(a)) if a.len() >= PAR_MIN_LEN => Some(self.par_array(a, Node::Val)),
+            Node::Ref(FogValueRef::Map(m)) if m.len() >= PAR_MIN_LEN => {
+                Some(self.par_map(m.iter().map(|(k, v)| (*k, Node::Ref(v))).collect()))
+            },
+            Node::Val(FogValue::Map(m)) if m.len() >= PAR_MIN_LEN => {
+                Some(self.par_map(m.iter().map(|(k, v)| (k.as_str(), Node::Val(v))).collect()))
+            },
+            _ => None,
+        }
+    }
+
+    /// Convert a large top-level array, splitting it into chunks that are converted in parallel.
+    #[cfg(feature = "rayon")]
+    fn par_array<'v, 'b, T: Sync>(&mut self, array: &'v [T], node: fn(&'v T) -> Node<'v, 'b>) -> JsonValue {
+        use rayon::prelude::*;
+        let parts: Vec<(Vec<JsonValue>, Encoder)> = array.par_chunks(PAR_CHUNK_LEN)
+            .map(|chunk| {
+                let mut enc = self.fork();
+                let json = chunk.iter().map(|v| enc.node(node(v))).collect();
+                (json, enc)
+            })
+            .collect();
+        let mut out = Vec::with_capacity(array.len());
+        for (json, enc) in parts {
+            out.extend(json);
+            self.join(enc);
+        }
+        JsonValue::Array(out)
+    }
+
+    /// Convert a large top-level map, splitting it into partitions that are converted in parallel.
+    #[cfg(feature = "rayon")]
+    fn par_map(&mut self, entries: Vec<(&str, Node)>) -> JsonValue {
+        use rayon::prelude::*;
+        let parts: Vec<(Vec<(String, JsonValue)>, Encoder)> = entries.par_chunks(PAR_CHUNK_LEN)
+            .map(|chunk| {
+                let mut enc = self.fork();
+                let json = chunk.iter().map(|(k, v)| (k.to_string(), enc.node(*v))).collect();
+                (json, enc)
+            })
+            .collect();
+        let mut out = JsonMap::new();
+        for (json, enc) in parts {
+            out.extend(json);
+            self.join(enc);
+        }
+        JsonValue::Object(out)
+    }
+
+    /// Convert a value, moving its strings and map keys into the result.
+    fn value_owned(&mut self, val: FogValue) -> JsonValue {
+        let mut stack: Vec<OwnedFrame> = Vec::new();
+        let mut current = val;
+        loop {
+            let mut finished = self.owned_start(current, &mut stack);
+            // Finish arrays and maps until one has another value to convert
+            loop {
+                let Some(frame) = stack.last_mut() else {
+                    return finished.expect("Root value should be finished once the stack is empty");
+                };
+                if let Some(json) = finished.take() { frame.push(json); }
+                match frame.next() {
+                    Some(child) => {
+                        current = child;
+                        break;
+                    },
+                    None => {
+                        finished = stack.pop().map(OwnedFrame::finish);
+                        self.depth -= 1;
+                    },
+                }
             }
-            else {
-                let mut s = String::from(F32HEX_PREFIX);
-                let v = hex::encode(f.to_be_bytes());
-                s.push_str(&v);
-                JsonValue::String(s)
+        }
+    }
+
+    /// Start converting an owned value, returning it if it isn't an array or map. Arrays and 
+    /// maps are pushed onto the stack instead.
+    fn owned_start(&mut self, val: FogValue, stack: &mut Vec<OwnedFrame>) -> Option<JsonValue> {
+        match val {
+            FogValue::Array(_) | FogValue::Map(_) | FogValue::Str(_) => (),
+            val => return Some(self.value(&val.as_ref())),
+        }
+        self.stats.nodes += 1;
+        match val {
+            FogValue::Str(s) => {
+                if !s.starts_with(self.opts.prefix()) {
+                    return Some(JsonValue::String(s));
+                }
+                self.stats.annotations += 1;
+                let mut new_s = self.opts.tag("Str");
+                new_s.push_str(&s);
+                return Some(JsonValue::String(new_s));
+            },
+            _ if !self.enter() => return Some(JsonValue::Null),
+            FogValue::Array(array) => {
+                let out = Vec::with_capacity(array.len());
+                stack.push(OwnedFrame::Array(array.into_iter(), out));
+            },
+            FogValue::Map(map) => stack.push(OwnedFrame::Map(map.into_iter(), String::new(), JsonMap::new())),
+            _ => unreachable!(),
+        }
+        None
+    }
+
+    fn write_plain<W: std::io::Write, T: serde::Serialize + ?Sized>(&mut self, w: &mut W, val: &T) -> std::io::Result<()> {
+        self.stats.nodes += 1;
+        Ok(serde_json::to_writer(w, val)?)
+    }
+
+    pub(crate) fn value(&mut self, val: &FogValueRef) -> JsonValue {
+        self.node(Node::Ref(val))
+    }
+
+    /// Convert a value straight from a [`FogValue`], without going through a ValueRef first.
+    pub(crate) fn value_fog(&mut self, val: &FogValue) -> JsonValue {
+        self.node(Node::Val(val))
+    }
+
+    fn node(&mut self, val: Node) -> JsonValue {
+        let mut stack: Vec<Frame> = Vec::new();
+        let mut current = val;
+        loop {
+            let warned = self.warnings.len();
+            let mut finished = self.start(current, &mut stack);
+            self.warnings.locate(warned, || frames_pointer(&stack));
+            // Finish arrays and maps until one has another value to convert
+            loop {
+                let Some(frame) = stack.last_mut() else {
+                    return finished.expect("Root value should be finished once the stack is empty");
+                };
+                if let Some(json) = finished.take() { frame.push(json); }
+                match frame.next() {
+                    Some(child) => {
+                        current = child;
+                        break;
+                    },
+                    None => {
+                        finished = stack.pop().map(Frame::finish);
+                        self.depth -= 1;
+                    },
+                }
             }
-        },
-        FogValueRef::F64(f) => {
-            const F64HEX_PREFIX: &str = "$fog-F64Hex:";
-            if let Some(n) = JsonNumber::from_f64(*f) {
-                JsonValue::Number(n)
+        }
+    }
+
+    /// Start converting a value, returning it if it isn't an array or map. Arrays and maps are 
+    /// pushed onto the stack instead.
+    fn start<'v, 'b>(&mut self, val: Node<'v, 'b>, stack: &mut Vec<Frame<'v, 'b>>) -> Option<JsonValue> {
+        self.stats.nodes += 1;
+        if val.is_container() {
+            if !self.enter() {
+                return Some(JsonValue::Null);
             }
-            else {
-                let mut s = String::from(F64HEX_PREFIX);
-                let v = hex::encode(f.to_be_bytes());
-                s.push_str(&v);
-                JsonValue::String(s)
+            // Warnings need the location of each value, which forked encoders don't track
+            #[cfg(feature = "rayon")]
+            if self.depth == 1 && !self.warnings.enabled() {
+                if let Some(json) = self.par_container(val) {
+                    self.depth -= 1;
+                    return Some(json);
+                }
             }
-        },
-        FogValueRef::Bin(b) => {
-            let mut s = String::from("$fog-Bin:");
-            base64_encode(b, &mut s);
-            JsonValue::String(s)
-        },
-        FogValueRef::Map(map) => {
-            let mut obj = JsonMap::new();
-            for (k, v) in map.iter() {
-                obj.insert(k.to_string(), fogref_to_json(v));
+            stack.push(Frame::new(val));
+            return None;
+        }
+        let json = match val {
+            Node::Ref(val) => self.value_inner(val),
+            Node::Val(val) => self.value_inner(&val.as_ref()),
+        };
+        if let JsonValue::String(s) = &json {
+            if s.starts_with(self.opts.prefix()) { self.stats.annotations += 1; }
+        }
+        Some(json)
+    }
+
+    /// Round a finite F32 to the digits set with [`EncodeOptions::float_precision`], returning 
+    /// `None` if that would change it and rounding must be lossless.
+    fn round_f32(&mut self, f: f32) -> Option<f32> {
+        let Some(digits) = self.opts.float_precision else { return Some(f) };
+        // Rounding up can overflow, in which case the value is left alone
+        let rounded = self.scratch.float_digits(f, digits).parse::<f32>().ok()
+            .filter(|r| r.is_finite())
+            .unwrap_or(f);
+        self.allow_rounding(rounded == f).then_some(rounded)
+    }
+
+    /// Round a finite F64 to the digits set with [`EncodeOptions::float_precision`], as with 
+    /// [`round_f32`][Self::round_f32].
+    fn round_f64(&mut self, f: f64) -> Option<f64> {
+        let Some(digits) = self.opts.float_precision else { return Some(f) };
+        let rounded = self.scratch.float_digits(f, digits).parse::<f64>().ok()
+            .filter(|r| r.is_finite())
+            .unwrap_or(f);
+        self.allow_rounding(rounded == f).then_some(rounded)
+    }
+
+    /// Check if a rounded float can be used, warning about it if it isn't exact.
+    fn allow_rounding(&mut self, exact: bool) -> bool {
+        if exact {
+            return true;
+        }
+        if self.opts.float_precision_lossless {
+            return false;
+        }
+        self.warnings.push(WarningKind::RoundedFloat);
+        true
+    }
+
+    /// Convert a value that isn't an array or map.
+    fn value_inner(&mut self, val: &FogValueRef) -> JsonValue {
+        let mut s = String::new();
+        self.leaf(val, &mut s).unwrap_or(JsonValue::String(s))
+    }
+
+    /// Convert a value that isn't an array or map. If it converts to a string, that string is 
+    /// added to `s` and `None` is returned, so the caller can reuse a buffer for it.
+    fn leaf(&mut self, val: &FogValueRef, s: &mut String) -> Option<JsonValue> {
+        let opts = self.opts;
+        match val {
+            FogValueRef::Null => return Some(JsonValue::Null),
+            FogValueRef::Bool(b) => return Some(JsonValue::Bool(*b)),
+            FogValueRef::Int(i) => if opts.int_annotate || (opts.int_annotate_unsafe && !is_js_safe(i)) {
+                use std::fmt::Write;
+                opts.push_tag(s, "Int");
+                write!(s, "{}", i).unwrap();
+            } else if let Some(i) = i.as_u64() {
+                return Some(JsonValue::Number(JsonNumber::from(i)));
+            } else {
+                return Some(JsonValue::Number(JsonNumber::from(i.as_i64().unwrap())));
+            },
+            FogValueRef::Str(v) => {
+                if v.starts_with(opts.prefix()) { opts.push_tag(s, "Str"); }
+                s.push_str(v);
+            },
+            FogValueRef::F32(f) => {
+                let decimal = (f.is_finite() && !opts.float_hex).then(|| self.round_f32(*f)).flatten();
+                if let Some(d) = decimal.filter(|_| opts.f32_plain) {
+                    let mut buf = ryu::Buffer::new();
+                    let n = buf.format_finite(d).parse::<f64>().ok().and_then(JsonNumber::from_f64);
+                    if let Some(n) = n {
+                        self.warnings.push(WarningKind::PlainF32);
+                        return Some(JsonValue::Number(n));
+                    }
+                }
+                if let Some(d) = decimal {
+                    opts.push_tag(s, "F32");
+                    let mut buf = ryu::Buffer::new();
+                    s.push_str(buf.format_finite(d));
+                }
+                else if let Some(word) = float_keyword(*f as f64, f.to_bits() == f32::NAN.to_bits())
+                    .filter(|_| opts.float_keywords && !opts.float_hex)
+                {
+                    opts.push_tag(s, "F32");
+                    s.push_str(word);
+                }
+                else {
+                    if !f.is_finite() && !opts.float_hex {
+                        self.warnings.push(WarningKind::NonFiniteFloat);
+                    }
+                    opts.push_tag(s, "F32Hex");
+                    push_hex(s, &f.to_be_bytes());
+                }
+            },
+            FogValueRef::F64(f) => {
+                let decimal = (f.is_finite() && !opts.float_hex).then(|| self.round_f64(*f)).flatten();
+                if let Some(n) = decimal.and_then(JsonNumber::from_f64) {
+                    return Some(JsonValue::Number(n));
+                }
+                else if let Some(word) = float_keyword(*f, f.to_bits() == f64::NAN.to_bits())
+                    .filter(|_| opts.float_keywords && !opts.float_hex)
+                {
+                    opts.push_tag(s, "F64");
+                    s.push_str(word);
+                }
+                else {
+                    if !f.is_finite() && !opts.float_hex {
+                        self.warnings.push(WarningKind::NonFiniteFloat);
+                    }
+                    opts.push_tag(s, "F64Hex");
+                    push_hex(s, &f.to_be_bytes());
+                }
+            },
+            FogValueRef::Bin(b) => {
+                if opts.bin_omit_max.is_some_and(|max| b.len() > max) {
+                    use std::fmt::Write;
+                    opts.push_tag(s, "BinOmitted");
+                    write!(s, "{},", b.len()).unwrap();
+                    self.scratch.push_base58(s, fog_pack::types::Hash::new(b).as_ref());
+                }
+                else if opts.bin_array_max.is_some_and(|max| b.len() <= max) {
+                    let array = JsonValue::Array(b.iter().map(|v| JsonValue::from(*v)).collect());
+                    if opts.bin_array_wrapped {
+                        let mut obj = JsonMap::new();
+                        obj.insert(format!("{}Bin", opts.prefix()), array);
+                        return Some(JsonValue::Object(obj));
+                    }
+                    return Some(array);
+                }
+                else if opts.bin_hex_max.is_some_and(|max| b.len() <= max) {
+                    opts.push_tag(s, "Hex");
+                    push_hex(s, b);
+                }
+                else if opts.bin_hexdump {
+                    return Some(JsonValue::Array(hexdump(b)));
+                }
+                else {
+                    opts.push_tag(s, "Bin");
+                    self.base64(b, s);
+                }
+            },
+            FogValueRef::Array(_) | FogValueRef::Map(_) => unreachable!("Containers are converted by value()"),
+            FogValueRef::Hash(v) => {
+                opts.push_tag(s, "Hash");
+                if opts.multibase { s.push(MULTIBASE_BASE58BTC); }
+                self.scratch.push_base58(s, v.as_ref());
+            },
+            FogValueRef::Identity(v) => {
+                opts.push_tag(s, "Identity");
+                if opts.multibase { s.push(MULTIBASE_BASE58BTC); }
+                self.scratch.push_base58_with(s, |buf| v.encode_vec(buf));
+                if let Some(name) = opts.identity_names.as_ref().and_then(|r| r.identity_name(v)) {
+                    s.push_str(" (");
+                    s.push_str(&name);
+                    s.push(')');
+                }
+            },
+            FogValueRef::StreamId(v) => {
+                opts.push_tag(s, "StreamId");
+                if opts.multibase { s.push(MULTIBASE_BASE58BTC); }
+                self.scratch.push_base58_with(s, |buf| v.encode_vec(buf));
+            },
+            FogValueRef::LockId(v) => {
+                opts.push_tag(s, "LockId");
+                if opts.multibase { s.push(MULTIBASE_BASE58BTC); }
+                self.scratch.push_base58_with(s, |buf| v.encode_vec(buf));
+            },
+            FogValueRef::DataLockbox(v) => self.lockbox("DataLockbox", v.as_bytes(), s),
+            FogValueRef::IdentityLockbox(v) => self.lockbox("IdentityLockbox", v.as_bytes(), s),
+            FogValueRef::StreamLockbox(v) => self.lockbox("StreamLockbox", v.as_bytes(), s),
+            FogValueRef::LockLockbox(v) => self.lockbox("LockLockbox", v.as_bytes(), s),
+            FogValueRef::Timestamp(t) => {
+                use chrono::{offset::TimeZone, Datelike};
+                use std::fmt::Write;
+                let sec = t.timestamp_utc();
+                let nano = t.timestamp_subsec_nanos();
+                let formatted = match &opts.time_format {
+                    TimeFormat::Rfc3339 => {
+                        // RFC 3339 only covers years 0000-9999, and chrono can't represent every 
+                        // fog-pack timestamp, so fall back to the raw form for anything else
+                        chrono::Utc.timestamp_opt(sec, nano)
+                            .single()
+                            .filter(|time| (0..=9999).contains(&time.year()))
+                            .map(|time| {
+                                let format = if opts.time_fixed_precision {
+                                    chrono::SecondsFormat::Nanos
+                                } else {
+                                    chrono::SecondsFormat::AutoSi
+                                };
+                                time.to_rfc3339_opts(format, true)
+                            })
+                    },
+                    TimeFormat::Epoch => epoch_time(sec, nano, opts.time_fixed_precision),
+                    TimeFormat::Custom(format) => {
+                        // Invalid format strings fail when written, so fall back to the raw form
+                        chrono::Utc.timestamp_opt(sec, nano).single().and_then(|time| {
+                            let mut s = String::new();
+                            write!(s, "{}", time.format(format.as_str())).ok().map(|_| s)
+                        })
+                    },
+                };
+                if let Some(t) = formatted {
+                    opts.push_tag(s, "Time");
+                    s.push_str(&t);
+                }
+                else {
+                    self.warnings.push(WarningKind::RawTime);
+                    opts.push_tag(s, "TimeRaw");
+                    write!(s, "{},{}", sec, nano).unwrap();
+                }
             }
-            JsonValue::Object(obj)
-        }
-        FogValueRef::Array(array) => {
-            let array: Vec<JsonValue> = array.iter().map(fogref_to_json).collect();
-            JsonValue::Array(array)
-        },
-        FogValueRef::Hash(v) => {
-            let mut s = String::from("$fog-Hash:");
-            let v = v.to_base58();
-            s.push_str(&v);
-            JsonValue::String(s)
-        },
-        FogValueRef::Identity(v) => {
-            let mut s = String::from("$fog-Identity:");
-            let v = v.to_base58();
-            s.push_str(&v);
-            JsonValue::String(s)
-        },
-        FogValueRef::StreamId(v) => {
-            let mut s = String::from("$fog-StreamId:");
-            let v = v.to_base58();
-            s.push_str(&v);
-            JsonValue::String(s)
-        },
-        FogValueRef::LockId(v) => {
-            let mut s = String::from("$fog-LockId:");
-            let v = v.to_base58();
-            s.push_str(&v);
-            JsonValue::String(s)
-        },
-        FogValueRef::DataLockbox(v) => {
-            let mut s = String::from("$fog-DataLockbox:");
-            base64_encode(v.as_bytes(), &mut s);
-            JsonValue::String(s)
-        },
-        FogValueRef::IdentityLockbox(v) => {
-            let mut s = String::from("$fog-IdentityLockbox:");
-            base64_encode(v.as_bytes(), &mut s);
-            JsonValue::String(s)
-        },
-        FogValueRef::StreamLockbox(v) => {
-            let mut s = String::from("$fog-StreamLockbox:");
-            base64_encode(v.as_bytes(), &mut s);
-            JsonValue::String(s)
-        },
-        FogValueRef::LockLockbox(v) => {
-            let mut s = String::from("$fog-LockLockbox:");
-            base64_encode(v.as_bytes(), &mut s);
-            JsonValue::String(s)
-        },
-        FogValueRef::Timestamp(t) => {
-            use chrono::offset::TimeZone;
-            let mut s = String::from("$fog-Time:");
-            let time = chrono::Utc.timestamp_opt(
-                t.timestamp_utc(), t.timestamp_subsec_nanos()
-            ).unwrap();
-            let t = time.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true);
-            s.push_str(&t);
-            JsonValue::String(s)
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bin_as_array() {
+        let opts = EncodeOptions::new().bin_as_array(4);
+        let short = FogValue::Bin(vec![0, 1, 2, 255]);
+        let long = FogValue::Bin(vec![0, 1, 2, 3, 4]);
+        assert_eq!(fog_to_json_with_options(&short, &opts), serde_json::json!([0, 1, 2, 255]));
+        assert_eq!(fog_to_json_with_options(&long, &opts), serde_json::json!("$fog-Bin:AAECAwQ"));
+
+        let opts = opts.bin_array_wrapped(true);
+        let json = fog_to_json_with_options(&short, &opts);
+        assert_eq!(json, serde_json::json!({"$fog-Bin": [0, 1, 2, 255]}));
+        let dec = DecodeOptions::new().bin_wrapper(true);
+        assert_eq!(json_to_fog_with_options(&json, &dec).unwrap(), short);
+    }
+
+    #[test]
+    fn bin_as_hex() {
+        let opts = EncodeOptions::new().bin_as_hex(usize::MAX);
+        let bin = FogValue::Bin(vec![0xde, 0xad, 0xbe, 0xef]);
+        let json = fog_to_json_with_options(&bin, &opts);
+        assert_eq!(json, serde_json::json!("$fog-Hex:deadbeef"));
+        assert_eq!(json_to_fog(&json).unwrap(), bin);
+        let opts = EncodeOptions::new().bin_as_hex(2);
+        assert_eq!(fog_to_json_with_options(&bin, &opts), serde_json::json!("$fog-Bin:3q2+7w"));
+    }
+
+    #[test]
+    fn base64_url_safe() {
+        let opts = EncodeOptions::new().base64_url_safe(true);
+        let bin = FogValue::Bin(vec![0xfb, 0xff, 0xbf]);
+        let json = fog_to_json_with_options(&bin, &opts);
+        assert_eq!(json, serde_json::json!("$fog-Bin:-_-_"));
+        assert_eq!(json_to_fog(&json).unwrap(), bin);
+        assert_eq!(fog_to_json(&bin), serde_json::json!("$fog-Bin:+/+/"));
+    }
+
+    #[test]
+    fn base64_padding() {
+        let opts = EncodeOptions::new().base64_padding(true);
+        let bin = FogValue::Bin(vec![0, 1, 2, 3, 4]);
+        let json = fog_to_json_with_options(&bin, &opts);
+        assert_eq!(json, serde_json::json!("$fog-Bin:AAECAwQ="));
+        assert_eq!(json_to_fog(&json).unwrap(), bin);
+    }
+
+    #[test]
+    fn bin_omit_over() {
+        let opts = EncodeOptions::new().bin_omit_over(4);
+        let short = FogValue::Bin(vec![0, 1, 2, 3]);
+        let long = FogValue::Bin(vec![0, 1, 2, 3, 4]);
+        assert_eq!(fog_to_json_with_options(&short, &opts), serde_json::json!("$fog-Bin:AAECAw"));
+        let json = fog_to_json_with_options(&long, &opts);
+        let hash = fog_pack::types::Hash::new([0, 1, 2, 3, 4]).to_base58();
+        assert_eq!(json, JsonValue::String(format!("$fog-BinOmitted:5,{}", hash)));
+        assert!(matches!(json_to_fog(&json), Err(DecodeError::OmittedBin { len: 5 })));
+    }
+
+    #[test]
+    fn int_annotate() {
+        let opts = EncodeOptions::new().int_annotate(true);
+        for i in [FogValue::from(5u8), FogValue::from(-12i64), FogValue::from(u64::MAX)] {
+            let json = fog_to_json_with_options(&i, &opts);
+            assert!(json.as_str().unwrap().starts_with("$fog-Int:"));
+            assert_eq!(json_to_fog(&json).unwrap(), i);
+        }
+    }
+
+    #[test]
+    fn int_annotate_unsafe() {
+        let opts = EncodeOptions::new().int_annotate_unsafe(true);
+        let safe = (1i64 << 53) - 1;
+        let cases = [
+            (FogValue::from(safe), serde_json::json!(safe)),
+            (FogValue::from(-safe), serde_json::json!(-safe)),
+            (FogValue::from(safe + 1), serde_json::json!("$fog-Int:9007199254740992")),
+            (FogValue::from(-safe - 1), serde_json::json!("$fog-Int:-9007199254740992")),
+        ];
+        for (val, expected) in cases.iter() {
+            assert_eq!(&fog_to_json_with_options(val, &opts), expected);
+        }
+    }
+
+    #[test]
+    fn float_hex() {
+        let opts = EncodeOptions::new().float_hex(true);
+        let cases = [
+            (FogValue::F32(1.5), "$fog-F32Hex:3fc00000"),
+            (FogValue::F64(-0.0), "$fog-F64Hex:8000000000000000"),
+            (FogValue::F64(0.1), "$fog-F64Hex:3fb999999999999a"),
+        ];
+        for (val, expected) in cases.iter() {
+            let json = fog_to_json_with_options(val, &opts);
+            assert_eq!(json, serde_json::json!(expected));
+            assert_eq!(&json_to_fog(&json).unwrap(), val);
+        }
+    }
+
+    #[test]
+    fn float_keywords() {
+        let opts = EncodeOptions::new().float_keywords(true);
+        let cases = [
+            (FogValue::F32(f32::INFINITY), "$fog-F32:Infinity"),
+            (FogValue::F32(f32::NAN), "$fog-F32:NaN"),
+            (FogValue::F64(f64::NEG_INFINITY), "$fog-F64:-Infinity"),
+            (FogValue::F64(-f64::NAN), "$fog-F64Hex:fff8000000000000"),
+        ];
+        let hex = EncodeOptions::new().float_hex(true);
+        for (val, expected) in cases.iter() {
+            let json = fog_to_json_with_options(val, &opts);
+            assert_eq!(json, serde_json::json!(expected));
+            // NaN never equals itself, so compare the bits
+            let back = json_to_fog(&json).unwrap();
+            assert_eq!(fog_to_json_with_options(&back, &hex), fog_to_json_with_options(val, &hex));
+        }
+        assert!(fog_to_json_with_warnings(&FogValue::F64(f64::INFINITY), &opts).1.is_empty());
+        let opts = opts.float_hex(true);
+        assert_eq!(fog_to_json_with_options(&FogValue::F64(f64::NAN), &opts), serde_json::json!("$fog-F64Hex:7ff8000000000000"));
+    }
+
+    #[test]
+    fn float_precision() {
+        let opts = EncodeOptions::new().float_precision(6);
+        let cases = [
+            (FogValue::F64(0.3), serde_json::json!(0.3)),
+            (FogValue::F64(0.1 + 0.2), serde_json::json!(0.3)),
+            (FogValue::F64(1234567.0), serde_json::json!(1234570.0)),
+            (FogValue::F32(0.25), serde_json::json!("$fog-F32:0.25")),
+            (FogValue::F32(1.0e-7 + 1.0), serde_json::json!("$fog-F32:1.0")),
+            (FogValue::F32(f32::MAX), serde_json::json!("$fog-F32:3.40282e38")),
+            (FogValue::F64(f64::INFINITY), serde_json::json!("$fog-F64Hex:7ff0000000000000")),
+        ];
+        for (val, expected) in cases.iter() {
+            assert_eq!(&fog_to_json_with_options(val, &opts), expected);
+        }
+        let (_, warnings) = fog_to_json_with_warnings(&FogValue::F64(0.1 + 0.2), &opts);
+        assert_eq!(warnings[0].kind, WarningKind::RoundedFloat);
+        let plain = opts.clone().f32_plain(true);
+        assert_eq!(fog_to_json_with_options(&FogValue::F32(1.1), &plain), serde_json::json!(1.1));
+
+        let opts = opts.float_precision_lossless(true);
+        let cases = [
+            (FogValue::F64(0.3), serde_json::json!(0.3)),
+            (FogValue::F64(0.1 + 0.2), serde_json::json!("$fog-F64Hex:3fd3333333333334")),
+            (FogValue::F32(0.25), serde_json::json!("$fog-F32:0.25")),
+            (FogValue::F32(1.0e-7 + 1.0), serde_json::json!("$fog-F32Hex:3f800001")),
+        ];
+        for (val, expected) in cases.iter() {
+            let json = fog_to_json_with_options(val, &opts);
+            assert_eq!(&json, expected);
+            assert_eq!(&json_to_fog(&json).unwrap(), val);
+        }
+    }
+
+    #[test]
+    fn f32_plain() {
+        let opts = EncodeOptions::new().f32_plain(true);
+        assert_eq!(fog_to_json_with_options(&FogValue::F32(1.1), &opts), serde_json::json!(1.1));
+        assert_eq!(fog_to_json_with_options(&FogValue::F32(f32::NAN), &opts), serde_json::json!("$fog-F32Hex:7fc00000"));
+    }
+
+    #[test]
+    fn multibase() {
+        let opts = EncodeOptions::new().multibase(true);
+        let hash = fog_pack::types::Hash::new(b"multibase");
+        let val = FogValue::Hash(hash.clone());
+        let json = fog_to_json_with_options(&val, &opts);
+        assert_eq!(json, JsonValue::String(format!("$fog-Hash:z{}", hash.to_base58())));
+        assert_eq!(json_to_fog(&json).unwrap(), val);
+        assert_eq!(json_to_fog(&fog_to_json(&val)).unwrap(), val);
+    }
+
+    #[test]
+    fn type_prefix() {
+        let enc = EncodeOptions::new().type_prefix("@fog-");
+        let dec = DecodeOptions::new().type_prefix("@fog-");
+        let val = FogValue::Array(vec![
+            FogValue::Bin(vec![0, 1, 2]),
+            FogValue::Str("@fog-looks typed".into()),
+            FogValue::Str("$fog-not special".into()),
+        ]);
+        let json = fog_to_json_with_options(&val, &enc);
+        let expected = serde_json::json!(["@fog-Bin:AAEC", "@fog-Str:@fog-looks typed", "$fog-not special"]);
+        assert_eq!(json, expected);
+        assert_eq!(json_to_fog_with_options(&json, &dec).unwrap(), val);
+    }
+
+    #[test]
+    fn redact_lockboxes() {
+        let key = fog_crypto::stream::StreamKey::new_temp(&mut rand::rngs::OsRng);
+        let lockbox = key.encrypt_data(&mut rand::rngs::OsRng, b"secret");
+        let len = lockbox.as_bytes().len();
+        let val = FogValue::DataLockbox(lockbox);
+        let opts = EncodeOptions::new().redact_lockboxes(true);
+        let json = fog_to_json_with_options(&val, &opts);
+        assert_eq!(json, JsonValue::String(format!("$fog-Redacted:DataLockbox,{}", len)));
+        assert!(matches!(json_to_fog(&json), Err(DecodeError::Redacted { len: l, .. }) if l == len));
+    }
+
+    #[test]
+    fn max_depth() {
+        let mut val = FogValue::Null;
+        for _ in 0..10 {
+            val = FogValue::Array(vec![val]);
+        }
+        assert!(try_fog_to_json(&val, &EncodeOptions::new().max_depth(10)).is_ok());
+        assert!(matches!(
+            try_fog_to_json(&val, &EncodeOptions::new().max_depth(9)),
+            Err(EncodeError::TooDeep(9))
+        ));
+        for _ in 0..100_000 {
+            val = FogValue::Array(vec![val]);
+        }
+        assert!(try_fog_to_json(&val, &EncodeOptions::new().max_depth(fog_pack::MAX_DEPTH)).is_err());
+
+        // Far deeper than the stack could handle if conversion recursed, and without a limit by 
+        // default
+        let opts = EncodeOptions::new();
+        let json = fog_to_json(&val);
+        let expected = "[".repeat(100_010) + "null" + &"]".repeat(100_010);
+        assert_eq!(fog_to_json_string(&val, &opts), expected);
+        crate::tests::drop_json(json);
+        crate::tests::drop_fog(val);
+    }
+
+    #[test]
+    fn warnings() {
+        let mut map = BTreeMap::new();
+        map.insert("nan".to_string(), FogValue::F64(f64::NAN));
+        map.insert("list".to_string(), FogValue::Array(vec![FogValue::F32(1.5), FogValue::F32(f32::INFINITY)]));
+        let val = FogValue::Map(map);
+        let opts = EncodeOptions::new().f32_plain(true);
+        let (json, warnings) = fog_to_json_with_warnings(&val, &opts);
+        assert_eq!(json, fog_to_json_with_options(&val, &opts));
+        let warnings: Vec<(&str, &WarningKind)> = warnings.iter().map(|w| (w.pointer.as_str(), &w.kind)).collect();
+        assert_eq!(warnings, vec![
+            ("/list/0", &WarningKind::PlainF32),
+            ("/list/1", &WarningKind::NonFiniteFloat),
+            ("/nan", &WarningKind::NonFiniteFloat),
+        ]);
+        assert!(fog_to_json_with_warnings(&val, &EncodeOptions::new().float_hex(true)).1.is_empty());
+    }
+
+    #[test]
+    fn fog_into_json() {
+        let json = serde_json::json!({
+            "list": [1, "$fog-Str:$fog-Int:5", "plain", {"inner": "$fog-F32:1.5"}],
+            "bin": "$fog-Bin:AAECAwQ",
+        });
+        let val = json_to_fog(&json).unwrap();
+        let opts = EncodeOptions::new().bin_as_hex(8);
+        let expected = fog_to_json_with_options(&val, &opts);
+        let (_, expected_stats) = fog_to_json_with_stats(&val, &opts);
+        let mut enc = Encoder::new(&opts);
+        assert_eq!(enc.value_owned(val.clone()), expected);
+        assert_eq!(enc.stats.nodes, expected_stats.nodes);
+        assert_eq!(enc.stats.annotations, expected_stats.annotations);
+        assert_eq!(super::fog_into_json(val), json);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel() {
+        let array: Vec<FogValue> = (0..5000u32)
+            .map(|i| if i % 2 == 0 { FogValue::from(i) } else { FogValue::Bin(i.to_be_bytes().to_vec()) })
+            .collect();
+        let map: BTreeMap<String, FogValue> = (0..3000u32)
+            .map(|i| (format!("key{}", i), FogValue::Str(format!("$fog-{}", i))))
+            .collect();
+        let opts = EncodeOptions::new();
+        for val in [FogValue::Array(array), FogValue::Map(map)] {
+            let (json, stats) = fog_to_json_with_stats(&val, &opts);
+            let mut enc = Encoder::new(&opts);
+            enc.depth = 1;
+            let expected = match val.as_ref() {
+                FogValueRef::Array(a) => JsonValue::Array(a.iter().map(|v| enc.value(v)).collect()),
+                FogValueRef::Map(m) => enc.map(&m),
+                _ => unreachable!(),
+            };
+            assert_eq!(json, expected);
+            assert_eq!(stats.nodes, enc.stats.nodes + 1);
+            assert_eq!(stats.annotations, enc.stats.annotations);
+            assert_eq!(json_to_fog(&json).unwrap(), val);
+        }
+    }
+
+    #[test]
+    fn bin_hexdump() {
+        let opts = EncodeOptions::new().bin_hexdump(true);
+        let bin = FogValue::Bin(b"fog-pack hexdump test".to_vec());
+        let expected = serde_json::json!([
+            "00000000  66 6f 67 2d 70 61 63 6b  20 68 65 78 64 75 6d 70  |fog-pack hexdump|",
+            "00000010  20 74 65 73 74                                    | test|",
+        ]);
+        assert_eq!(fog_to_json_with_options(&bin, &opts), expected);
+    }
+
+    #[test]
+    fn time_epoch() {
+        use fog_pack::types::Timestamp;
+        let opts = EncodeOptions::new().time_format(TimeFormat::Epoch);
+        let cases = [
+            (Timestamp::from_utc(1689183193, 0).unwrap(), "$fog-Time:1689183193"),
+            (Timestamp::from_utc(1689183193, 5).unwrap(), "$fog-Time:1689183193.000000005"),
+            (Timestamp::from_utc(-1, 500_000_000).unwrap(), "$fog-Time:-0.500000000"),
+            (Timestamp::from_utc(-2, 0).unwrap(), "$fog-Time:-2"),
+            (Timestamp::from_utc(0, 1_500_000_000).unwrap(), "$fog-TimeRaw:0,1500000000"),
+        ];
+        for (time, expected) in cases.iter() {
+            let json = fog_to_json_with_options(&FogValue::Timestamp(*time), &opts);
+            assert_eq!(json, serde_json::json!(expected));
+            assert_eq!(json_to_fog(&json).unwrap(), FogValue::Timestamp(*time));
+        }
+    }
+
+    #[test]
+    fn time_fixed_precision() {
+        use fog_pack::types::Timestamp;
+        let time = FogValue::Timestamp(Timestamp::from_utc(1689183193, 500_000_000).unwrap());
+        let opts = EncodeOptions::new().time_fixed_precision(true);
+        let json = fog_to_json_with_options(&time, &opts);
+        assert_eq!(json, serde_json::json!("$fog-Time:2023-07-12T17:33:13.500000000Z"));
+        assert_eq!(json_to_fog(&json).unwrap(), time);
+        let time = FogValue::Timestamp(Timestamp::from_utc(1689183193, 0).unwrap());
+        let opts = opts.time_format(TimeFormat::Epoch);
+        assert_eq!(fog_to_json_with_options(&time, &opts), serde_json::json!("$fog-Time:1689183193.000000000"));
+    }
+
+    #[test]
+    fn time_custom() {
+        use fog_pack::types::Timestamp;
+        let time = FogValue::Timestamp(Timestamp::from_utc(1689183193, 0).unwrap());
+        let opts = EncodeOptions::new().time_format(TimeFormat::Custom("%Y-%m-%d %H:%M:%S".into()));
+        assert_eq!(fog_to_json_with_options(&time, &opts), serde_json::json!("$fog-Time:2023-07-12 17:33:13"));
+        let opts = EncodeOptions::new().time_format(TimeFormat::Custom("%Q".into()));
+        assert_eq!(fog_to_json_with_options(&time, &opts), serde_json::json!("$fog-TimeRaw:1689183193,0"));
+    }
+
+    #[test]
+    fn time_raw() {
+        use fog_pack::types::Timestamp;
+        let cases = [
+            (Timestamp::min_value(), "$fog-TimeRaw:-9223372036854775808,0"),
+            (Timestamp::max_value(), "$fog-TimeRaw:9223372036854775807,1999999999"),
+            (Timestamp::from_utc(0, 1_500_000_000).unwrap(), "$fog-TimeRaw:0,1500000000"),
+            (Timestamp::from_utc(59, 1_500_000_000).unwrap(), "$fog-Time:1970-01-01T00:00:60.500Z"),
+        ];
+        for (time, expected) in cases {
+            let json = fog_to_json(&FogValue::Timestamp(time));
+            assert_eq!(json, JsonValue::from(expected));
+            assert_eq!(json_to_fog(&json).unwrap(), FogValue::Timestamp(time));
         }
     }
 }
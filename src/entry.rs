@@ -1,15 +1,50 @@
 use super::*;
+use std::{borrow::Borrow, collections::HashMap};
 use fog_crypto::identity::IdentityKey;
 use fog_pack::{
     types::{Hash, Identity},
     document::Document,
-    entry::NewEntry,
+    entry::{Entry, NewEntry},
+    schema::Schema,
 };
 
 // Entries require the document they come from...cool....
 // The thing to do here, it seems, is to convert the JSON into a parsed thing, then proceed through 
 // two additional states
 
+/// A store of parent [`Document`]s that a [`JsonEntry`] can be completed against, with 
+/// [`JsonEntry::complete_from`].
+///
+/// Implemented for maps from document hashes to documents, and for slices of documents.
+pub trait ParentSource {
+    /// Get the document with a given hash, if it's in the store.
+    fn get(&self, hash: &Hash) -> Option<&Document>;
+}
+
+impl ParentSource for HashMap<Hash, Document> {
+    fn get(&self, hash: &Hash) -> Option<&Document> {
+        HashMap::get(self, hash)
+    }
+}
+
+impl ParentSource for BTreeMap<Hash, Document> {
+    fn get(&self, hash: &Hash) -> Option<&Document> {
+        BTreeMap::get(self, hash)
+    }
+}
+
+impl ParentSource for [Document] {
+    fn get(&self, hash: &Hash) -> Option<&Document> {
+        self.iter().find(|doc| doc.hash() == hash)
+    }
+}
+
+impl ParentSource for Vec<Document> {
+    fn get(&self, hash: &Hash) -> Option<&Document> {
+        ParentSource::get(self.as_slice(), hash)
+    }
+}
+
 /// Partially converted JSON value that can be completed into a 
 /// [NewEntry][fog_pack::entry::NewEntry].
 ///
@@ -36,32 +71,58 @@ impl JsonEntry {
     /// It may also include:
     ///
     /// - "signer": An Identity to sign the entry with. Conversion fails if the corresponding 
-    ///     IdentityKey cannot be retrieved or used for signing.
+    ///   IdentityKey cannot be retrieved or used for signing.
     /// - "compression": Overrides the default compression settings for the entry. Can be Null or 
-    ///     0-255.
+    ///   0-255.
     ///
+    /// A `"$fog-generator"` entry, as added by [`EncodeOptions::generator`], and a `"signature"` 
+    /// entry, as added by [`EncodeOptions::signature_details`], are ignored.
     pub fn from_json(json: &JsonValue) -> Result<Self, ObjectError> {
+        Self::from_json_with_options(json, &DecodeOptions::default())
+    }
+
+    /// Parse a JSON value as part of converting it into an Entry, as with 
+    /// [`from_json`][Self::from_json], using the provided decoding options.
+    pub fn from_json_with_options(json: &JsonValue, opts: &DecodeOptions) -> Result<Self, ObjectError> {
+        Self::from_json_inner(json, &mut Decoder::new(opts))
+    }
+
+    /// Parse a JSON value as part of converting it into an Entry, as with 
+    /// [`from_json`][Self::from_json], also returning statistics about the conversion.
+    pub fn from_json_with_stats(
+        json: &JsonValue,
+        opts: &DecodeOptions
+    ) -> Result<(Self, ConvertStats), ObjectError> {
+        let start = std::time::Instant::now();
+        let mut dec = Decoder::new(opts);
+        let entry = Self::from_json_inner(json, &mut dec)?;
+        dec.stats.elapsed = start.elapsed();
+        Ok((entry, dec.stats))
+    }
+
+    fn from_json_inner(json: &JsonValue, dec: &mut Decoder) -> Result<Self, ObjectError> {
         let obj = json.as_object().ok_or(ObjectError::NotAnObject)?;
 
         // Make sure we only have fields we recognize
         for k in obj.keys() {
             match k.as_str() {
-                "data" | "signer" | "key" | "parent" | "compression" => (),
+                "data" | "signer" | "key" | "parent" | "compression" | "signature" | GENERATOR_KEY => (),
+                k if dec.opts.skip_key(k) => (),
                 k => return Err(ObjectError::UnrecognizedKey(k.to_string())),
             }
         }
 
         // Fetch & convert the required fields
-        let data = obj.get("data").ok_or_else(|| ObjectError::MissingKey("data"))?;
-        let data = json_to_fog(data).map_err(|e| ObjectError::Decode { key: "data", src: e })?;
-        let key = obj.get("key").ok_or_else(|| ObjectError::MissingKey("key"))?;
-        let key = json_to_fog(key)
+        let data = obj.get("data").ok_or(ObjectError::MissingKey("data"))?;
+        let data = dec.root(data).map_err(|e| ObjectError::Decode { key: "data", src: e })?;
+        let key = obj.get("key").ok_or(ObjectError::MissingKey("key"))?;
+        let key = dec.value(key)
             .map_err(|e| ObjectError::Decode { key: "key", src: e })?
             .as_str()
             .ok_or(ObjectError::WrongDataType("key"))?
             .to_owned();
-        let parent = obj.get("parent").ok_or_else(|| ObjectError::MissingKey("parent"))?;
-        let parent = json_to_fog(parent)
+        let parent = obj.get("parent").ok_or(ObjectError::MissingKey("parent"))?;
+        let parent = dec.value(parent)
             .map_err(|e| ObjectError::Decode { key: "parent", src: e })?
             .as_hash()
             .ok_or(ObjectError::WrongDataType("parent"))?
@@ -87,7 +148,7 @@ impl JsonEntry {
 
         // Check the optional signer field
         let signer = if let Some(s) = obj.get("signer") {
-            let s = json_to_fog(s).map_err(|e| ObjectError::Decode { key: "signer", src: e })?
+            let s = dec.value(s).map_err(|e| ObjectError::Decode { key: "signer", src: e })?
                 .as_identity()
                 .ok_or(ObjectError::WrongDataType("signer"))?
                 .to_owned();
@@ -126,6 +187,202 @@ impl JsonEntry {
         };
         Ok(ok)
     }
+
+    /// Attempt to complete the [`NewEntry`], as with [`complete`][Self::complete], looking up the 
+    /// parent [`Document`] in `source`. Fails with [`ObjectError::ParentNotFound`] if it isn't 
+    /// there.
+    pub fn complete_from<P: ParentSource + ?Sized>(self, source: &P) -> Result<MaybeEntry, ObjectError> {
+        let parent = source.get(&self.parent)
+            .ok_or_else(|| ObjectError::ParentNotFound(Box::new(self.parent.clone())))?;
+        self.complete(parent)
+    }
+
+    /// Attempt to complete the [`NewEntry`] by providing the parent [`Document`], as with 
+    /// [`complete`][Self::complete], then validate the entry against the parent's schema, 
+    /// retrieved from `schemas`, like a [`SchemaCache`].
+    ///
+    /// This catches invalid entry data immediately, instead of when the entry is later passed 
+    /// through the schema. Only the entry's own data is checked; any checks the schema requires 
+    /// against other linked documents must still be done when finishing validation.
+    pub fn complete_validated<S: SchemaSource + ?Sized>(
+        self,
+        parent: &Document,
+        schemas: &S
+    ) -> Result<MaybeEntry, ObjectError> {
+        let maybe = self.complete(parent)?;
+        let entry = match &maybe {
+            MaybeEntry::NewEntry(entry) => entry,
+            MaybeEntry::SignEntry(sign) => &sign.entry,
+        };
+        let schema = schemas.schema(entry.schema_hash())
+            .ok_or_else(|| ObjectError::SchemaNotFound(Box::new(entry.schema_hash().to_owned())))?;
+        schema.validate_new_entry(entry.clone())
+            .map_err(|src| ObjectError::Validation { key: "data", src })?;
+        Ok(maybe)
+    }
+}
+
+/// Convert JSON into a [`NewEntry`] in one step, when the parent [`Document`] is already at 
+/// hand. This parses the JSON as with [`JsonEntry::from_json`], then finishes it with 
+/// [`JsonEntry::complete`].
+///
+/// Fails with [`ObjectError::HashMismatch`] if the JSON's "parent" isn't the hash of `parent`.
+pub fn json_to_entry(json: &JsonValue, parent: &Document) -> Result<MaybeEntry, ObjectError> {
+    json_to_entry_with_options(json, parent, &DecodeOptions::default())
+}
+
+/// Convert JSON into a [`NewEntry`] in one step, as with [`json_to_entry`], using the provided 
+/// decoding options.
+pub fn json_to_entry_with_options(
+    json: &JsonValue,
+    parent: &Document,
+    opts: &DecodeOptions
+) -> Result<MaybeEntry, ObjectError> {
+    let entry = JsonEntry::from_json_with_options(json, opts)?;
+    if entry.parent() != parent.hash() {
+        return Err(ObjectError::HashMismatch {
+            expected: Box::new(entry.parent.clone()),
+            actual: Box::new(parent.hash().to_owned()),
+        });
+    }
+    entry.complete(parent)
+}
+
+/// Convert a batch of JSON values into [`NewEntry`]s in one pass, looking up each entry's parent 
+/// [`Document`] with `resolver`, as with [`json_to_entry`].
+///
+/// Results are returned in the same order as the JSON values, with failures reported per-item. 
+/// Entries whose parent can't be found fail with [`ObjectError::ParentNotFound`].
+///
+/// ```
+/// # use fog_human_json::*;
+/// # use std::collections::HashMap;
+/// # use fog_pack::{document::Document, types::Hash};
+/// let parents: HashMap<Hash, Document> = HashMap::new();
+/// let json = vec![serde_json::json!({"data": 1, "key": "item", "parent": "$fog-Hash:abc"})];
+/// let results = json_to_entries(&json, |hash| parents.get(hash));
+/// assert!(results[0].is_err());
+/// ```
+pub fn json_to_entries<'d, I, F>(jsons: I, resolver: F) -> Vec<Result<MaybeEntry, ObjectError>>
+where
+    I: IntoIterator,
+    I::Item: Borrow<JsonValue>,
+    F: FnMut(&Hash) -> Option<&'d Document>,
+{
+    json_to_entries_with_options(jsons, resolver, &DecodeOptions::default())
+}
+
+/// Convert a batch of JSON values into [`NewEntry`]s, as with [`json_to_entries`], using the 
+/// provided decoding options.
+pub fn json_to_entries_with_options<'d, I, F>(
+    jsons: I,
+    mut resolver: F,
+    opts: &DecodeOptions
+) -> Vec<Result<MaybeEntry, ObjectError>>
+where
+    I: IntoIterator,
+    I::Item: Borrow<JsonValue>,
+    F: FnMut(&Hash) -> Option<&'d Document>,
+{
+    jsons.into_iter()
+        .map(|json| {
+            let entry = JsonEntry::from_json_with_options(json.borrow(), opts)?;
+            let parent = resolver(entry.parent())
+                .ok_or_else(|| ObjectError::ParentNotFound(Box::new(entry.parent().to_owned())))?;
+            entry.complete(parent)
+        })
+        .collect()
+}
+
+/// Convert JSON into an [`Entry`] ready for storage, completing it against `parent` as with
+/// [`json_to_entry`], then validating it against `schema`, the parent's schema.
+///
+/// Validation failures are reported as [`ObjectError::Validation`], under the "data" key, or the
+/// "parent" key if the parent doesn't use the given schema. If the schema checks documents linked
+/// from the entry's data, use [`json_to_validated_entry_with_options`] to provide them. Entries
+/// that must be signed are checked right away, and validated again once signed by
+/// [`SignValidEntry::complete`].
+pub fn json_to_validated_entry<'s>(
+    json: &JsonValue,
+    parent: &Document,
+    schema: &'s Schema
+) -> Result<MaybeValidEntry<'s>, ObjectError> {
+    json_to_validated_entry_with_options(json, parent, schema, &[][..], &DecodeOptions::default())
+}
+
+/// Convert JSON into a validated [`Entry`], as with [`json_to_validated_entry`], using the
+/// provided decoding options. Documents linked from the entry's data that the schema needs to
+/// check are looked up in `linked`.
+pub fn json_to_validated_entry_with_options<'s, P: ParentSource + ?Sized>(
+    json: &JsonValue,
+    parent: &Document,
+    schema: &'s Schema,
+    linked: &P,
+    opts: &DecodeOptions
+) -> Result<MaybeValidEntry<'s>, ObjectError> {
+    match json_to_entry_with_options(json, parent, opts)? {
+        MaybeEntry::NewEntry(entry) => {
+            let entry = validate_entry(entry, schema, linked, |_| ())?;
+            Ok(MaybeValidEntry::Entry(entry))
+        },
+        MaybeEntry::SignEntry(sign) => {
+            let mut needed = Vec::new();
+            validate_entry(sign.entry.clone(), schema, linked, |doc| needed.push(doc.clone()))?;
+            Ok(MaybeValidEntry::SignEntry(SignValidEntry { sign, schema, linked: needed }))
+        },
+    }
+}
+
+/// Validate a completed entry against its parent's schema, checking any linked documents the
+/// schema requires with documents from `linked`, which are passed to `used` once checked.
+fn validate_entry<P: ParentSource + ?Sized>(
+    entry: NewEntry,
+    schema: &Schema,
+    linked: &P,
+    mut used: impl FnMut(&Document)
+) -> Result<Entry, ObjectError> {
+    let key = if entry.schema_hash() == schema.hash() { "data" } else { "parent" };
+    let fail = |src| ObjectError::Validation { key, src };
+    let mut checklist = schema.validate_new_entry(entry).map_err(fail)?;
+    for (hash, item) in checklist.iter() {
+        let doc = linked.get(&hash).ok_or_else(|| {
+            let msg = format!("Linked document {} needed for validation wasn't found", hash);
+            fail(fog_pack::error::Error::FailValidate(msg))
+        })?;
+        item.check(doc).map_err(fail)?;
+        used(doc);
+    }
+    checklist.complete().map_err(fail)
+}
+
+/// An [`Entry`] that has been validated, or that must be signed before it can be.
+// Either way this holds a whole entry, so there's little to gain from boxing.
+#[allow(clippy::large_enum_variant)]
+pub enum MaybeValidEntry<'s> {
+    /// A validated [`Entry`]
+    Entry(Entry),
+    /// An entry that must first be signed
+    SignEntry(SignValidEntry<'s>),
+}
+
+/// A [`SignEntry`] that is validated once it's signed. Complete it by finding the appropriate
+/// [`IdentityKey`][IdentityKey] and calling [`complete`][SignValidEntry::complete].
+pub struct SignValidEntry<'s> {
+    sign: SignEntry,
+    schema: &'s Schema,
+    linked: Vec<Document>,
+}
+
+impl<'s> SignValidEntry<'s> {
+    /// Get the Identity that should sign this.
+    pub fn signer(&self) -> &Identity {
+        self.sign.signer()
+    }
+
+    /// Attempt to sign the Entry, then validate it and complete it.
+    pub fn complete(self, key: &IdentityKey) -> Result<Entry, ObjectError> {
+        validate_entry(self.sign.complete(key)?, self.schema, &self.linked, |_| ())
+    }
 }
 
 /// A [`NewEntry`] that may still require signing.
@@ -136,6 +393,42 @@ pub enum MaybeEntry {
     SignEntry(SignEntry),
 }
 
+impl MaybeEntry {
+    /// Complete the [`NewEntry`], signing it first if needed with the matching key from `keys`. 
+    /// Fails with [`ObjectError::KeyNotFound`] if the key isn't available.
+    pub fn complete_with<K: KeySource + ?Sized>(self, keys: &K) -> Result<NewEntry, ObjectError> {
+        match self {
+            MaybeEntry::NewEntry(entry) => Ok(entry),
+            MaybeEntry::SignEntry(sign) => {
+                let key = doc::find_key(keys, sign.signer())?;
+                sign.complete(&key)
+            },
+        }
+    }
+
+    /// Complete the [`NewEntry`], signing it first if needed with whichever of `keys` matches the 
+    /// signer. Fails with [`ObjectError::KeyNotFound`], naming the required Identity, if none of 
+    /// them do.
+    pub fn complete_with_keys(self, keys: &[IdentityKey]) -> Result<NewEntry, ObjectError> {
+        self.complete_with(keys)
+    }
+}
+
+/// Sign a batch of [`SignEntry`]s with keys from `keys`, completing each as with 
+/// [`SignEntry::complete`].
+///
+/// Results are returned in the same order as the entries, with failures reported per-item. 
+/// Entries whose key isn't available fail with [`ObjectError::KeyNotFound`].
+pub fn sign_entries<I, K>(entries: I, keys: &K) -> Vec<Result<NewEntry, ObjectError>>
+where
+    I: IntoIterator<Item = SignEntry>,
+    K: KeySource + ?Sized,
+{
+    entries.into_iter()
+        .map(|sign| MaybeEntry::SignEntry(sign).complete_with(keys))
+        .collect()
+}
+
 /// An almost completed [`NewEntry`]. Complete it by finding the appropriate 
 /// [`IdentityKey`][IdentityKey] and calling 
 /// [`complete`][SignEntry::complete].
@@ -160,6 +453,14 @@ impl SignEntry {
             Ok(self.entry.sign(key)?)
         }
     }
+
+    /// Attempt to sign the Entry with an [`AsyncSigner`] and complete it, as with 
+    /// [`complete`][Self::complete].
+    #[cfg(feature = "async_signing")]
+    pub async fn complete_async<S: AsyncSigner + ?Sized>(self, signer: &S) -> Result<NewEntry, ObjectError> {
+        let key = sign::presigned_key(signer, &self.signer, self.entry.hash()).await?;
+        self.complete(&key)
+    }
 }
 
 
@@ -171,17 +472,316 @@ impl SignEntry {
 /// - "key": The entry's key, as a string
 /// - "parent": The Hash of the parent document
 ///
-/// It may also include a "signer" key, containing the Identity that signed the entry.
+/// It may also include a "signer" key, containing the Identity that signed the entry, and a 
+/// "signature" key with details about the signature, if enabled with 
+/// [`EncodeOptions::signature_details`].
+///
+/// # Panics
+///
+/// Panics if the entry's data can't be read, which can only happen if it was loaded without 
+/// validation, as with `trusted_decode_entry`. Use [`try_entry_to_json`] for entries from 
+/// untrusted storage.
 pub fn entry_to_json(entry: &fog_pack::entry::Entry) -> JsonValue {
-    let data: FogValueRef = entry.deserialize().unwrap();
+    entry_to_json_with_options(entry, &EncodeOptions::default())
+}
+
+/// Convert an [Entry][fog_pack::entry::Entry] into a JSON Value, as with [`entry_to_json`], using 
+/// the provided encoding options.
+///
+/// # Panics
+///
+/// Panics if the entry's data can't be read, or is nested deeper than 
+/// [`EncodeOptions::max_depth`]. Use [`try_entry_to_json`] to get an error instead.
+pub fn entry_to_json_with_options(entry: &fog_pack::entry::Entry, opts: &EncodeOptions) -> JsonValue {
+    Encoder::new(opts).envelope(&entry_data(entry, opts), JsonMap::new())
+}
+
+/// Convert an [Entry][fog_pack::entry::Entry] into a JSON Value, as with 
+/// [`entry_to_json_with_options`]. Fails instead of panicking if the entry's data can't be read, 
+/// or is nested deeper than [`EncodeOptions::max_depth`].
+pub fn try_entry_to_json(entry: &fog_pack::entry::Entry, opts: &EncodeOptions) -> Result<JsonValue, EncodeError> {
+    Encoder::new(opts).try_envelope(&entry_to_fogref(entry, opts)?, JsonMap::new())
+}
+
+/// Convert an [Entry][fog_pack::entry::Entry] into a JSON Value, as with [`entry_to_json`], also 
+/// returning statistics about the conversion.
+pub fn entry_to_json_with_stats(
+    entry: &fog_pack::entry::Entry,
+    opts: &EncodeOptions
+) -> (JsonValue, ConvertStats) {
+    let start = std::time::Instant::now();
+    let mut enc = Encoder::new(opts);
+    let json = enc.envelope(&entry_data(entry, opts), JsonMap::new());
+    enc.stats.elapsed = start.elapsed();
+    (json, enc.stats)
+}
+
+/// Convert an [Entry][fog_pack::entry::Entry] into compact JSON text, as with 
+/// [`entry_to_json_with_options`], writing it straight to `writer` without building the JSON 
+/// value first.
+///
+/// Data that can't be read, or is nested deeper than [`EncodeOptions::max_depth`], is reported as 
+/// an [`InvalidData`][std::io::ErrorKind::InvalidData] error. The depth limit is only found while 
+/// writing, so the output may be left incomplete.
+pub fn entry_to_json_writer<W: std::io::Write>(
+    entry: &fog_pack::entry::Entry,
+    opts: &EncodeOptions,
+    mut writer: W
+) -> std::io::Result<()> {
+    let data = entry_to_fogref(entry, opts).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+    Encoder::new(opts).write_envelope(&mut writer, &data, JsonMap::new())
+}
+
+fn entry_data<'a>(entry: &'a fog_pack::entry::Entry, opts: &EncodeOptions) -> FogValueRef<'a> {
+    entry_to_fogref(entry, opts).unwrap_or_else(|err| panic!("{}; use try_entry_to_json to handle this", err))
+}
+
+pub(crate) fn entry_to_fogref<'a>(
+    entry: &'a fog_pack::entry::Entry,
+    opts: &EncodeOptions
+) -> Result<FogValueRef<'a>, EncodeError> {
+    // Validated entries always deserialize, but trusted decoding skips validation
+    let data: FogValueRef = entry.deserialize().map_err(EncodeError::Deserialize)?;
     let mut map: BTreeMap<&str, FogValueRef> = BTreeMap::new();
     map.insert("data", data);
     map.insert("key", FogValueRef::Str(entry.key()));
     map.insert("parent", FogValueRef::Hash(entry.parent().to_owned()));
     if let Some(signer) = entry.signer() {
         map.insert("signer", FogValueRef::Identity(signer.to_owned()));
+        if opts.signature_details_enabled() {
+            // Trusted decoding skips verifying the signature, so there's no telling if it's valid
+            map.insert("signature", enc::signature_details(signer, None));
+        }
     }
-    let entry = FogValueRef::Map(map);
-    fogref_to_json(&entry)
+    Ok(FogValueRef::Map(map))
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fog_pack::{schema::{NoSchema, Schema, SchemaBuilder}, validator::*};
+
+    #[test]
+    fn complete_validated() {
+        let schema_doc = SchemaBuilder::new(Validator::Null)
+            .entry_add("item", IntValidator::new().max(10).build(), None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = fog_pack::document::NewDocument::new(Some(schema.hash()), ()).unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+        let mut schemas = SchemaCache::new();
+        schemas.get_or_compile(&schema_doc).unwrap();
+
+        let entry = |data: u32| {
+            let json = serde_json::json!({
+                "data": data,
+                "key": "item",
+                "parent": fog_to_json(&FogValue::Hash(parent.hash().to_owned())),
+            });
+            JsonEntry::from_json(&json).unwrap()
+        };
+        assert!(entry(5).complete_validated(&parent, &schemas).is_ok());
+        assert!(matches!(
+            entry(50).complete_validated(&parent, &schemas),
+            Err(ObjectError::Validation { key: "data", .. })
+        ));
+        assert!(matches!(
+            entry(5).complete_validated(&parent, &SchemaCache::new()),
+            Err(ObjectError::SchemaNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn json_to_entry() {
+        let schema_doc = SchemaBuilder::new(StrValidator::new().build())
+            .entry_add("item", IntValidator::new().build(), None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let doc = |data: &str| {
+            let doc = fog_pack::document::NewDocument::new(Some(schema.hash()), data).unwrap();
+            schema.validate_new_doc(doc).unwrap()
+        };
+        let (parent, other) = (doc("parent"), doc("other"));
+        let json = serde_json::json!({
+            "data": 5,
+            "key": "item",
+            "parent": fog_to_json(&FogValue::Hash(parent.hash().to_owned())),
+        });
+        let MaybeEntry::NewEntry(entry) = super::json_to_entry(&json, &parent).unwrap() else {
+            panic!("entry shouldn't need signing");
+        };
+        assert_eq!(entry.parent(), parent.hash());
+        assert_eq!(entry.key(), "item");
+        assert!(matches!(super::json_to_entry(&json, &other), Err(ObjectError::HashMismatch { .. })));
+    }
+
+    #[test]
+    fn json_to_entries() {
+        let schema_doc = SchemaBuilder::new(StrValidator::new().build())
+            .entry_add("item", IntValidator::new().build(), None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parents: std::collections::HashMap<Hash, Document> = ["a", "b"].iter()
+            .map(|data| {
+                let doc = fog_pack::document::NewDocument::new(Some(schema.hash()), *data).unwrap();
+                let doc = schema.validate_new_doc(doc).unwrap();
+                (doc.hash().to_owned(), doc)
+            })
+            .collect();
+        let missing = fog_to_json(&FogValue::Hash(Hash::new(b"missing")));
+        let mut jsons: Vec<JsonValue> = parents.keys()
+            .map(|hash| serde_json::json!({
+                "data": 1,
+                "key": "item",
+                "parent": fog_to_json(&FogValue::Hash(hash.to_owned())),
+            }))
+            .collect();
+        jsons.push(serde_json::json!({"data": 1, "key": "item", "parent": missing}));
+        jsons.push(serde_json::json!({"data": 1}));
+
+        let results = super::json_to_entries(&jsons, |hash| parents.get(hash));
+        assert_eq!(results.len(), 4);
+        for (result, json) in results.iter().zip(&jsons).take(2) {
+            let Ok(MaybeEntry::NewEntry(entry)) = result else { panic!("entry should convert") };
+            assert_eq!(fog_to_json(&FogValue::Hash(entry.parent().to_owned())), json["parent"]);
+        }
+        assert!(matches!(results[2], Err(ObjectError::ParentNotFound(_))));
+        assert!(matches!(results[3], Err(ObjectError::MissingKey("key"))));
+    }
+
+    #[test]
+    fn complete_from() {
+        let schema_doc = SchemaBuilder::new(StrValidator::new().build())
+            .entry_add("item", IntValidator::new().build(), None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let doc = |data: &str| {
+            let doc = fog_pack::document::NewDocument::new(Some(schema.hash()), data).unwrap();
+            schema.validate_new_doc(doc).unwrap()
+        };
+        let docs = vec![doc("a"), doc("b")];
+        let entry = |parent: &Document| {
+            let json = serde_json::json!({
+                "data": 1,
+                "key": "item",
+                "parent": fog_to_json(&FogValue::Hash(parent.hash().to_owned())),
+            });
+            JsonEntry::from_json(&json).unwrap()
+        };
+        let map: HashMap<Hash, Document> = docs.iter().map(|d| (d.hash().to_owned(), d.clone())).collect();
+        let Ok(MaybeEntry::NewEntry(new)) = entry(&docs[1]).complete_from(&map) else {
+            panic!("entry should complete");
+        };
+        assert_eq!(new.parent(), docs[1].hash());
+        assert!(entry(&docs[0]).complete_from(&docs).is_ok());
+        assert!(matches!(
+            entry(&doc("c")).complete_from(&docs[..]),
+            Err(ObjectError::ParentNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn sign_entries() {
+        let schema_doc = SchemaBuilder::new(Validator::Null)
+            .entry_add("item", IntValidator::new().build(), None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = fog_pack::document::NewDocument::new(Some(schema.hash()), ()).unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+        let key = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let missing = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let pending = [&key, &missing].map(|signer| {
+            let json = serde_json::json!({
+                "data": 1,
+                "key": "item",
+                "parent": fog_to_json(&FogValue::Hash(parent.hash().to_owned())),
+                "signer": fog_to_json(&FogValue::Identity(signer.id().clone())),
+            });
+            match super::json_to_entry(&json, &parent).unwrap() {
+                MaybeEntry::SignEntry(sign) => sign,
+                MaybeEntry::NewEntry(_) => panic!("Entry should have needed signing"),
+            }
+        });
+
+        let results = super::sign_entries(pending, &[key.clone()][..]);
+        let entry = schema.validate_new_entry(results[0].as_ref().unwrap().clone()).unwrap();
+        let entry = entry.complete().unwrap();
+        assert_eq!(entry.signer(), Some(key.id()));
+        assert!(matches!(&results[1], Err(ObjectError::KeyNotFound(id)) if **id == *missing.id()));
+
+        let opts = EncodeOptions::new().signature_details(true);
+        let json = entry_to_json_with_options(&entry, &opts);
+        assert_eq!(json["signature"]["signer"], fog_to_json(&FogValue::Identity(key.id().clone())));
+        assert!(json["signature"].get("valid").is_none());
+    }
+
+    #[test]
+    fn json_to_validated_entry() {
+        let schema_doc = SchemaBuilder::new(Validator::Null)
+            .entry_add("item", IntValidator::new().max(10).build(), None)
+            .entry_add("link", HashValidator::new().link(StrValidator::new().build()).build(), None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = fog_pack::document::NewDocument::new(Some(schema.hash()), ()).unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+        let linked = fog_pack::document::NewDocument::new(None, "linked").unwrap();
+        let linked = NoSchema::validate_new_doc(linked).unwrap();
+        let json = |key: &str, data: JsonValue| serde_json::json!({
+            "data": data,
+            "key": key,
+            "parent": fog_to_json(&FogValue::Hash(parent.hash().to_owned())),
+        });
+
+        let Ok(MaybeValidEntry::Entry(entry)) = super::json_to_validated_entry(&json("item", 5.into()), &parent, &schema) else {
+            panic!("entry should validate");
+        };
+        assert_eq!(entry.key(), "item");
+        let err = super::json_to_validated_entry(&json("item", 50.into()), &parent, &schema);
+        assert_eq!(err.err().unwrap().pointer().as_deref(), Some("/data"));
+
+        let link = json("link", fog_to_json(&FogValue::Hash(linked.hash().to_owned())));
+        assert!(super::json_to_validated_entry(&link, &parent, &schema).is_err());
+        let opts = DecodeOptions::default();
+        let docs = vec![linked];
+        assert!(json_to_validated_entry_with_options(&link, &parent, &schema, &docs, &opts).is_ok());
+
+        let key = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let mut signed = link;
+        signed["signer"] = fog_to_json(&FogValue::Identity(key.id().clone()));
+        let result = json_to_validated_entry_with_options(&signed, &parent, &schema, &docs, &opts);
+        let Ok(MaybeValidEntry::SignEntry(sign)) = result else {
+            panic!("entry should need signing");
+        };
+        assert_eq!(sign.complete(&key).unwrap().signer(), Some(key.id()));
+    }
+
+    #[test]
+    fn try_entry_to_json() {
+        let schema_doc = SchemaBuilder::new(Validator::Null)
+            .entry_add("item", StrValidator::new().build(), None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = fog_pack::document::NewDocument::new(Some(schema.hash()), ()).unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+        let new_entry = NewEntry::new("item", &parent, "hello").unwrap().compression(None);
+        let entry = schema.validate_new_entry(new_entry).unwrap().complete().unwrap();
+        let opts = EncodeOptions::default();
+        assert_eq!(super::try_entry_to_json(&entry, &opts).unwrap(), entry_to_json(&entry));
+
+        // Corrupt the data's first marker, past the compression byte and data length
+        let hash = entry.hash().to_owned();
+        let (_, mut raw, _) = schema.encode_entry(entry).unwrap();
+        raw[3] = 0xc1;
+        let entry = schema.trusted_decode_entry(raw, "item", &parent, &hash).unwrap();
+        assert!(matches!(super::try_entry_to_json(&entry, &opts), Err(EncodeError::Deserialize(_))));
+        assert!(super::entry_to_json_writer(&entry, &opts, Vec::new()).is_err());
+    }
+}
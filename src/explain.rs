@@ -0,0 +1,206 @@
+use super::*;
+use fog_crypto::lockbox::LockboxRecipient;
+use enc::Node;
+use info::{encoded_size, fold, marker_size, type_name, Folded};
+
+/// Convert a fog-pack value into an annotated JSON view, for diagnostics.
+///
+/// Every value is wrapped in an Object with its fog-pack type name under "type" and its JSON 
+/// encoding under "value". Arrays and Maps hold wrapped values, and opaque types carry extra 
+/// metadata:
+///
+/// - Bin: "length", the number of bytes.
+/// - Hash / Identity / StreamId / LockId: "version", the cryptographic algorithm version.
+/// - Lockboxes: "version", plus "recipient" with the wrapped LockId or StreamId that can open it.
+///
+/// This is a read-only view; it can't be converted back into fog-pack.
+pub fn fog_to_json_explain(val: &FogValue) -> JsonValue {
+    explain(Node::Val(val))
+}
+
+/// Convert a fog-pack ValueRef into an annotated JSON view, as with [`fog_to_json_explain`].
+pub fn fogref_to_json_explain(val: &FogValueRef) -> JsonValue {
+    explain(Node::Ref(val))
+}
+
+fn explain(val: Node) -> JsonValue {
+    fold(val, |items| match items {
+        Folded::Leaf(val) => explain_leaf(val),
+        Folded::Array(items) => JsonValue::Object(wrap("Array", JsonValue::Array(items))),
+        Folded::Map(items) => {
+            let items = items.into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+            JsonValue::Object(wrap("Map", JsonValue::Object(items)))
+        },
+    })
+}
+
+/// Start the annotated view of a value with its type name and JSON encoding.
+fn wrap(name: &str, value: JsonValue) -> JsonMap {
+    let mut obj = JsonMap::new();
+    obj.insert("type".into(), JsonValue::from(name));
+    obj.insert("value".into(), value);
+    obj
+}
+
+fn explain_leaf(val: &FogValueRef) -> JsonValue {
+    let mut obj = wrap(type_name(val), fogref_to_json(val));
+    let version = match val {
+        FogValueRef::Bin(b) => {
+            obj.insert("length".into(), JsonValue::from(b.len()));
+            None
+        },
+        FogValueRef::Hash(v) => Some(v.version()),
+        FogValueRef::Identity(v) => Some(v.version()),
+        FogValueRef::StreamId(v) => Some(v.version()),
+        FogValueRef::LockId(v) => Some(v.version()),
+        FogValueRef::DataLockbox(v) => Some(lockbox(&mut obj, v.version(), v.recipient())),
+        FogValueRef::IdentityLockbox(v) => Some(lockbox(&mut obj, v.version(), v.recipient())),
+        FogValueRef::StreamLockbox(v) => Some(lockbox(&mut obj, v.version(), v.recipient())),
+        FogValueRef::LockLockbox(v) => Some(lockbox(&mut obj, v.version(), v.recipient())),
+        _ => None,
+    };
+    if let Some(version) = version {
+        obj.insert("version".into(), JsonValue::from(version));
+    }
+    JsonValue::Object(obj)
+}
+
+/// Convert a fog-pack value into JSON annotated with encoded sizes, for finding which parts of a 
+/// value take up the most space.
+///
+/// Every value is wrapped in an Object with its encoded fog-pack size in bytes under "size" and its 
+/// JSON encoding under "value". Arrays and Maps hold wrapped values, and their size includes 
+/// everything inside them (and for Maps, the keys). This is a read-only view; it can't be 
+/// converted back into fog-pack.
+pub fn fog_to_json_sized(val: &FogValue) -> JsonValue {
+    sized(Node::Val(val))
+}
+
+/// Convert a fog-pack ValueRef into JSON annotated with encoded sizes, as with 
+/// [`fog_to_json_sized`].
+pub fn fogref_to_json_sized(val: &FogValueRef) -> JsonValue {
+    sized(Node::Ref(val))
+}
+
+fn sized(val: Node) -> JsonValue {
+    let (json, _) = fold(val, |items| {
+        let (value, size) = match items {
+            Folded::Leaf(val) => (fogref_to_json(val), encoded_size(val)),
+            Folded::Array(items) => {
+                let mut size = marker_size(items.len(), Some(15), 255, 65535);
+                let items = items.into_iter().map(|(json, item_size)| {
+                    size += item_size;
+                    json
+                }).collect();
+                (JsonValue::Array(items), size)
+            },
+            Folded::Map(items) => {
+                let mut size = marker_size(items.len(), Some(15), 255, 65535);
+                let items = items.into_iter().map(|(k, (json, item_size))| {
+                    size += encoded_size(&FogValueRef::Str(k)) + item_size;
+                    (k.to_string(), json)
+                }).collect();
+                (JsonValue::Object(items), size)
+            },
+        };
+        let mut obj = JsonMap::new();
+        obj.insert("size".into(), JsonValue::from(size));
+        obj.insert("value".into(), value);
+        (JsonValue::Object(obj), size)
+    });
+    json
+}
+
+/// Add a lockbox's recipient, passing its version through.
+fn lockbox(obj: &mut JsonMap, version: u8, recipient: LockboxRecipient) -> u8 {
+    let recipient = match recipient {
+        LockboxRecipient::LockId(id) => FogValue::LockId(id),
+        LockboxRecipient::StreamId(id) => FogValue::StreamId(id),
+    };
+    obj.insert("recipient".into(), fog_to_json_explain(&recipient));
+    version
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn explain() {
+        let key = fog_crypto::stream::StreamKey::new_temp(&mut rand::rngs::OsRng);
+        let lockbox = key.encrypt_data(&mut rand::rngs::OsRng, b"secret");
+        let mut map = BTreeMap::new();
+        map.insert("bin".to_string(), FogValue::Bin(vec![1, 2]));
+        map.insert("list".to_string(), FogValue::Array(vec![FogValue::from(1u8)]));
+        map.insert("box".to_string(), FogValue::DataLockbox(lockbox));
+        let json = fog_to_json_explain(&FogValue::Map(map));
+
+        assert_eq!(json["type"], "Map");
+        assert_eq!(json["value"]["bin"], json!({"type": "Bin", "value": "$fog-Bin:AQI", "length": 2}));
+        assert_eq!(json["value"]["list"], json!({"type": "Array", "value": [{"type": "Int", "value": 1}]}));
+        let recipient = fog_to_json(&FogValue::StreamId(key.id().clone()));
+        assert_eq!(json["value"]["box"]["recipient"], json!({"type": "StreamId", "value": recipient, "version": 1}));
+        assert_eq!(json["value"]["box"]["version"], 1);
+    }
+
+    #[test]
+    fn explain_deep() {
+        // Far deeper than the stack could handle if walking the value recursed
+        let depth = |json: &JsonValue| {
+            let mut depth = 0;
+            let mut json = json;
+            while json["type"] != "Null" {
+                json = match &json["value"] {
+                    JsonValue::Array(a) => &a[0],
+                    map => &map["k"],
+                };
+                depth += 1;
+            }
+            depth
+        };
+        let val = crate::tests::deep_fogref(100_000);
+        let json = fogref_to_json_explain(&val);
+        assert_eq!(depth(&json), 100_000);
+        crate::tests::drop_json(json);
+        crate::tests::drop_fogref(val);
+
+        let mut val = FogValue::Null;
+        for _ in 0..100_000 {
+            val = FogValue::Array(vec![val]);
+        }
+        let json = fog_to_json_explain(&val);
+        assert_eq!(depth(&json), 100_000);
+        crate::tests::drop_json(json);
+        crate::tests::drop_fog(val);
+    }
+
+    #[test]
+    fn sized() {
+        let val = json_to_fog(&json!({"a": [1, "$fog-Bin:AAECAwQ"], "long": "x".repeat(40)})).unwrap();
+        let json = fog_to_json_sized(&val);
+        let total = encoded_size(&val.as_ref());
+        assert_eq!(json["size"], total);
+        assert_eq!(json["value"]["a"]["size"], 1 + 1 + (2 + 5));
+        assert_eq!(json["value"]["long"], json!({"size": 2 + 40, "value": "x".repeat(40)}));
+    }
+
+    #[test]
+    fn sized_deep() {
+        // Far deeper than the stack could handle if walking the value recursed
+        let val = crate::tests::deep_fogref(100_000);
+        let json = fogref_to_json_sized(&val);
+        assert_eq!(json["size"], encoded_size(&val));
+        crate::tests::drop_json(json);
+        crate::tests::drop_fogref(val);
+
+        let mut val = FogValue::Null;
+        for _ in 0..100_000 {
+            val = FogValue::Array(vec![val]);
+        }
+        let json = fog_to_json_sized(&val);
+        assert_eq!(json["size"], 100_000 + 1);
+        crate::tests::drop_json(json);
+        crate::tests::drop_fog(val);
+    }
+}
@@ -0,0 +1,249 @@
+use super::*;
+
+/// Storage for the `$fog-TYPE:` values converted by [`json_to_fogref`], which the resulting 
+/// ValueRef borrows from. It can be reused across conversions.
+#[derive(Clone, Debug, Default)]
+pub struct FogRefStorage {
+    /// Converted typed values in the order they appear, ending at the first that failed
+    typed: Vec<Result<FogValue, DecodeError>>,
+}
+
+impl FogRefStorage {
+    /// Create empty storage.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Convert a JSON Value to a fog-pack ValueRef that borrows from it.
+///
+/// Plain strings, object keys, and the structure of arrays and objects are borrowed from the 
+/// JSON. Only `$fog-TYPE:` strings and `{"$fog-Bin": ...}` objects are converted to owned values, 
+/// which are kept in `storage`. This gives the same result as [`json_to_fog`] at a fraction of 
+/// the allocations, for when the result only needs to be validated or encoded.
+///
+/// ```
+/// # use fog_human_json::*;
+/// let json = serde_json::json!({"name": "thing", "size": "$fog-Int:5"});
+/// let mut storage = FogRefStorage::new();
+/// let val = json_to_fogref(&json, &mut storage).unwrap();
+/// assert_eq!(val.to_owned(), json_to_fog(&json).unwrap());
+/// ```
+pub fn json_to_fogref<'a>(
+    val: &'a JsonValue,
+    storage: &'a mut FogRefStorage
+) -> Result<FogValueRef<'a>, DecodeError> {
+    json_to_fogref_with_options(val, &DecodeOptions::default(), storage)
+}
+
+/// Convert a JSON Value to a fog-pack ValueRef that borrows from it, as with [`json_to_fogref`], 
+/// using the provided decoding options.
+pub fn json_to_fogref_with_options<'a>(
+    val: &'a JsonValue,
+    opts: &DecodeOptions,
+    storage: &'a mut FogRefStorage
+) -> Result<FogValueRef<'a>, DecodeError> {
+    let mut dec = Decoder::new(opts);
+    storage.typed.clear();
+    convert_typed(&mut dec, val, &mut storage.typed);
+    let storage: &'a FogRefStorage = storage;
+    convert(&mut dec, val, &mut storage.typed.iter())
+}
+
+/// Convert every typed value, in the same order the second pass will reach them. Stops at the 
+/// first one that fails, as conversion won't get past it.
+fn convert_typed(dec: &mut Decoder, val: &JsonValue, out: &mut Vec<Result<FogValue, DecodeError>>) {
+    let mut stack = vec![(val, true)];
+    while let Some((val, is_root)) = stack.pop() {
+        let res = match val {
+            JsonValue::String(s) if dec.opts.strip_prefix(s).is_some() => dec.string(s),
+            JsonValue::Object(o) => match (o.len(), o.iter().next()) {
+                (1, Some((k, v))) if dec.opts.is_bin_key(k) => dec.bin_wrapper(v).map(FogValue::Bin),
+                _ => {
+                    let children = o.iter()
+                        .rev()
+                        .filter(|(k, _)| !(is_root && dec.opts.skip_key(k)))
+                        .map(|(_, v)| (v, false));
+                    stack.extend(children);
+                    continue;
+                },
+            },
+            JsonValue::Array(a) => {
+                stack.extend(a.iter().rev().map(|v| (v, false)));
+                continue;
+            },
+            _ => continue,
+        };
+        let failed = res.is_err();
+        out.push(res);
+        if failed { break; }
+    }
+}
+
+/// An array or object partway through conversion.
+enum Frame<'a> {
+    Array(std::slice::Iter<'a, JsonValue>, Vec<FogValueRef<'a>>),
+    /// Also holds the key of the value currently being converted, and whether to skip editor keys
+    Object(serde_json::map::Iter<'a>, &'a str, BTreeMap<&'a str, FogValueRef<'a>>, bool),
+}
+
+impl<'a> Frame<'a> {
+    fn next(&mut self, opts: &DecodeOptions) -> Option<&'a JsonValue> {
+        match self {
+            Frame::Array(iter, _) => iter.next(),
+            Frame::Object(iter, key, _, is_root) => {
+                let (k, v) = iter.find(|(k, _)| !(*is_root && opts.skip_key(k)))?;
+                *key = k;
+                Some(v)
+            },
+        }
+    }
+
+    fn push(&mut self, val: FogValueRef<'a>) {
+        match self {
+            Frame::Array(_, out) => out.push(val),
+            Frame::Object(_, key, out, _) => { out.insert(key, val); },
+        }
+    }
+
+    /// Wrap an error from the value currently being converted with its location.
+    fn wrap(self, err: DecodeError) -> DecodeError {
+        match self {
+            Frame::Array(_, out) => DecodeError::Array { loc: out.len(), err: Box::new(err) },
+            Frame::Object(_, key, _, _) => DecodeError::Map { key: key.to_string(), err: Box::new(err) },
+        }
+    }
+
+    fn finish(self) -> FogValueRef<'a> {
+        match self {
+            Frame::Array(_, out) => FogValueRef::Array(out),
+            Frame::Object(_, _, out, _) => FogValueRef::Map(out),
+        }
+    }
+}
+
+type TypedIter<'a> = std::slice::Iter<'a, Result<FogValue, DecodeError>>;
+
+/// Convert a value without recursing, borrowing everything but the typed values from the JSON.
+fn convert<'a>(
+    dec: &mut Decoder,
+    val: &'a JsonValue,
+    typed: &mut TypedIter<'a>
+) -> Result<FogValueRef<'a>, DecodeError> {
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut current = val;
+    loop {
+        let is_root = stack.is_empty();
+        let mut finished = start(dec, current, is_root, &mut stack, typed);
+        loop {
+            let Some(frame) = stack.last_mut() else {
+                return finished.map(|v| v.expect("Root value should be finished once the stack is empty"));
+            };
+            match finished {
+                Ok(Some(v)) => frame.push(v),
+                Ok(None) => (),
+                Err(mut err) => {
+                    while let Some(frame) = stack.pop() {
+                        err = frame.wrap(err);
+                        dec.depth -= 1;
+                    }
+                    return Err(err);
+                },
+            }
+            match frame.next(dec.opts) {
+                Some(child) => {
+                    current = child;
+                    break;
+                },
+                None => {
+                    finished = Ok(stack.pop().map(Frame::finish));
+                    dec.depth -= 1;
+                },
+            }
+        }
+    }
+}
+
+/// Start converting a value, returning it if it isn't an array or object. Arrays and objects are 
+/// pushed onto the stack instead.
+fn start<'a>(
+    dec: &mut Decoder,
+    val: &'a JsonValue,
+    is_root: bool,
+    stack: &mut Vec<Frame<'a>>,
+    typed: &mut TypedIter<'a>
+) -> Result<Option<FogValueRef<'a>>, DecodeError> {
+    dec.stats.nodes += 1;
+    match val {
+        JsonValue::String(s) if dec.opts.strip_prefix(s).is_none() => {
+            dec.add_string(s.len())?;
+            return Ok(Some(FogValueRef::Str(s)));
+        },
+        JsonValue::String(_) => return next_typed(typed).map(Some),
+        JsonValue::Array(a) => {
+            dec.check_depth()?;
+            dec.check_len(a.len())?;
+            stack.push(Frame::Array(a.iter(), Vec::with_capacity(a.len())));
+        },
+        JsonValue::Object(o) => {
+            dec.check_depth()?;
+            dec.check_len(o.len())?;
+            if let (1, Some((k, _))) = (o.len(), o.iter().next()) {
+                if dec.opts.is_bin_key(k) {
+                    return next_typed(typed).map(Some);
+                }
+            }
+            dec.add_string(o.keys().map(|k| k.len()).sum())?;
+            stack.push(Frame::Object(o.iter(), "", BTreeMap::new(), is_root));
+        },
+        _ => {
+            return Ok(Some(match dec.value_inner(val)? {
+                FogValue::Null => FogValueRef::Null,
+                FogValue::Bool(b) => FogValueRef::Bool(b),
+                FogValue::Int(i) => FogValueRef::Int(i),
+                FogValue::F64(f) => FogValueRef::F64(f),
+                _ => unreachable!("JSON scalars only convert to Null, Bool, Int, or F64"),
+            }));
+        },
+    }
+    dec.depth += 1;
+    Ok(None)
+}
+
+/// Take the next typed value from the first pass.
+fn next_typed<'a>(typed: &mut TypedIter<'a>) -> Result<FogValueRef<'a>, DecodeError> {
+    match typed.next() {
+        Some(Ok(val)) => Ok(val.as_ref()),
+        Some(Err(err)) => Err(err.clone()),
+        None => unreachable!("Every typed value should have been converted in the first pass"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn json_to_fogref() {
+        let json = json!({
+            "$schema": "x",
+            "list": [1, "$fog-Str:$fog-Int:5", "plain", {"inner": "$fog-F32:1.5"}, null, -2.5],
+            "bin": {"$fog-Bin": [0, 1, 2]},
+            "hex": "$fog-Hex:0a0b",
+        });
+        let opts = DecodeOptions::new().ignore_editor_keys(true).bin_wrapper(true);
+        let mut storage = FogRefStorage::new();
+        let val = json_to_fogref_with_options(&json, &opts, &mut storage).unwrap();
+        assert_eq!(val.to_owned(), json_to_fog_with_options(&json, &opts).unwrap());
+        let FogValueRef::Map(map) = &val else { panic!("Should have converted to a map") };
+        let FogValueRef::Array(list) = &map["list"] else { panic!("Should have converted to an array") };
+        assert!(matches!(list[2], FogValueRef::Str(s) if std::ptr::eq(s, json["list"][2].as_str().unwrap())));
+
+        let json = json!({"a": [1, "$fog-Int:x"], "b": "$fog-Int:y"});
+        let err = super::json_to_fogref(&json, &mut storage).unwrap_err();
+        let expected = json_to_fog(&json).unwrap_err();
+        assert_eq!(err.to_string(), expected.to_string());
+        assert!(matches!(err, DecodeError::Map { key, err } if key == "a" && matches!(*err, DecodeError::Array { loc: 1, .. })));
+    }
+}
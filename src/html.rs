@@ -0,0 +1,174 @@
+use fog_pack::{document::Document, entry::Entry};
+
+use super::*;
+use pretty::{FogStr, FogStyle};
+
+const STYLE: &str = "<style>\
+.fog-json{font-family:monospace;white-space:pre-wrap}\
+.fog-json ul{list-style:none;margin:0;padding-left:2em}\
+.fog-json summary{cursor:pointer}\
+.fog-json details:not([open])>summary::after{content:\"\u{2026}\" attr(data-close)}\
+.fog-key{color:#0550ae;font-weight:bold}\
+.fog-str{color:#116329}\
+.fog-num{color:#953800}\
+.fog-lit{color:#8250df}\
+.fog-tag{color:#6e7781}\
+.fog-id{color:#0a7a8a}\
+.fog-time{color:#bf3989}\
+.fog-bin{color:#cf222e}\
+.fog-copy{font-size:0.8em;margin-left:0.5em}\
+</style>";
+
+/// Render a [Document] as a collapsible, syntax-highlighted HTML fragment.
+///
+/// The document is converted with [`doc_to_json`], and each array and object can be collapsed.
+/// Hashes, Identities, StreamIds, and LockIds get a button that copies them to the clipboard. The
+/// fragment is self-contained, carrying its own `<style>` element, and is wrapped in a `<div
+/// class="fog-json">`.
+///
+/// # Panics
+///
+/// Panics if the document's data can't be read, which can only happen if it was loaded without 
+/// validation. Use [`try_doc_to_html`] to get an error instead.
+pub fn doc_to_html(doc: &Document) -> String {
+    try_doc_to_html(doc).unwrap_or_else(|err| panic!("{}; use try_doc_to_html to handle this", err))
+}
+
+/// Render a [Document] as an HTML fragment, as with [`doc_to_html`], failing instead of panicking 
+/// if the document's data can't be read.
+pub fn try_doc_to_html(doc: &Document) -> Result<String, EncodeError> {
+    Ok(json_to_html(&try_doc_to_json(doc, &EncodeOptions::default())?))
+}
+
+/// Render an [Entry] as a collapsible, syntax-highlighted HTML fragment, as with [`doc_to_html`].
+///
+/// # Panics
+///
+/// Panics if the entry's data can't be read, which can only happen if it was loaded without 
+/// validation. Use [`try_entry_to_html`] to get an error instead.
+pub fn entry_to_html(entry: &Entry) -> String {
+    try_entry_to_html(entry).unwrap_or_else(|err| panic!("{}; use try_entry_to_html to handle this", err))
+}
+
+/// Render an [Entry] as an HTML fragment, as with [`entry_to_html`], failing instead of panicking 
+/// if the entry's data can't be read.
+pub fn try_entry_to_html(entry: &Entry) -> Result<String, EncodeError> {
+    Ok(json_to_html(&try_entry_to_json(entry, &EncodeOptions::default())?))
+}
+
+fn json_to_html(json: &JsonValue) -> String {
+    let mut out = String::from("<div class=\"fog-json\">");
+    out.push_str(STYLE);
+    value(&mut out, json);
+    out.push_str("</div>");
+    out
+}
+
+fn value(out: &mut String, json: &JsonValue) {
+    match json {
+        JsonValue::Array(a) if a.is_empty() => out.push_str("[]"),
+        JsonValue::Object(o) if o.is_empty() => out.push_str("{}"),
+        JsonValue::Array(a) => {
+            out.push_str("<details open data-close=\"]\"><summary>[</summary><ul>");
+            for (i, v) in a.iter().enumerate() {
+                out.push_str("<li>");
+                value(out, v);
+                if i + 1 < a.len() { out.push(','); }
+                out.push_str("</li>");
+            }
+            out.push_str("</ul>]</details>");
+        },
+        JsonValue::Object(o) => {
+            out.push_str("<details open data-close=\"}\"><summary>{</summary><ul>");
+            for (i, (k, v)) in o.iter().enumerate() {
+                out.push_str("<li>");
+                span(out, "fog-key", &JsonValue::String(k.clone()).to_string());
+                out.push_str(": ");
+                value(out, v);
+                if i + 1 < o.len() { out.push(','); }
+                out.push_str("</li>");
+            }
+            out.push_str("</ul>}</details>");
+        },
+        JsonValue::String(s) => {
+            let Some(fog) = FogStr::parse(s) else {
+                span(out, "fog-str", &json.to_string());
+                return;
+            };
+            let class = match fog.style {
+                FogStyle::Str => "fog-str",
+                FogStyle::Id => "fog-id",
+                FogStyle::Time => "fog-time",
+                FogStyle::Number => "fog-num",
+                FogStyle::Binary => "fog-bin",
+            };
+            let (tag, rest) = fog.quoted();
+            span(out, "fog-tag", &tag);
+            span(out, class, &rest);
+            if matches!(fog.ty, "Hash" | "Identity" | "StreamId" | "LockId") {
+                out.push_str("<button class=\"fog-copy\" data-copy=\"");
+                escape(out, fog.rest);
+                out.push_str("\" onclick=\"navigator.clipboard.writeText(this.dataset.copy)\">Copy</button>");
+            }
+        },
+        JsonValue::Number(_) => span(out, "fog-num", &json.to_string()),
+        _ => span(out, "fog-lit", &json.to_string()),
+    }
+}
+
+fn span(out: &mut String, class: &str, text: &str) {
+    out.push_str("<span class=\"");
+    out.push_str(class);
+    out.push_str("\">");
+    escape(out, text);
+    out.push_str("</span>");
+}
+
+fn escape(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fog_crypto::identity::IdentityKey;
+    use fog_pack::{document::NewDocument, schema::NoSchema};
+
+    #[test]
+    fn doc_to_html() {
+        let key = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let data = serde_json::json!({"<b>": ["x", 1, true], "empty": {}});
+        let new_doc = NewDocument::new(None, json_to_fog(&data).unwrap()).unwrap().sign(&key).unwrap();
+        let doc = NoSchema::validate_new_doc(new_doc).unwrap();
+        let html = super::doc_to_html(&doc);
+        assert!(html.starts_with("<div class=\"fog-json\"><style>"));
+        assert!(html.ends_with("</details></div>"));
+        assert!(html.contains("<span class=\"fog-key\">&quot;&lt;b&gt;&quot;</span>"));
+        assert!(html.contains("<span class=\"fog-tag\">&quot;$fog-Identity:</span>"));
+        assert!(html.contains(&format!("data-copy=\"{}\"", key.id())));
+        assert!(html.contains("<span class=\"fog-key\">&quot;empty&quot;</span>: {}"));
+
+        // Corrupt the data's first marker, past the compression byte, hash length, and data length
+        let doc = NewDocument::new(None, "hello").unwrap().compression(None);
+        let (_, mut encoded) = NoSchema::encode_doc(NoSchema::validate_new_doc(doc).unwrap()).unwrap();
+        encoded[5] = 0xc1;
+        let doc = NoSchema::trusted_decode_doc(encoded).unwrap();
+        assert!(matches!(try_doc_to_html(&doc), Err(EncodeError::Deserialize(_))));
+    }
+
+    #[test]
+    fn escaped_type() {
+        let html = super::json_to_html(&serde_json::json!("$fog-\"é:x"));
+        assert!(html.contains("<span class=\"fog-tag\">&quot;$fog-\\&quot;é:</span>\
+            <span class=\"fog-bin\">x&quot;</span>"));
+    }
+}
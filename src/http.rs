@@ -0,0 +1,94 @@
+/// Media type for fog-pack data converted to human-readable JSON by this crate.
+pub const CONTENT_TYPE_FOG_JSON: &str = "application/fog+json";
+
+/// Media type for raw encoded fog-pack data.
+pub const CONTENT_TYPE_FOG_PACK: &str = "application/fog-pack";
+
+/// Media type for plain JSON, which human-readable fog-pack JSON also is.
+pub const CONTENT_TYPE_JSON: &str = "application/json";
+
+/// The two wire formats an HTTP service built on fog-pack can use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ContentFormat {
+    /// Raw encoded fog-pack bytes.
+    FogPack,
+    /// fog-pack data converted to JSON by this crate.
+    FogJson,
+}
+
+impl ContentFormat {
+    /// Get the media type to use in a `Content-Type` header for this format.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ContentFormat::FogPack => CONTENT_TYPE_FOG_PACK,
+            ContentFormat::FogJson => CONTENT_TYPE_FOG_JSON,
+        }
+    }
+
+    /// Determine the format from a `Content-Type` header value. Plain `application/json` is
+    /// treated as human-readable fog-pack JSON. Returns `None` for any other media type.
+    pub fn from_content_type(header: &str) -> Option<Self> {
+        let media_type = header.split(';').next().unwrap_or("").trim();
+        if media_type.eq_ignore_ascii_case(CONTENT_TYPE_FOG_PACK) {
+            Some(ContentFormat::FogPack)
+        }
+        else if media_type.eq_ignore_ascii_case(CONTENT_TYPE_FOG_JSON)
+            || media_type.eq_ignore_ascii_case(CONTENT_TYPE_JSON)
+        {
+            Some(ContentFormat::FogJson)
+        }
+        else {
+            None
+        }
+    }
+}
+
+/// Pick a response format from an `Accept` header value.
+///
+/// Media ranges are ranked by their quality value, with more specific ranges winning ties. Plain
+/// `application/json` selects [`FogJson`][ContentFormat::FogJson], and wildcard ranges select
+/// [`FogPack`][ContentFormat::FogPack]. Returns `None` if neither format is acceptable.
+pub fn negotiate(accept: &str) -> Option<ContentFormat> {
+    let mut best: Option<(f32, u8, ContentFormat)> = None;
+    for range in accept.split(',') {
+        let mut parts = range.split(';');
+        let media_type = parts.next().unwrap_or("").trim();
+        let quality = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if quality <= 0.0 { continue; }
+        let (specificity, format) = if let Some(format) = ContentFormat::from_content_type(media_type) {
+            (2, format)
+        }
+        else if media_type == "*/*" || media_type.eq_ignore_ascii_case("application/*") {
+            (1, ContentFormat::FogPack)
+        }
+        else {
+            continue;
+        };
+        let better = best.is_none_or(|(best_q, best_s, _)| {
+            quality > best_q || (quality == best_q && specificity > best_s)
+        });
+        if better {
+            best = Some((quality, specificity, format));
+        }
+    }
+    best.map(|(_, _, format)| format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiation() {
+        assert_eq!(negotiate("application/fog+json"), Some(ContentFormat::FogJson));
+        assert_eq!(negotiate("*/*"), Some(ContentFormat::FogPack));
+        assert_eq!(negotiate("text/html, application/json;q=0.9, */*;q=0.8"), Some(ContentFormat::FogJson));
+        assert_eq!(negotiate("application/fog-pack;q=0.5, application/fog+json;q=0.6"), Some(ContentFormat::FogJson));
+        assert_eq!(negotiate("*/*, application/fog+json"), Some(ContentFormat::FogJson));
+        assert_eq!(negotiate("application/fog+json;q=0, text/plain"), None);
+        assert_eq!(ContentFormat::from_content_type("Application/Fog-Pack; charset=binary"), Some(ContentFormat::FogPack));
+    }
+}
@@ -0,0 +1,303 @@
+use super::*;
+use enc::Node;
+use fog_pack::document::Document;
+
+/// Get the fog-pack type name of a value, as used in `$fog-TYPE:` strings.
+pub(crate) fn type_name(val: &FogValueRef) -> &'static str {
+    match val {
+        FogValueRef::Null => "Null",
+        FogValueRef::Bool(_) => "Bool",
+        FogValueRef::Int(_) => "Int",
+        FogValueRef::Str(_) => "Str",
+        FogValueRef::F32(_) => "F32",
+        FogValueRef::F64(_) => "F64",
+        FogValueRef::Bin(_) => "Bin",
+        FogValueRef::Array(_) => "Array",
+        FogValueRef::Map(_) => "Map",
+        FogValueRef::Hash(_) => "Hash",
+        FogValueRef::Identity(_) => "Identity",
+        FogValueRef::StreamId(_) => "StreamId",
+        FogValueRef::LockId(_) => "LockId",
+        FogValueRef::Timestamp(_) => "Time",
+        FogValueRef::DataLockbox(_) => "DataLockbox",
+        FogValueRef::IdentityLockbox(_) => "IdentityLockbox",
+        FogValueRef::StreamLockbox(_) => "StreamLockbox",
+        FogValueRef::LockLockbox(_) => "LockLockbox",
+    }
+}
+
+/// Size of a length-prefixed element's marker, given the inclusive upper bounds of the
+/// single-byte "fix" format (if any) and the 8/16-bit formats.
+pub(crate) fn marker_size(len: usize, fix_max: Option<usize>, max8: usize, max16: usize) -> usize {
+    if fix_max.is_some_and(|max| len <= max) { 1 }
+    else if len <= max8 { 2 }
+    else if len <= max16 { 3 }
+    else { 4 }
+}
+
+/// Size of an extension type's marker and type byte.
+fn ext_size(len: usize) -> usize {
+    // Note that these are exclusive bounds, unlike the other markers
+    let marker = if len < u8::MAX as usize { 2 } else if len < u16::MAX as usize { 3 } else { 4 };
+    marker + 1 + len
+}
+
+/// A value's converted items, as passed to the function given to [`fold`].
+pub(crate) enum Folded<'a, 'v, T> {
+    /// A value that isn't an Array or Map
+    Leaf(&'a FogValueRef<'v>),
+    Array(Vec<T>),
+    Map(Vec<(&'v str, T)>),
+}
+
+/// An Array or Map partway through [`fold`], with its items folded so far.
+enum FoldFrame<'v, 'b, T> {
+    Array(std::slice::Iter<'v, FogValueRef<'b>>, Vec<T>),
+    /// Also holds the key of the item currently being folded
+    Map(std::collections::btree_map::Iter<'v, &'b str, FogValueRef<'b>>, &'v str, Vec<(&'v str, T)>),
+    ValArray(std::slice::Iter<'v, FogValue>, Vec<T>),
+    ValMap(std::collections::btree_map::Iter<'v, String, FogValue>, &'v str, Vec<(&'v str, T)>),
+}
+
+/// Fold a value bottom-up, calling `f` on each value once its items are done. This uses an 
+/// explicit stack instead of recursing, so values nested too deeply for the call stack can still 
+/// be walked.
+pub(crate) fn fold<'v, 'b, T>(val: Node<'v, 'b>, mut f: impl FnMut(Folded<'_, 'v, T>) -> T) -> T {
+    let mut stack: Vec<FoldFrame<T>> = Vec::new();
+    let mut next = val;
+    loop {
+        let mut done = match next {
+            Node::Ref(FogValueRef::Array(a)) => {
+                stack.push(FoldFrame::Array(a.iter(), Vec::with_capacity(a.len())));
+                None
+            },
+            Node::Ref(FogValueRef::Map(m)) => {
+                stack.push(FoldFrame::Map(m.iter(), "", Vec::with_capacity(m.len())));
+                None
+            },
+            Node::Val(FogValue::Array(a)) => {
+                stack.push(FoldFrame::ValArray(a.iter(), Vec::with_capacity(a.len())));
+                None
+            },
+            Node::Val(FogValue::Map(m)) => {
+                stack.push(FoldFrame::ValMap(m.iter(), "", Vec::with_capacity(m.len())));
+                None
+            },
+            Node::Ref(val) => Some(f(Folded::Leaf(val))),
+            Node::Val(val) => Some(f(Folded::Leaf(&val.as_ref()))),
+        };
+        // Finish off frames until one of them has another item to fold
+        loop {
+            let Some(frame) = stack.last_mut() else {
+                // The stack only empties once the root value is done
+                return done.unwrap();
+            };
+            let item = match frame {
+                FoldFrame::Array(iter, out) => {
+                    out.extend(done.take());
+                    iter.next().map(Node::Ref)
+                },
+                FoldFrame::Map(iter, key, out) => {
+                    out.extend(done.take().map(|v| (*key, v)));
+                    iter.next().map(|(k, v)| {
+                        *key = k;
+                        Node::Ref(v)
+                    })
+                },
+                FoldFrame::ValArray(iter, out) => {
+                    out.extend(done.take());
+                    iter.next().map(Node::Val)
+                },
+                FoldFrame::ValMap(iter, key, out) => {
+                    out.extend(done.take().map(|v| (*key, v)));
+                    iter.next().map(|(k, v)| {
+                        *key = k;
+                        Node::Val(v)
+                    })
+                },
+            };
+            if let Some(item) = item {
+                next = item;
+                break;
+            }
+            done = match stack.pop() {
+                Some(FoldFrame::Array(_, out) | FoldFrame::ValArray(_, out)) => Some(f(Folded::Array(out))),
+                Some(FoldFrame::Map(_, _, out) | FoldFrame::ValMap(_, _, out)) => Some(f(Folded::Map(out))),
+                None => unreachable!("The stack was just checked"),
+            };
+        }
+    }
+}
+
+/// Calculate how many bytes a value takes up when encoded as fog-pack.
+pub(crate) fn encoded_size(val: &FogValueRef) -> usize {
+    fold(Node::Ref(val), |items| match items {
+        Folded::Leaf(val) => leaf_size(val),
+        Folded::Array(sizes) => marker_size(sizes.len(), Some(15), 255, 65535) + sizes.iter().sum::<usize>(),
+        Folded::Map(items) => {
+            marker_size(items.len(), Some(15), 255, 65535)
+                + items.iter().map(|(k, size)| leaf_size(&FogValueRef::Str(k)) + size).sum::<usize>()
+        },
+    })
+}
+
+/// Calculate how many bytes a value that isn't an Array or Map takes up when encoded.
+fn leaf_size(val: &FogValueRef) -> usize {
+    match val {
+        FogValueRef::Null | FogValueRef::Bool(_) => 1,
+        FogValueRef::Int(i) => {
+            if let Some(v) = i.as_u64() {
+                if v <= 127 { 1 }
+                else if v <= u8::MAX as u64 { 2 }
+                else if v <= u16::MAX as u64 { 3 }
+                else if v <= u32::MAX as u64 { 5 }
+                else { 9 }
+            }
+            else {
+                let v = i.as_i64().unwrap();
+                if v >= -32 { 1 }
+                else if v >= i8::MIN as i64 { 2 }
+                else if v >= i16::MIN as i64 { 3 }
+                else if v >= i32::MIN as i64 { 5 }
+                else { 9 }
+            }
+        },
+        FogValueRef::Str(s) => marker_size(s.len(), Some(31), 255, 65535) + s.len(),
+        FogValueRef::F32(_) => 5,
+        FogValueRef::F64(_) => 9,
+        FogValueRef::Bin(b) => marker_size(b.len(), None, 255, 65535) + b.len(),
+        FogValueRef::Array(_) | FogValueRef::Map(_) => unreachable!("Only called on leaf values"),
+        FogValueRef::Hash(v) => ext_size(v.as_ref().len()),
+        FogValueRef::Identity(v) => ext_size(v.size()),
+        FogValueRef::StreamId(v) => ext_size(v.size()),
+        FogValueRef::LockId(v) => ext_size(v.size()),
+        FogValueRef::Timestamp(v) => ext_size(v.size()),
+        FogValueRef::DataLockbox(v) => ext_size(v.as_bytes().len()),
+        FogValueRef::IdentityLockbox(v) => ext_size(v.as_bytes().len()),
+        FogValueRef::StreamLockbox(v) => ext_size(v.as_bytes().len()),
+        FogValueRef::LockLockbox(v) => ext_size(v.as_bytes().len()),
+    }
+}
+
+/// Walk a value, tallying up the count of each type and returning the maximum nesting depth.
+fn tally(val: &FogValueRef, counts: &mut BTreeMap<&'static str, u64>) -> usize {
+    fold(Node::Ref(val), |items| {
+        let (name, depth) = match items {
+            Folded::Leaf(val) => (type_name(val), 0),
+            Folded::Array(depths) => ("Array", 1 + depths.into_iter().max().unwrap_or(0)),
+            Folded::Map(items) => ("Map", 1 + items.into_iter().map(|(_, depth)| depth).max().unwrap_or(0)),
+        };
+        *counts.entry(name).or_insert(0) += 1;
+        depth
+    })
+}
+
+/// Summarize a [`Document`] as a compact JSON Object, without including its data.
+///
+/// The resulting Object has the following key-value pairs:
+///
+/// - "hash": The Hash of the document.
+/// - "size": The document's total encoded size in bytes, before compression.
+/// - "data_size": The size of the encoded document data in bytes.
+/// - "depth": The maximum nesting depth of arrays and maps in the data. Zero if the data is a
+///   single non-container value.
+/// - "counts": An Object with the number of times each fog-pack type occurs in the data, keyed by
+///   type name.
+///
+/// It may also include:
+///
+/// - "schema": A fog-pack Hash of the schema used by the document.
+/// - "signer": A fog-pack Identity that signed the document.
+///
+/// # Panics
+///
+/// Panics if the document's data can't be read, which can only happen if it was loaded without 
+/// validation, as with `trusted_decode_doc`. Use [`try_doc_info_to_json`] for documents from 
+/// untrusted storage.
+pub fn doc_info_to_json(doc: &Document) -> JsonValue {
+    try_doc_info_to_json(doc)
+        .unwrap_or_else(|err| panic!("{}; use try_doc_info_to_json to handle this", err))
+}
+
+/// Summarize a [`Document`] as a compact JSON Object, as with [`doc_info_to_json`]. Fails instead 
+/// of panicking if the document's data can't be read.
+pub fn try_doc_info_to_json(doc: &Document) -> Result<JsonValue, EncodeError> {
+    let data = doc::doc_data(doc)?;
+    let mut counts = BTreeMap::new();
+    let depth = tally(&data, &mut counts);
+    let data_size = encoded_size(&data);
+
+    // Header is the compression byte, schema hash length & hash, and 3-byte data length
+    let mut size = 5 + data_size;
+    if let Some(schema) = doc.schema_hash() {
+        size += schema.as_ref().len();
+    }
+    if let Some(signer) = doc.signer() {
+        // Hash version, signer identity, and an ed25519 signature
+        size += 1 + signer.size() + 64;
+    }
+
+    let mut obj = JsonMap::new();
+    obj.insert("hash".into(), fogref_to_json(&FogValueRef::Hash(doc.hash().to_owned())));
+    obj.insert("size".into(), JsonValue::from(size));
+    obj.insert("data_size".into(), JsonValue::from(data_size));
+    obj.insert("depth".into(), JsonValue::from(depth));
+    obj.insert("counts".into(), JsonValue::Object(
+        counts.into_iter().map(|(k, v)| (k.to_owned(), JsonValue::from(v))).collect()
+    ));
+    if let Some(schema) = doc.schema_hash() {
+        obj.insert("schema".into(), fogref_to_json(&FogValueRef::Hash(schema.to_owned())));
+    }
+    if let Some(signer) = doc.signer() {
+        obj.insert("signer".into(), fogref_to_json(&FogValueRef::Identity(signer.to_owned())));
+    }
+    Ok(JsonValue::Object(obj))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fog_pack::schema::NoSchema;
+    use fog_crypto::identity::IdentityKey;
+
+    #[test]
+    fn doc_info() {
+        let mut rng = rand::thread_rng();
+        let key = IdentityKey::new_temp(&mut rng);
+        let data = serde_json::json!({
+            "a": [1, -200, 70000, "$fog-F32:1.5", "$fog-Bin:AAECAwQ"],
+            "b": { "c": "$fog-Time:2023-07-12T17:33:13Z", "d": "x".repeat(300) },
+            "e": "$fog-Int:-5000000000",
+        });
+        let data = json_to_fog(&data).unwrap();
+        let doc = fog_pack::document::NewDocument::new(None, &data).unwrap()
+            .compression(None)
+            .sign(&key).unwrap();
+        let doc = NoSchema::validate_new_doc(doc).unwrap();
+        let info = doc_info_to_json(&doc);
+        let (_, encoded) = NoSchema::encode_doc(doc).unwrap();
+        assert_eq!(info["size"], JsonValue::from(encoded.len()));
+        assert_eq!(info["depth"], JsonValue::from(2));
+        assert_eq!(info["counts"]["Map"], JsonValue::from(2));
+        assert_eq!(info["counts"]["Int"], JsonValue::from(4));
+
+        // Corrupt the data's first marker, past the compression byte, hash length, and data length
+        let doc = fog_pack::document::NewDocument::new(None, "hello").unwrap().compression(None);
+        let (_, mut encoded) = NoSchema::encode_doc(NoSchema::validate_new_doc(doc).unwrap()).unwrap();
+        encoded[5] = 0xc1;
+        let doc = NoSchema::trusted_decode_doc(encoded).unwrap();
+        assert!(matches!(try_doc_info_to_json(&doc), Err(EncodeError::Deserialize(_))));
+    }
+
+    #[test]
+    fn deep_values() {
+        // Far deeper than the stack could handle if walking the value recursed
+        let val = crate::tests::deep_fogref(100_000);
+        let mut counts = BTreeMap::new();
+        assert_eq!(tally(&val, &mut counts), 100_000);
+        assert_eq!((counts["Array"], counts["Map"], counts["Null"]), (50_000, 50_000, 1));
+        // Each Array is a marker, and each Map is a marker and a 1-byte key
+        assert_eq!(encoded_size(&val), 50_000 + 50_000 * 3 + 1);
+        crate::tests::drop_fogref(val);
+    }
+}
@@ -17,19 +17,44 @@
 //! - Str: A regular string. This is just prepended so fog-pack strings that start 
 //!   with `$fog-` won't get caught by the parser.
 //! - Bin: Encodes the binary data as Base64 using the "standard" encoding (bonus 
-//!   symbols of `+/`, no padding used, padding is accepted when parsing).
+//!   symbols of `+/`, no padding by default, padding is accepted when parsing). The URL-safe alphabet 
+//!   (`-_`) can be selected when encoding, and either alphabet is accepted when parsing. If 
+//!   enabled with [`DecodeOptions::bin_wrapper`], binary data may also be written as an object 
+//!   with a single `$fog-Bin` key when parsing, holding a Base64 string, an array of Base64 
+//!   strings that are joined together, or an array of byte values, like 
+//!   `{"$fog-Bin": [0, 1, 2, 3]}`.
+//! - Hex: Encodes binary data as lowercase hex, for easy comparison against hexdumps. Only 
+//!   written when requested through the encoding options, and parses to the same Bin value.
+//! - BinOmitted: A placeholder for binary data that was left out, holding its length and its 
+//!   fog-pack Hash, as `<length>,<hash>`. Only written when requested through the encoding 
+//!   options, and can't be parsed back into a value.
 //! - F32Hex / F64Hex: Encodes a binary32/64 IEEE floating-point value in big-endian hex. 
 //!   The fog-to-json process should only do this when writing out a NaN or 
-//!   Infinity.
+//!   Infinity. When parsing, F32 and F64 also accept the keywords `NaN`, `Infinity`, and 
+//!   `-Infinity`, like `$fog-F64:-Infinity`, which the encoding options can select for output.
 //! - F32 / F64 / Int: Prints a standard JSON Number, but includes the type 
 //!   information. This done by telling the converter to do it specifically, by a 
 //!   user adding type information, or by the converter for any F32 value (as 
-//!   `serde_json` will always use F64 for floating-point).
-//! - Time: Encodes the time as a RFC 3339 formatted string.
+//!   `serde_json` will always use F64 for floating-point). When parsing, digits may be 
+//!   grouped with `_` separators, like `$fog-Int:1_000_000`.
+//! - Time: Encodes the time as a RFC 3339 formatted string. Decimal seconds since the Unix epoch 
+//!   (like `1689183193.5`) can be selected when encoding, and are also accepted when parsing.
+//! - TimeRaw: Encodes the time as `<seconds>,<nanoseconds>` since the Unix epoch. This is only used 
+//!   for times that can't be written in RFC 3339, like those outside of the years 0000-9999.
 //! - Hash / Identity / StreamId / LockId: Encodes the corresponding primitive as a 
-//!   base58 string (in the Bitcoin base58 style).
+//!   base58 string (in the Bitcoin base58 style). A multibase `z` prefix can be added 
+//!   when encoding, and is accepted when parsing.
+//!   Identities may have a name appended in parentheses, like `<base58> (alice)`, which is ignored 
+//!   when parsing.
+//! - IdentityName: An Identity given by name, which is looked up through a resolver when parsing. 
+//!   Never written when encoding.
 //! - DataLockbox / IdentityLockbox / StreamLockbox / LockLockbox: Encodes the 
 //!   corresponding lockbox as Base64 data, just like with the "Bin" type.
+//! - Redacted: A marker for a redacted lockbox, holding its type and length, as `<type>,<length>`. 
+//!   Only written when requested through the encoding options, and can't be parsed back into a value.
+//! 
+//! When parsing, a few aliases for the type names are also accepted, like `Binary` for `Bin`, 
+//! `Timestamp` for `Time`, and `Integer` for `Int`.
 //! 
 //! That covers conversion between fog-pack Values and JSON values, but not 
 //! Documents and Entries. Those are converted into JSON objects with the following 
@@ -37,16 +62,24 @@
 //! 
 //! - Documents:
 //!   - "schema": If present, a `$fog-Hash:HASH` with the schema.
+//!   - "schema_name": If present, a human-friendly name for the schema. This is only written if a 
+//!     resolver is provided, and can be resolved back into the schema hash when parsing.
 //!   - "signer": If present, a `$fog-Identity:IDENTITY` with the signer's 
 //!     Identity. 
 //!   - "compression": If not present, uses default compression. If present and 
 //!     null, no compression is used. If set to a number between 0-255, uses that 
 //!     as the compression level.
 //!   - "data": The document content. Must be present.
+//!   - "hash": If present, the document's own hash. This is only written if requested, and is 
+//!     checked against the converted document.
+//!   - "signature": If present, details about the signature, including whether it's valid. This 
+//!     is only written if requested, and is ignored when parsing.
 //! - Entries:
 //!   - "parent": Parent document's hash.
 //!   - "key": Entry's string key.
 //!   - "signer": If present, holds the signer's Identity.
+//!   - "signature": If present, details about the signature. This is only written if requested, 
+//!     and is ignored when parsing.
 //!   - "compression": If not present, uses default compression. If present and 
 //!     null, no compression is used. If set to a number between 0 & 255, uses that 
 //!     as the compression level.
@@ -56,6 +89,29 @@
 //! struct will be provided that must be signed by a 
 //! [`IdentityKey`][fog_crypto::identity::IdentityKey] that matches the signer.
 //!
+//! fog-pack maps always hold their keys in lexicographic order, so there's no insertion order to 
+//! preserve: JSON objects are always written with sorted keys, which is also the canonical order. 
+//! This holds even if `serde_json`'s `preserve_order` feature is enabled. Converting JSON back 
+//! into fog-pack sorts the keys again; [`key_order_report`] shows which objects that will 
+//! reorder.
+//!
+//! Enabling this crate's `preserve_order` feature turns on `serde_json`'s `preserve_order`, so 
+//! JSON parsed from text (including by [`json_str_to_fog`]) keeps its original key order. Keys 
+//! are only sorted into fog-pack order when the JSON is converted into fog-pack, so a JSON 
+//! document can be edited and written back out without its keys moving around.
+//!
+//! Enabling the `arbitrary_precision` feature turns on `serde_json`'s `arbitrary_precision`, so 
+//! numbers keep every digit they were written with. Integer literals too big for a fog-pack Int 
+//! then fail to convert, instead of quietly being rounded to the nearest F64.
+//!
+//! Enabling the `json5` feature adds `json5_to_fog` and `json5_to_doc`, which accept 
+//! [JSON5](https://json5.org) text, with its comments, trailing commas, and unquoted keys, for 
+//! documents that are written and maintained by hand.
+//!
+//! Enabling the `async_signing` feature adds `SignDocument::complete_async` and 
+//! `SignEntry::complete_async`, which sign through an `AsyncSigner`, like a remote signing 
+//! service, without blocking an async runtime.
+//!
 //! As an example, let's take a struct that looks the one below, put it into a document, and look 
 //! at the resulting JSON:
 //!
@@ -153,32 +209,97 @@ type JsonValue = serde_json::Value;
 type JsonNumber = serde_json::Number;
 type JsonMap = serde_json::Map<String, JsonValue>;
 const FOG_PREFIX: &str = "$fog-";
+const GENERATOR_KEY: &str = "$fog-generator";
 
 mod enc;
 mod dec;
 mod doc;
 mod entry;
 mod query;
+mod bundle;
+mod order;
+mod info;
+mod stats;
+mod schema;
+mod http;
+mod stream;
+mod resolve;
+mod explain;
+mod pretty;
+mod html;
+mod markdown;
+mod write;
+mod text;
+mod fogref;
+mod warn;
+mod lint;
+mod value;
+#[cfg(feature = "ansi")]
+mod ansi;
+#[cfg(feature = "async_signing")]
+mod sign;
 
 use std::collections::BTreeMap;
+use dec::Decoder;
+use enc::Encoder;
+use warn::Warnings;
 
-pub use enc::{fog_to_json, fogref_to_json};
-pub use dec::{json_to_fog, DecodeError};
+pub use enc::{
+    fog_to_json, fogref_to_json, fog_to_json_with_options, fogref_to_json_with_options,
+    fog_to_json_with_stats, fogref_to_json_with_stats, try_fog_to_json, try_fogref_to_json,
+    fog_into_json, fog_into_json_with_options, fog_to_json_with_warnings, fogref_to_json_with_warnings,
+    EncodeError, EncodeOptions, TimeFormat,
+};
+pub use dec::{
+    json_to_fog, json_to_fog_with_options, json_to_fog_with_stats, json_to_fog_with_warnings, json_into_fog,
+    json_into_fog_with_options, DecodeError, DecodeOptions,
+};
+pub use warn::{Warning, WarningKind};
+pub use lint::{lint_json, lint_json_with_options, Lint, LintKind};
 pub use doc::*;
 pub use entry::*;
 pub use query::*;
+pub use bundle::*;
+pub use order::*;
+pub use info::{doc_info_to_json, try_doc_info_to_json};
+pub use stats::ConvertStats;
+pub use schema::*;
+pub use http::*;
+pub use stream::*;
+pub use resolve::*;
+pub use explain::*;
+pub use pretty::fog_pretty_string;
+pub use html::*;
+pub use markdown::{doc_to_markdown, try_doc_to_markdown};
+pub use write::*;
+pub use fogref::{json_to_fogref, json_to_fogref_with_options, FogRefStorage};
+pub use text::{
+    json_str_to_fog, json_str_to_fog_with_options, json_reader_to_fog, json_reader_to_fog_with_options,
+    jsonc_to_fog, jsonc_to_fog_with_options, strip_json_comments,
+};
+#[cfg(feature = "json5")]
+pub use text::{json5_to_fog, json5_to_fog_with_options};
+#[cfg(feature = "ansi")]
+pub use ansi::render_ansi;
+#[cfg(feature = "async_signing")]
+pub use sign::AsyncSigner;
 
 /// An error that occurred while converting from JSON to a fog-pack object, like a Document or 
 /// Entry.
 #[derive(Clone, Debug, Error)]
 pub enum ObjectError {
-    /// Data conversion failed for a particular key-value pair in the object
-    #[error("Data conversion failed for key {key}")]
+    /// Data conversion failed for a particular key-value pair in the object. The message includes 
+    /// a JSON Pointer to the failing value, which is also available from 
+    /// [`pointer`][ObjectError::pointer].
+    #[error("Data conversion failed at \"{}\"", decode_pointer(key, src))]
     Decode {
         key: &'static str,
         #[source]
         src: DecodeError,
     },
+    /// The JSON text couldn't be parsed
+    #[error("Couldn't parse the JSON text")]
+    Parse(#[source] DecodeError),
     /// Expected a different data type
     #[error("Wrong data type for key \"{0}\"")]
     WrongDataType(&'static str),
@@ -197,8 +318,94 @@ pub enum ObjectError {
     /// The provided key was incorrect
     #[error("Incorrect Identity Key for signing, needed {0}")]
     IncorrectIdentityKey(Box<fog_pack::types::Identity>),
+    /// An external signer failed to produce a valid signature
+    #[error("Signing failed")]
+    Signer(#[source] std::sync::Arc<dyn std::error::Error + Send + Sync>),
+    /// No key was available to sign as the required Identity
+    #[error("No IdentityKey available for signer {0}")]
+    KeyNotFound(Box<fog_pack::types::Identity>),
+    /// The schema needed for validation wasn't available
+    #[error("Schema {0} wasn't found")]
+    SchemaNotFound(Box<fog_pack::types::Hash>),
+    /// The parent Document of an Entry wasn't available
+    #[error("Parent document {0} wasn't found")]
+    ParentNotFound(Box<fog_pack::types::Hash>),
+    /// Conversion failed for one of the documents or entries in a bundle
+    #[error("Bundle conversion failed for {key} item {index}")]
+    BundleItem {
+        key: &'static str,
+        index: usize,
+        #[source]
+        src: Box<ObjectError>,
+    },
+    /// The converted value failed validation against its schema
+    #[error("Schema validation failed for key \"{key}\"")]
+    Validation {
+        key: &'static str,
+        #[source]
+        src: fog_pack::error::Error,
+    },
+    /// A schema's field wasn't valid, like a malformed validator. The message includes a JSON
+    /// Pointer to the failing value, which is also available from
+    /// [`pointer`][ObjectError::pointer].
+    #[error("Invalid schema at \"{path}\"")]
+    Schema {
+        path: String,
+        #[source]
+        src: fog_pack::error::Error,
+    },
+    /// The "schema_name" couldn't be resolved to a schema
+    #[error("Unknown schema name \"{0}\"")]
+    UnknownSchemaName(String),
+    /// A bundle's manifest didn't list the same number of documents or entries as the bundle 
+    /// itself
+    #[error("Bundle manifest lists {listed} {key}, but the bundle has {actual}")]
+    ManifestMismatch {
+        key: &'static str,
+        listed: usize,
+        actual: usize,
+    },
+    /// A hash recorded in the JSON, like a document's "hash" or an entry's "parent", didn't match 
+    /// the hash of the converted result or the document provided for it
+    #[error("Hash mismatch, expected {expected} but got {actual}")]
+    HashMismatch {
+        expected: Box<fog_pack::types::Hash>,
+        actual: Box<fog_pack::types::Hash>,
+    },
+}
+
+impl ObjectError {
+    /// Get an RFC 6901 JSON Pointer to the value that failed to convert, relative to the root 
+    /// Object, like `/data/items/3`. Returns `None` if the error isn't about a specific value.
+    pub fn pointer(&self) -> Option<String> {
+        match self {
+            ObjectError::Decode { key, src } => Some(decode_pointer(key, src)),
+            ObjectError::Parse(src) => Some(src.pointer()),
+            ObjectError::WrongDataType(key) | ObjectError::Validation { key, .. } => {
+                let mut pointer = String::new();
+                dec::push_pointer_segment(&mut pointer, key);
+                Some(pointer)
+            },
+            ObjectError::Schema { path, .. } => Some(path.clone()),
+            ObjectError::BundleItem { key, index, src } => {
+                Some(format!("/{}/{}{}", key, index, src.pointer().unwrap_or_default()))
+            },
+            _ => None,
+        }
+    }
 }
 
+/// JSON Pointer from the root Object to a value under `key` that failed to convert.
+fn decode_pointer(key: &str, src: &DecodeError) -> String {
+    // Errors from JSON text already have a pointer from the root
+    if matches!(src, DecodeError::Located { .. }) {
+        return src.pointer();
+    }
+    let mut pointer = String::new();
+    dec::push_pointer_segment(&mut pointer, key);
+    pointer.push_str(&src.pointer());
+    pointer
+}
 
 #[cfg(test)]
 mod tests {
@@ -207,6 +414,58 @@ mod tests {
     use fog_pack::{types::*, schema::NoSchema};
     use serde::{Deserialize, Serialize};
 
+    /// Take apart a JSON value without recursing, as dropping one nested too deeply for the stack 
+    /// would overflow it.
+    pub(crate) fn drop_json(val: JsonValue) {
+        let mut stack = vec![val];
+        while let Some(val) = stack.pop() {
+            match val {
+                JsonValue::Array(a) => stack.extend(a),
+                JsonValue::Object(o) => stack.extend(o.into_iter().map(|(_, v)| v)),
+                _ => (),
+            }
+        }
+    }
+
+    /// Take apart a fog-pack value without recursing, as with [`drop_json`].
+    pub(crate) fn drop_fog(val: FogValue) {
+        let mut stack = vec![val];
+        while let Some(val) = stack.pop() {
+            match val {
+                FogValue::Array(a) => stack.extend(a),
+                FogValue::Map(m) => stack.extend(m.into_values()),
+                _ => (),
+            }
+        }
+    }
+
+    /// Take apart a fog-pack ValueRef without recursing, as with [`drop_json`].
+    pub(crate) fn drop_fogref(val: FogValueRef) {
+        let mut stack = vec![val];
+        while let Some(val) = stack.pop() {
+            match val {
+                FogValueRef::Array(a) => stack.extend(a),
+                FogValueRef::Map(m) => stack.extend(m.into_values()),
+                _ => (),
+            }
+        }
+    }
+
+    /// Nest a value in alternating single-item Arrays and Maps, `depth` levels deep, without 
+    /// recursing.
+    pub(crate) fn deep_fogref(depth: usize) -> FogValueRef<'static> {
+        let mut val = FogValueRef::Null;
+        for i in 0..depth {
+            val = if i % 2 == 0 {
+                FogValueRef::Array(vec![val])
+            }
+            else {
+                FogValueRef::Map(BTreeMap::from([("k", val)]))
+            };
+        }
+        val
+    }
+
     #[test]
     fn back_and_forth() {
 
@@ -283,4 +542,78 @@ mod tests {
 
         assert!(roundtrip_test == test);
     }
+
+    #[test]
+    fn generator_key() {
+        let doc = fog_pack::document::NewDocument::new(None, 1u8).unwrap();
+        let doc = NoSchema::validate_new_doc(doc).unwrap();
+        let json = doc_to_json_with_options(&doc, &EncodeOptions::new().generator(true));
+        assert_eq!(json[GENERATOR_KEY]["name"], JsonValue::from("fog-human-json"));
+        let options = &json[GENERATOR_KEY]["options"];
+        assert_eq!(options["generator"], JsonValue::Bool(true));
+        assert_eq!(options["bin_as_array"], JsonValue::Null);
+        assert_eq!(options["type_prefix"], JsonValue::from("$fog-"));
+        let MaybeDocument::NewDocument(parsed) = json_to_doc(&json).unwrap() else {
+            panic!("Document shouldn't have needed signing")
+        };
+        assert_eq!(parsed.hash(), doc.hash());
+    }
+
+    #[test]
+    fn editor_keys() {
+        let json = serde_json::json!({
+            "$schema": "https://example.com/doc.json",
+            "data": { "$id": "thing", "a": 1 },
+        });
+        assert!(json_to_doc(&json).is_err());
+        let opts = DecodeOptions::new().ignore_editor_keys(true);
+        let MaybeDocument::NewDocument(parsed) = json_to_doc_with_options(&json, &opts).unwrap() else {
+            panic!("Document shouldn't have needed signing")
+        };
+        let expected = fog_pack::document::NewDocument::new(None, json_to_fog(&serde_json::json!({"a": 1})).unwrap()).unwrap();
+        assert_eq!(parsed.hash(), expected.hash());
+    }
+
+    #[test]
+    fn object_error_pointer() {
+        let json = serde_json::json!({"data": {"items": [0, 1, 2, "$fog-Hash:bad"]}});
+        let err = json_to_doc(&json).err().unwrap();
+        assert_eq!(err.pointer().as_deref(), Some("/data/items/3"));
+        assert_eq!(err.to_string(), "Data conversion failed at \"/data/items/3\"");
+        let text = serde_json::to_vec(&json).unwrap();
+        let err = json_reader_to_doc(text.as_slice()).err().unwrap();
+        assert_eq!(err.pointer().as_deref(), Some("/data/items/3"));
+        let err = json_to_doc(&serde_json::json!({"data": 1, "compression": "x"})).err().unwrap();
+        assert_eq!(err.pointer().as_deref(), Some("/compression"));
+        assert_eq!(ObjectError::NotAnObject.pointer(), None);
+    }
+
+    #[test]
+    fn sorted_keys() {
+        let mut map = BTreeMap::new();
+        for k in ["zeta", "alpha", "Beta", "mid"] {
+            map.insert(k.to_string(), FogValue::Null);
+        }
+        let json = serde_json::to_string(&fog_to_json(&FogValue::Map(map))).unwrap();
+        assert_eq!(json, r#"{"Beta":null,"alpha":null,"mid":null,"zeta":null}"#);
+    }
+
+    #[cfg(feature = "preserve_order")]
+    #[test]
+    fn preserve_order() {
+        let text = r#"{"zeta":1,"alpha":2}"#;
+        let json: JsonValue = serde_json::from_str(text).unwrap();
+        assert_eq!(serde_json::to_string(&json).unwrap(), text);
+        let fog = json_str_to_fog(text).unwrap();
+        assert_eq!(serde_json::to_string(&fog_to_json(&fog)).unwrap(), r#"{"alpha":2,"zeta":1}"#);
+
+        // Envelope-only keys still come out sorted
+        let doc = fog_pack::document::NewDocument::new(None, 1u8).unwrap();
+        let doc = NoSchema::validate_new_doc(doc).unwrap();
+        let opts = EncodeOptions::new().generator(true);
+        let mut written = Vec::new();
+        doc_to_json_writer(&doc, &opts, &mut written).unwrap();
+        let json = serde_json::to_vec(&doc_to_json_with_options(&doc, &opts)).unwrap();
+        assert_eq!(json, written);
+    }
 }
@@ -0,0 +1,168 @@
+use super::*;
+use dec::TYPE_NAMES;
+
+/// A string that looks like it was meant to be a `$fog-TYPE:` string, but isn't quite one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lint {
+    /// JSON Pointer to the string within the JSON value.
+    pub path: String,
+    /// What's wrong with the string.
+    pub kind: LintKind,
+    /// The start of the string as written, up to and including the first colon.
+    pub found: String,
+    /// The `$fog-TYPE:` start that was probably meant, if there's a close enough type name.
+    pub suggestion: Option<String>,
+}
+
+/// The kinds of mistakes found by [`lint_json`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LintKind {
+    /// The string starts with something close to the type prefix, like `fog-Hash:`, so it will
+    /// silently be read as a plain string.
+    NearMissPrefix,
+    /// The string has the type prefix, but the type name isn't recognized, so conversion will
+    /// fail, or keep it as a plain string if [lenient][DecodeOptions::lenient].
+    UnknownType,
+}
+
+/// Longest start of a string, up to its first colon, that's checked for a near-miss prefix.
+const MAX_START_LEN: usize = 40;
+
+/// Scan a JSON value for strings that look like mistyped `$fog-TYPE:` strings, like
+/// `$fog-Indentity:` or `fog-Hash:`, suggesting what was probably meant.
+///
+/// Lints are returned in the order their strings appear in the value. Object keys aren't checked.
+///
+/// ```
+/// # use fog_human_json::*;
+/// let json = serde_json::json!({"owner": "fog-Identity:abc"});
+/// let lints = lint_json(&json);
+/// assert_eq!(lints[0].path, "/owner");
+/// assert_eq!(lints[0].suggestion.as_deref(), Some("$fog-Identity:"));
+/// ```
+pub fn lint_json(val: &JsonValue) -> Vec<Lint> {
+    lint_json_with_options(val, &DecodeOptions::default())
+}
+
+/// Scan a JSON value for mistyped `$fog-TYPE:` strings, as with [`lint_json`], using the type
+/// prefix and type name handling from the provided decoding options.
+pub fn lint_json_with_options(val: &JsonValue, opts: &DecodeOptions) -> Vec<Lint> {
+    let child = |path: &str, segment: &str| {
+        let mut path = path.to_owned();
+        dec::push_pointer_segment(&mut path, segment);
+        path
+    };
+    let mut lints = Vec::new();
+    let mut stack = vec![(val, String::new())];
+    while let Some((val, path)) = stack.pop() {
+        match val {
+            JsonValue::String(s) => {
+                if let Some((kind, found, suggestion)) = lint_str(s, opts) {
+                    lints.push(Lint { path, kind, found, suggestion });
+                }
+            },
+            JsonValue::Array(a) => {
+                stack.extend(a.iter().enumerate().rev().map(|(i, v)| (v, child(&path, &i.to_string()))));
+            },
+            JsonValue::Object(o) => {
+                stack.extend(o.iter().rev().map(|(k, v)| (v, child(&path, k))));
+            },
+            _ => (),
+        }
+    }
+    lints
+}
+
+/// Check a single string, returning the kind of mistake, the start as written, and a suggestion.
+fn lint_str(s: &str, opts: &DecodeOptions) -> Option<(LintKind, String, Option<String>)> {
+    let suggest = |name: &str| format!("{}{}:", opts.prefix(), name);
+    if let Some(rest) = opts.strip_prefix(s) {
+        let (ty, _) = rest.split_once(':')?;
+        if TYPE_NAMES.contains(&opts.type_name(ty)) {
+            return None;
+        }
+        let found = s[..s.len() - rest.len() + ty.len() + 1].to_owned();
+        return Some((LintKind::UnknownType, found, closest_type(ty, opts).map(suggest)));
+    }
+
+    let start = &s[..s.find(':')?];
+    if start.len() > MAX_START_LEN {
+        return None;
+    }
+    // Try splitting the start around where the prefix should end, keeping the closest match
+    let prefix = opts.prefix();
+    let (_, name) = (prefix.len().saturating_sub(2)..=prefix.len() + 1)
+        .filter(|&i| start.is_char_boundary(i) && i < start.len())
+        .filter(|&i| edit_distance(&start[..i], prefix) <= 1)
+        .filter_map(|i| {
+            let ty = &start[i..];
+            let name = closest_type(ty, opts)?;
+            let distance = edit_distance(&start[..i], prefix) + edit_distance(ty, name);
+            Some((distance, name))
+        })
+        .min_by_key(|(distance, _)| *distance)?;
+    Some((LintKind::NearMissPrefix, format!("{}:", start), Some(suggest(name))))
+}
+
+/// Find the type name closest to one that was written, if any are close enough.
+fn closest_type(ty: &str, opts: &DecodeOptions) -> Option<&'static str> {
+    let resolved = opts.type_name(ty);
+    if let Some(name) = TYPE_NAMES.iter().find(|name| **name == resolved) {
+        return Some(name);
+    }
+    let lower = ty.to_ascii_lowercase();
+    TYPE_NAMES.iter()
+        .map(|name| (edit_distance(&lower, &name.to_ascii_lowercase()), *name))
+        .filter(|(distance, name)| *distance <= if name.len() <= 4 { 1 } else { 2 })
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, name)| name)
+}
+
+/// Count the single-character insertions, deletions, substitutions, and adjacent swaps needed to
+/// turn one string into another.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d: Vec<Vec<usize>> = (0..=a.len())
+        .map(|i| (0..=b.len()).map(|j| if i == 0 { j } else if j == 0 { i } else { 0 }).collect())
+        .collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(d[i - 2][j - 2] + 1);
+            }
+            d[i][j] = best;
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn lint_json() {
+        let json = json!({
+            "a": ["fog-Hash:abc", "$fog-Indentity:abc", "$fgo-Int:5", "$Fog-Time:x"],
+            "b": {"c": "$fog-Whatever:1", "d": "fine: just text", "e": "$fog-Binary:AAEC"},
+            "f": "$fog-Int:5",
+        });
+        let lints = super::lint_json(&json);
+        let lints: Vec<(&str, LintKind, &str, Option<&str>)> = lints.iter()
+            .map(|l| (l.path.as_str(), l.kind, l.found.as_str(), l.suggestion.as_deref()))
+            .collect();
+        assert_eq!(lints, vec![
+            ("/a/0", LintKind::NearMissPrefix, "fog-Hash:", Some("$fog-Hash:")),
+            ("/a/1", LintKind::UnknownType, "$fog-Indentity:", Some("$fog-Identity:")),
+            ("/a/2", LintKind::NearMissPrefix, "$fgo-Int:", Some("$fog-Int:")),
+            ("/a/3", LintKind::NearMissPrefix, "$Fog-Time:", Some("$fog-Time:")),
+            ("/b/c", LintKind::UnknownType, "$fog-Whatever:", None),
+        ]);
+        let opts = DecodeOptions::new().case_insensitive_types(true);
+        assert!(lint_json_with_options(&json!("$Fog-Time:x"), &opts).is_empty());
+    }
+}
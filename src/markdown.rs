@@ -0,0 +1,68 @@
+use fog_pack::document::Document;
+
+use super::*;
+
+/// Width used when pretty-printing the document data.
+const MARKDOWN_WIDTH: usize = 80;
+
+/// Render a [Document] as a Markdown report.
+///
+/// The report starts with a header listing the document's hash, and its schema and signer if it
+/// has them. The data follows in a fenced `json` code block, converted with [`doc_to_json`] and
+/// pretty-printed with [`fog_pretty_string`].
+///
+/// # Panics
+///
+/// Panics if the document's data can't be read, which can only happen if it was loaded without 
+/// validation. Use [`try_doc_to_markdown`] to get an error instead.
+pub fn doc_to_markdown(doc: &Document) -> String {
+    try_doc_to_markdown(doc).unwrap_or_else(|err| panic!("{}; use try_doc_to_markdown to handle this", err))
+}
+
+/// Render a [Document] as a Markdown report, as with [`doc_to_markdown`], failing instead of 
+/// panicking if the document's data can't be read.
+pub fn try_doc_to_markdown(doc: &Document) -> Result<String, EncodeError> {
+    let mut json = try_doc_to_json(doc, &EncodeOptions::default())?;
+    let data = json["data"].take();
+    let mut out = String::from("# Document\n\n");
+    out.push_str(&format!("- Hash: `{}`\n", doc.hash()));
+    if let Some(schema) = doc.schema_hash() {
+        out.push_str(&format!("- Schema: `{}`\n", schema));
+    }
+    if let Some(signer) = doc.signer() {
+        out.push_str(&format!("- Signer: `{}`\n", signer));
+    }
+    let data = fog_pretty_string(&data, MARKDOWN_WIDTH);
+    // The fence must be longer than any run of backticks inside the block
+    let longest = data.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    out.push_str(&format!("\n{}json\n{}\n{}\n", fence, data, fence));
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fog_crypto::identity::IdentityKey;
+    use fog_pack::{document::NewDocument, schema::NoSchema};
+
+    #[test]
+    fn doc_to_markdown() {
+        let key = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let new_doc = NewDocument::new(None, ["a", "```"]).unwrap().sign(&key).unwrap();
+        let doc = NoSchema::validate_new_doc(new_doc).unwrap();
+        let expected = format!(
+            "# Document\n\n- Hash: `{}`\n- Signer: `{}`\n\n````json\n[\"a\", \"```\"]\n````\n",
+            doc.hash(),
+            key.id()
+        );
+        assert_eq!(super::doc_to_markdown(&doc), expected);
+
+        // Corrupt the data's first marker, past the compression byte, hash length, and data length
+        let doc = NewDocument::new(None, "hello").unwrap().compression(None);
+        let (_, mut encoded) = NoSchema::encode_doc(NoSchema::validate_new_doc(doc).unwrap()).unwrap();
+        encoded[5] = 0xc1;
+        let doc = NoSchema::trusted_decode_doc(encoded).unwrap();
+        assert!(matches!(try_doc_to_markdown(&doc), Err(EncodeError::Deserialize(_))));
+    }
+}
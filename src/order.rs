@@ -0,0 +1,115 @@
+use crate::dec;
+use serde::de::{Deserializer, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+use std::fmt;
+
+/// How the keys of one JSON object will be reordered when converted to fog-pack.
+///
+/// fog-pack maps are always encoded with their keys in lexicographic order, which frequently
+/// differs from the order they were written in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyOrder {
+    /// JSON Pointer to the object within the JSON value.
+    pub path: String,
+    /// Keys in the order they appear in the JSON text.
+    pub json_order: Vec<String>,
+    /// Keys in the order fog-pack will encode them.
+    pub fog_order: Vec<String>,
+}
+
+impl KeyOrder {
+    /// Iterate over the keys that will be in a different position once encoded.
+    pub fn moved(&self) -> impl Iterator<Item = &str> {
+        self.json_order.iter()
+            .zip(self.fog_order.iter())
+            .filter(|(json, fog)| json != fog)
+            .map(|(json, _)| json.as_str())
+    }
+}
+
+/// Report which objects in a JSON text will have their keys reordered when converted to fog-pack.
+///
+/// Only objects with at least one moved key are reported, in the order they appear in the text.
+/// Duplicate keys are only counted at their first position.
+pub fn key_order_report(json: &str) -> Result<Vec<KeyOrder>, serde_json::Error> {
+    let mut report = Vec::new();
+    let mut de = serde_json::Deserializer::from_str(json);
+    OrderWalker { path: String::new(), report: &mut report }.deserialize(&mut de)?;
+    de.end()?;
+    Ok(report)
+}
+
+struct OrderWalker<'a> {
+    path: String,
+    report: &'a mut Vec<KeyOrder>,
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for OrderWalker<'a> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for OrderWalker<'a> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any JSON value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> { Ok(()) }
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> { Ok(()) }
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> { Ok(()) }
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> { Ok(()) }
+    fn visit_str<E>(self, _: &str) -> Result<(), E> { Ok(()) }
+    fn visit_unit<E>(self) -> Result<(), E> { Ok(()) }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let mut i = 0usize;
+        loop {
+            let mut path = self.path.clone();
+            dec::push_pointer_segment(&mut path, &i.to_string());
+            let walker = OrderWalker { path, report: &mut *self.report };
+            if seq.next_element_seed(walker)?.is_none() { break; }
+            i += 1;
+        }
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        // Reserve our place in the report so parents come before their children
+        let index = self.report.len();
+        let mut json_order: Vec<String> = Vec::new();
+        while let Some(key) = map.next_key::<String>()? {
+            let mut path = self.path.clone();
+            dec::push_pointer_segment(&mut path, &key);
+            map.next_value_seed(OrderWalker { path, report: &mut *self.report })?;
+            if !json_order.contains(&key) {
+                json_order.push(key);
+            }
+        }
+        let mut fog_order = json_order.clone();
+        fog_order.sort_unstable();
+        if fog_order != json_order {
+            self.report.insert(index, KeyOrder { path: self.path, json_order, fog_order });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report() {
+        let json = r#"{"data": {"b": 1, "a": {"y": 2, "x": 3}, "c": [{"q": 0, "p": 1}]}}"#;
+        let report = key_order_report(json).unwrap();
+        let paths: Vec<&str> = report.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(paths, ["/data", "/data/a", "/data/c/0"]);
+        let moved: Vec<&str> = report[0].moved().collect();
+        assert_eq!(moved, ["b", "a"]);
+        assert!(key_order_report(r#"{"a": 1, "b": {"c": 2}}"#).unwrap().is_empty());
+    }
+}
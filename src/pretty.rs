@@ -0,0 +1,218 @@
+use super::*;
+
+/// Pretty-print JSON, keeping arrays and objects on one line when they fit.
+///
+/// `serde_json::to_string_pretty` puts every element on its own line, so a short value like
+/// `[0, 1, 2, 3]` spreads over six lines. This instead writes each array or object inline if it
+/// fits within `width` columns (counting indentation and its key), and only breaks it across
+/// lines, indented by two spaces per level, if it doesn't.
+///
+/// ```
+/// # use fog_human_json::*;
+/// let json = serde_json::json!({"magic": [0, 1, 2, 3], "name": "fog"});
+/// assert_eq!(fog_pretty_string(&json, 30), "{\n  \"magic\": [0, 1, 2, 3],\n  \"name\": \"fog\"\n}");
+/// ```
+pub fn fog_pretty_string(json: &JsonValue, width: usize) -> String {
+    Printer::plain(width).print(json)
+}
+
+/// Lays out JSON across lines, with hooks for how keys and non-container values are written.
+pub(crate) struct Printer {
+    width: usize,
+    key: fn(&str) -> String,
+    scalar: fn(&JsonValue) -> String,
+}
+
+impl Printer {
+    /// A printer that writes plain, uncolored JSON.
+    fn plain(width: usize) -> Self {
+        Self { width, key: plain_key, scalar: JsonValue::to_string }
+    }
+
+    /// A printer that writes keys and non-container values with the given functions. These must
+    /// only add zero-width text, like terminal escape codes, so the layout stays the same.
+    #[cfg_attr(not(feature = "ansi"), allow(dead_code))]
+    pub(crate) fn styled(width: usize, key: fn(&str) -> String, scalar: fn(&JsonValue) -> String) -> Self {
+        Self { width, key, scalar }
+    }
+
+    pub(crate) fn print(&self, json: &JsonValue) -> String {
+        let mut out = String::new();
+        self.pretty(&mut out, json, &Width::of(json), 0, 0);
+        out
+    }
+
+    /// Write a value that starts at column `column`, with the line indented by `indent` spaces.
+    fn pretty(&self, out: &mut String, json: &JsonValue, width: &Width, indent: usize, column: usize) {
+        if width.items.is_empty() || column + width.width <= self.width {
+            self.compact(out, json);
+            return;
+        }
+        let inner = indent + 2;
+        match json {
+            JsonValue::Array(a) => {
+                out.push_str("[\n");
+                for (i, (v, w)) in a.iter().zip(&width.items).enumerate() {
+                    push_indent(out, inner);
+                    self.pretty(out, v, w, inner, inner);
+                    if i + 1 < a.len() { out.push(','); }
+                    out.push('\n');
+                }
+                push_indent(out, indent);
+                out.push(']');
+            },
+            JsonValue::Object(o) => {
+                out.push_str("{\n");
+                for (i, ((k, v), w)) in o.iter().zip(&width.items).enumerate() {
+                    push_indent(out, inner);
+                    out.push_str(&(self.key)(k));
+                    out.push_str(": ");
+                    self.pretty(out, v, w, inner, inner + key_width(k) + 2);
+                    if i + 1 < o.len() { out.push(','); }
+                    out.push('\n');
+                }
+                push_indent(out, indent);
+                out.push('}');
+            },
+            _ => unreachable!("Only arrays and objects can be broken across lines"),
+        }
+    }
+
+    /// Write a value on a single line, with a space after each comma and colon.
+    fn compact(&self, out: &mut String, json: &JsonValue) {
+        match json {
+            JsonValue::Array(a) => {
+                out.push('[');
+                for (i, v) in a.iter().enumerate() {
+                    if i > 0 { out.push_str(", "); }
+                    self.compact(out, v);
+                }
+                out.push(']');
+            },
+            JsonValue::Object(o) => {
+                out.push('{');
+                for (i, (k, v)) in o.iter().enumerate() {
+                    if i > 0 { out.push_str(", "); }
+                    out.push_str(&(self.key)(k));
+                    out.push_str(": ");
+                    self.compact(out, v);
+                }
+                out.push('}');
+            },
+            _ => out.push_str(&(self.scalar)(json)),
+        }
+    }
+}
+
+/// How many characters a value takes up when written on a single line, worked out once for the 
+/// whole value so laying it out doesn't have to measure each part again at every level.
+struct Width {
+    width: usize,
+    /// The widths of the items of a non-empty array or object
+    items: Vec<Width>,
+}
+
+impl Width {
+    fn of(json: &JsonValue) -> Self {
+        let (keys, items): (usize, Vec<Width>) = match json {
+            JsonValue::Array(a) => (0, a.iter().map(Width::of).collect()),
+            JsonValue::Object(o) => (
+                o.keys().map(|k| key_width(k) + 2).sum(),
+                o.values().map(Width::of).collect(),
+            ),
+            _ => return Self { width: json.to_string().chars().count(), items: Vec::new() },
+        };
+        // The brackets, plus a comma and space between each item
+        let width = 2 + 2 * items.len().saturating_sub(1) + keys + items.iter().map(|w| w.width).sum::<usize>();
+        Self { width, items }
+    }
+}
+
+fn key_width(key: &str) -> usize {
+    plain_key(key).chars().count()
+}
+
+fn plain_key(key: &str) -> String {
+    JsonValue::String(key.to_owned()).to_string()
+}
+
+fn push_indent(out: &mut String, indent: usize) {
+    out.extend(std::iter::repeat_n(' ', indent));
+}
+
+/// How the styled renderers color a special `$fog-` string, by its type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FogStyle {
+    Str,
+    Id,
+    Time,
+    Number,
+    Binary,
+}
+
+/// A special `$fog-` string, split at the colon after its type.
+pub(crate) struct FogStr<'a> {
+    pub ty: &'a str,
+    pub rest: &'a str,
+    pub style: FogStyle,
+}
+
+impl<'a> FogStr<'a> {
+    /// Split a string with the `$fog-` prefix and a type tag, or return `None` if it has neither.
+    pub fn parse(s: &'a str) -> Option<Self> {
+        let (ty, rest) = s.strip_prefix(FOG_PREFIX)?.split_once(':')?;
+        let style = match ty {
+            "Str" => FogStyle::Str,
+            "Hash" | "Identity" | "IdentityName" | "StreamId" | "LockId" => FogStyle::Id,
+            "Time" | "TimeRaw" => FogStyle::Time,
+            "Int" | "F32" | "F64" | "F32Hex" | "F64Hex" => FogStyle::Number,
+            _ => FogStyle::Binary,
+        };
+        Some(Self { ty, rest, style })
+    }
+
+    /// The JSON-escaped string in two parts: the opening quote, prefix, type, and colon, then the
+    /// rest with the closing quote.
+    pub fn quoted(&self) -> (String, String) {
+        // Escape each part on its own, as escaping can change the type's length
+        let mut tag = JsonValue::String(format!("{}{}:", FOG_PREFIX, self.ty)).to_string();
+        tag.pop();
+        let mut rest = JsonValue::String(self.rest.to_owned()).to_string();
+        rest.remove(0);
+        (tag, rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn pretty() {
+        let json = json!({"data": {"bytes": [0, 1, 2, 3], "list": ["a long string", "another long string"]}, "empty": []});
+        let expected = r#"{
+  "data": {
+    "bytes": [0, 1, 2, 3],
+    "list": [
+      "a long string",
+      "another long string"
+    ]
+  },
+  "empty": []
+}"#;
+        let out = fog_pretty_string(&json, 40);
+        assert_eq!(out, expected);
+        assert_eq!(serde_json::from_str::<JsonValue>(&out).unwrap(), json);
+    }
+
+    #[test]
+    fn width_counts_chars() {
+        // The list fits in 30 columns, though it takes up more than 30 bytes
+        let json = json!({"nämé": ["héllo wörld", "ü"], "x": 1});
+        let expected = "{\n  \"nämé\": [\"héllo wörld\", \"ü\"],\n  \"x\": 1\n}";
+        assert_eq!(fog_pretty_string(&json, 30), expected);
+        let expected = "{\n  \"nämé\": [\n    \"héllo wörld\",\n    \"ü\"\n  ],\n  \"x\": 1\n}";
+        assert_eq!(fog_pretty_string(&json, 29), expected);
+    }
+}
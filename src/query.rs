@@ -1,30 +1,39 @@
 use super::*;
-use fog_pack::{
-    document::NewDocument,
-    query::{NewQuery, Query},
-    schema::NoSchema,
-};
+use fog_pack::query::{NewQuery, Query};
 
-/// Convert a [`NewQuery`] into a JSON value. Can panic if the query is too large.
+/// Convert a [`NewQuery`] into a JSON value.
 ///
 /// The resulting JSON value will be an Object with the following key-value pairs:
 ///
 /// - "validator": The query validator
 /// - "key": The query's key, which selects and queries all entries with a matching key.
 ///
+/// # Panics
+///
+/// Panics if the validator can't be converted into fog-pack data. Use 
+/// [`try_new_query_to_json`] to get an error instead.
 pub fn new_query_to_json(query: &NewQuery) -> JsonValue {
-    let doc = NewDocument::new(None, query.validator())
-        .expect("Query was way too large");
-    let doc = NoSchema::validate_new_doc(doc)
-        .expect("Queries should always be valid serializeable fog-pack");
-    let validator: FogValueRef = doc.deserialize()
-        .expect("All fog-pack documents should be deserializable to a ValueRef");
-    
-    let mut map: BTreeMap<&str, FogValueRef> = BTreeMap::new();
-    map.insert("validator", validator);
-    map.insert("key", FogValueRef::Str(query.key()));
-    let query = FogValueRef::Map(map);
-    fogref_to_json(&query)
+    new_query_to_json_with_options(query, &EncodeOptions::default())
+}
+
+/// Convert a [`NewQuery`] into a JSON value, as with [`new_query_to_json`], using the provided 
+/// encoding options.
+///
+/// # Panics
+///
+/// Panics if the validator can't be converted into fog-pack data, or is nested deeper than 
+/// [`EncodeOptions::max_depth`]. Use [`try_new_query_to_json`] to get an error instead.
+pub fn new_query_to_json_with_options(query: &NewQuery, opts: &EncodeOptions) -> JsonValue {
+    try_new_query_to_json(query, opts)
+        .unwrap_or_else(|err| panic!("{}; use try_new_query_to_json to handle this", err))
+}
+
+/// Convert a [`NewQuery`] into a JSON value, as with [`new_query_to_json_with_options`], failing 
+/// instead of panicking.
+pub fn try_new_query_to_json(query: &NewQuery, opts: &EncodeOptions) -> Result<JsonValue, EncodeError> {
+    with_query_parts(query.validator(), query.key(), |val| {
+        Encoder::new(opts).try_envelope(val, JsonMap::new())
+    })?
 }
 
 /// Convert a [`Query`] into a JSON value.
@@ -34,19 +43,78 @@ pub fn new_query_to_json(query: &NewQuery) -> JsonValue {
 /// - "validator": The query validator
 /// - "key": The query's key, which selects and queries all entries with a matching key.
 ///
+/// # Panics
+///
+/// Panics if the validator can't be converted into fog-pack data. Use [`try_query_to_json`] to 
+/// get an error instead.
 pub fn query_to_json(query: &Query) -> JsonValue {
-    let doc = NewDocument::new(None, query.validator())
-        .expect("Query was way too large, which shouldn't have been possible");
-    let doc = NoSchema::validate_new_doc(doc)
-        .expect("Queries should always be valid serializeable fog-pack");
-    let validator: FogValueRef = doc.deserialize()
-        .expect("All fog-pack documents should be deserializable to a ValueRef");
-    
+    query_to_json_with_options(query, &EncodeOptions::default())
+}
+
+/// Convert a [`Query`] into a JSON value, as with [`query_to_json`], using the provided encoding 
+/// options.
+///
+/// # Panics
+///
+/// Panics if the validator can't be converted into fog-pack data, or is nested deeper than 
+/// [`EncodeOptions::max_depth`]. Use [`try_query_to_json`] to get an error instead.
+pub fn query_to_json_with_options(query: &Query, opts: &EncodeOptions) -> JsonValue {
+    try_query_to_json(query, opts)
+        .unwrap_or_else(|err| panic!("{}; use try_query_to_json to handle this", err))
+}
+
+/// Convert a [`Query`] into a JSON value, as with [`query_to_json_with_options`], failing 
+/// instead of panicking.
+pub fn try_query_to_json(query: &Query, opts: &EncodeOptions) -> Result<JsonValue, EncodeError> {
+    with_query_parts(query.validator(), query.key(), |val| {
+        Encoder::new(opts).try_envelope(val, JsonMap::new())
+    })?
+}
+
+/// Convert a [`NewQuery`] into compact JSON text, as with [`new_query_to_json_with_options`], 
+/// writing it straight to `writer` without building the JSON value first.
+///
+/// A validator that can't be converted, or is nested deeper than [`EncodeOptions::max_depth`], is 
+/// reported as an [`InvalidData`][std::io::ErrorKind::InvalidData] error. The depth limit is only 
+/// found while writing, so the output may be left incomplete.
+pub fn new_query_to_json_writer<W: std::io::Write>(
+    query: &NewQuery,
+    opts: &EncodeOptions,
+    mut writer: W
+) -> std::io::Result<()> {
+    let res = with_query_parts(query.validator(), query.key(), |val| {
+        Encoder::new(opts).write_envelope(&mut writer, val, JsonMap::new())
+    });
+    res.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?
+}
+
+/// Convert a [`Query`] into compact JSON text, as with [`query_to_json_with_options`], writing it 
+/// straight to `writer` without building the JSON value first, as with 
+/// [`new_query_to_json_writer`].
+pub fn query_to_json_writer<W: std::io::Write>(
+    query: &Query,
+    opts: &EncodeOptions,
+    mut writer: W
+) -> std::io::Result<()> {
+    let res = with_query_parts(query.validator(), query.key(), |val| {
+        Encoder::new(opts).write_envelope(&mut writer, val, JsonMap::new())
+    });
+    res.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?
+}
+
+/// Run `f` on the fog-pack map of a query's validator and key.
+fn with_query_parts<T>(
+    validator: &fog_pack::validator::Validator,
+    key: &str,
+    f: impl FnOnce(&FogValueRef) -> T
+) -> Result<T, EncodeError> {
+    let validator = value::to_value(validator).map_err(EncodeError::Validator)?;
+    let validator = validator.as_ref();
+
     let mut map: BTreeMap<&str, FogValueRef> = BTreeMap::new();
     map.insert("validator", validator);
-    map.insert("key", FogValueRef::Str(query.key()));
-    let query = FogValueRef::Map(map);
-    fogref_to_json(&query)
+    map.insert("key", FogValueRef::Str(key));
+    Ok(f(&FogValueRef::Map(map)))
 }
 
 /// Convert JSON into a [`NewQuery`].
@@ -57,30 +125,59 @@ pub fn query_to_json(query: &Query) -> JsonValue {
 /// - "key": The query's key, which selects and queries all entries with a matching key.
 ///
 pub fn json_to_query(json: &JsonValue) -> Result<NewQuery, ObjectError> {
+    json_to_query_with_options(json, &DecodeOptions::default())
+}
+
+/// Convert JSON into a [`NewQuery`], as with [`json_to_query`], using the provided decoding 
+/// options.
+pub fn json_to_query_with_options(json: &JsonValue, opts: &DecodeOptions) -> Result<NewQuery, ObjectError> {
+    let mut dec = Decoder::new(opts);
     let obj = json.as_object().ok_or(ObjectError::NotAnObject)?;
 
     // Make sure we only have fields we recognize
     for k in obj.keys() {
         match k.as_str() {
-            "validator" | "key" => (),
+            "validator" | "key" | GENERATOR_KEY => (),
+            k if opts.skip_key(k) => (),
             k => return Err(ObjectError::UnrecognizedKey(k.to_string())),
         }
     }
 
     // Get the Key
-    let key = obj.get("key").ok_or_else(|| ObjectError::MissingKey("key"))?;
-    let key = json_to_fog(key)
+    let key = obj.get("key").ok_or(ObjectError::MissingKey("key"))?;
+    let key = dec.value(key)
         .map_err(|e| ObjectError::Decode { key: "key", src: e })?;
     let key = key
         .as_str()
         .ok_or(ObjectError::WrongDataType("key"))?;
 
-    // Get the Validator, which must round-trip through a Document to be encoded
-    let validator = obj.get("validator").ok_or_else(|| ObjectError::MissingKey("validator"))?;
-    let validator = json_to_fog(validator).map_err(|e| ObjectError::Decode { key: "validator", src: e })?;
-    let validator = NewDocument::new(None, validator)?;
-    let validator = NoSchema::validate_new_doc(validator)?;
-    let validator: fog_pack::validator::Validator = validator.deserialize()?;
+    // Get the Validator
+    let validator = obj.get("validator").ok_or(ObjectError::MissingKey("validator"))?;
+    let validator = dec.root(validator).map_err(|e| ObjectError::Decode { key: "validator", src: e })?;
+    let validator: fog_pack::validator::Validator = value::from_value(&validator)?;
 
     Ok(NewQuery::new(key, validator))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fog_pack::validator::*;
+
+    #[test]
+    fn try_new_query_to_json() {
+        let opts = EncodeOptions::default();
+        let query = NewQuery::new("item", IntValidator::new().max(10).build());
+        let json = super::try_new_query_to_json(&query, &opts).unwrap();
+        assert_eq!(json, new_query_to_json(&query));
+        let back = json_to_query(&json).unwrap();
+        assert_eq!(back.key(), "item");
+        assert_eq!(back.validator(), query.validator());
+
+        // Too large for a Document, but the validator no longer needs to fit in one
+        let huge = StrValidator::new().in_add("x".repeat(fog_pack::MAX_DOC_SIZE)).build();
+        let query = NewQuery::new("item", huge);
+        let json = super::try_new_query_to_json(&query, &opts).unwrap();
+        assert_eq!(json_to_query(&json).unwrap().validator(), query.validator());
+    }
+}
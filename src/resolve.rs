@@ -0,0 +1,84 @@
+use fog_pack::types::{Hash, Identity};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Looks up human-friendly names for schemas, so converted Documents can say which schema they use 
+/// in terms a reviewer recognizes.
+///
+/// Implemented for maps from schema hashes to names, and for closures that take a schema hash.
+pub trait SchemaNameResolver: Send + Sync {
+    /// Get the name of a schema, if it's known.
+    fn schema_name(&self, schema: &Hash) -> Option<String>;
+
+    /// Get the schema with a given name, if it's known. Used to fill in a missing schema hash when 
+    /// converting from JSON. Returns `None` by default.
+    fn schema_hash(&self, _name: &str) -> Option<Hash> {
+        None
+    }
+}
+
+impl fmt::Debug for dyn SchemaNameResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SchemaNameResolver")
+    }
+}
+
+impl SchemaNameResolver for HashMap<Hash, String> {
+    fn schema_name(&self, schema: &Hash) -> Option<String> {
+        self.get(schema).cloned()
+    }
+
+    fn schema_hash(&self, name: &str) -> Option<Hash> {
+        self.iter().find(|(_, n)| n.as_str() == name).map(|(h, _)| h.clone())
+    }
+}
+
+impl<F> SchemaNameResolver for F
+where
+    F: Fn(&Hash) -> Option<String> + Send + Sync,
+{
+    fn schema_name(&self, schema: &Hash) -> Option<String> {
+        self(schema)
+    }
+}
+
+/// Looks up petnames for Identities, from a directory of known Identities.
+///
+/// When encoding, names are appended to Identity strings, like `$fog-Identity:<base58> (alice)`. 
+/// When decoding, `$fog-IdentityName:alice` is resolved back into the Identity.
+///
+/// Implemented for maps from Identities to names, and for closures that take an Identity.
+pub trait IdentityNameResolver: Send + Sync {
+    /// Get the name of an Identity, if it's known.
+    fn identity_name(&self, id: &Identity) -> Option<String>;
+
+    /// Get the Identity with a given name, if it's known. Returns `None` by default.
+    fn identity(&self, _name: &str) -> Option<Identity> {
+        None
+    }
+}
+
+impl fmt::Debug for dyn IdentityNameResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("IdentityNameResolver")
+    }
+}
+
+impl IdentityNameResolver for HashMap<Identity, String> {
+    fn identity_name(&self, id: &Identity) -> Option<String> {
+        self.get(id).cloned()
+    }
+
+    fn identity(&self, name: &str) -> Option<Identity> {
+        self.iter().find(|(_, n)| n.as_str() == name).map(|(id, _)| id.clone())
+    }
+}
+
+impl<F> IdentityNameResolver for F
+where
+    F: Fn(&Identity) -> Option<String> + Send + Sync,
+{
+    fn identity_name(&self, id: &Identity) -> Option<String> {
+        self(id)
+    }
+}
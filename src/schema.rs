@@ -0,0 +1,363 @@
+use super::*;
+use fog_pack::{
+    document::Document,
+    schema::{Compress, Schema, SchemaBuilder},
+    types::{Hash, Integer},
+    validator::Validator,
+};
+use std::collections::HashMap;
+
+/// A store of compiled [`Schema`]s, looked up by the hash of their schema document.
+///
+/// Implemented for [`SchemaRegistry`], [`SchemaCache`], and maps from schema hashes to schemas.
+pub trait SchemaSource {
+    /// Get the schema with a given hash, if it's in the store.
+    fn schema(&self, hash: &Hash) -> Option<&Schema>;
+}
+
+impl SchemaSource for HashMap<Hash, Schema> {
+    fn schema(&self, hash: &Hash) -> Option<&Schema> {
+        self.get(hash)
+    }
+}
+
+impl SchemaSource for BTreeMap<Hash, Schema> {
+    fn schema(&self, hash: &Hash) -> Option<&Schema> {
+        self.get(hash)
+    }
+}
+
+/// A cache of compiled [`Schema`]s, keyed by the hash of their schema document.
+///
+/// Compiling a schema from its document is comparatively expensive, so bulk conversions should
+/// share one cache instead of recompiling the same schema for every document or entry.
+#[derive(Clone, Debug, Default)]
+pub struct SchemaCache {
+    schemas: HashMap<Hash, Schema>,
+    max_regex: Option<u8>,
+}
+
+impl SchemaCache {
+    /// Create a new, empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit how many regular expressions a schema may contain when compiled by this cache. See
+    /// [`Schema::from_doc_max_regex`] for why this matters with untrusted schemas.
+    pub fn max_regex(mut self, max_regex: u8) -> Self {
+        self.max_regex = Some(max_regex);
+        self
+    }
+
+    /// Look up a compiled schema by its hash.
+    pub fn get(&self, hash: &Hash) -> Option<&Schema> {
+        self.schemas.get(hash)
+    }
+
+    /// Add an already compiled schema to the cache, replacing any schema with the same hash.
+    pub fn insert(&mut self, schema: Schema) {
+        self.schemas.insert(schema.hash().to_owned(), schema);
+    }
+
+    /// Remove a schema from the cache, returning it if it was present.
+    pub fn remove(&mut self, hash: &Hash) -> Option<Schema> {
+        self.schemas.remove(hash)
+    }
+
+    /// Get the compiled schema for a schema document, compiling and caching it if it isn't
+    /// already present.
+    pub fn get_or_compile(&mut self, doc: &Document) -> Result<&Schema, ObjectError> {
+        use std::collections::hash_map::Entry;
+        match self.schemas.entry(doc.hash().to_owned()) {
+            Entry::Occupied(e) => Ok(e.into_mut()),
+            Entry::Vacant(e) => {
+                let schema = match self.max_regex {
+                    Some(max_regex) => Schema::from_doc_max_regex(doc, max_regex)?,
+                    None => Schema::from_doc(doc)?,
+                };
+                Ok(e.insert(schema))
+            },
+        }
+    }
+
+    /// Number of schemas in the cache.
+    pub fn len(&self) -> usize {
+        self.schemas.len()
+    }
+
+    /// Check if the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.schemas.is_empty()
+    }
+}
+
+impl SchemaSource for SchemaCache {
+    fn schema(&self, hash: &Hash) -> Option<&Schema> {
+        self.get(hash)
+    }
+}
+
+/// A simple in-memory [`SchemaSource`], holding schemas that have already been compiled.
+///
+/// This is the same type as [`SchemaCache`], which can also compile schema documents as they're 
+/// needed. Fill it with [`insert`][SchemaCache::insert] or by collecting an iterator of 
+/// [`Schema`]s.
+pub type SchemaRegistry = SchemaCache;
+
+impl FromIterator<Schema> for SchemaCache {
+    fn from_iter<I: IntoIterator<Item = Schema>>(iter: I) -> Self {
+        let mut cache = Self::new();
+        cache.extend(iter);
+        cache
+    }
+}
+
+impl Extend<Schema> for SchemaCache {
+    fn extend<I: IntoIterator<Item = Schema>>(&mut self, iter: I) {
+        for schema in iter {
+            self.insert(schema);
+        }
+    }
+}
+
+/// The keys a schema document may have, in the order fog-pack encodes them.
+const SCHEMA_KEYS: [&str; 8] =
+    ["doc", "description", "doc_compress", "entries", "name", "types", "version", "max_regex"];
+
+/// Convert a schema [`Document`] into a JSON value holding the schema's fields directly, without
+/// the document envelope.
+///
+/// The resulting JSON value will be an Object with at least a "doc" key present, holding the
+/// validator for documents using the schema. Optional key-value pairs are:
+///
+/// - "name", "description", "version": Documentation for the schema.
+/// - "doc_compress": The default compression for documents using the schema.
+/// - "entries": An Object with each entry key's settings, as an Object with an "entry" validator
+///   and an optional "compress" setting.
+/// - "types": An Object with the named validators that other validators can refer to.
+/// - "max_regex": The maximum number of regular expressions allowed in a query.
+/// - "signer": A fog-pack Identity that signed the schema document.
+/// - "hash": The schema document's own hash, if enabled with [`EncodeOptions::doc_hash`].
+/// - "signature": Details about the signature, if enabled with 
+///   [`EncodeOptions::signature_details`].
+///
+/// As with [`doc_to_json`], the document's compression setting can't be read back from a 
+/// [`Document`], so it isn't included.
+///
+/// Fails with [`EncodeError::NotSchema`] if the document doesn't hold a schema's fields. The 
+/// validators themselves aren't checked; compile the schema with [`Schema::from_doc`] for that.
+pub fn schema_to_json(doc: &Document) -> Result<JsonValue, EncodeError> {
+    schema_to_json_with_options(doc, &EncodeOptions::default())
+}
+
+/// Convert a schema [`Document`] into a JSON value, as with [`schema_to_json`], using the
+/// provided encoding options.
+pub fn schema_to_json_with_options(doc: &Document, opts: &EncodeOptions) -> Result<JsonValue, EncodeError> {
+    let not_schema = |msg: &str| EncodeError::NotSchema(fog_pack::error::Error::FailValidate(msg.to_owned()));
+    if doc.schema_hash().is_some() {
+        return Err(not_schema("schema documents can't have a schema"));
+    }
+    let data = doc::doc_data(doc)?;
+    let FogValueRef::Map(mut map) = data else {
+        return Err(not_schema("schema document isn't a map"));
+    };
+    if !map.contains_key("doc") {
+        return Err(not_schema("schema document has no \"doc\" validator"));
+    }
+    if map.keys().any(|k| !SCHEMA_KEYS.contains(k)) {
+        return Err(not_schema("schema document has an unrecognized field"));
+    }
+    if let Some(signer) = doc.signer() {
+        map.insert("signer", FogValueRef::Identity(signer.to_owned()));
+        if opts.signature_details_enabled() {
+            map.insert("signature", enc::signature_details(signer, Some(true)));
+        }
+    }
+    if opts.doc_hash_enabled() {
+        map.insert("hash", FogValueRef::Hash(doc.hash().to_owned()));
+    }
+    Encoder::new(opts).try_envelope(&FogValueRef::Map(map), JsonMap::new())
+}
+
+/// Convert JSON into a schema document, as made by [`schema_to_json`]. Once completed, the 
+/// document can be validated with [`NoSchema`][fog_pack::schema::NoSchema] and compiled with 
+/// [`Schema::from_doc`].
+///
+/// Alongside the schema's fields, the JSON may have the same "signer", "compression", and "hash" 
+/// keys as a document converted with [`json_to_doc`]. If signing is required, this returns a 
+/// [`SignDocument`] in an enum, which must first be signed.
+///
+/// Fields that aren't valid for a schema, like a malformed validator, are reported as
+/// [`ObjectError::Schema`], with a JSON Pointer to the failing value like `/entries/item/entry`.
+pub fn json_to_schema(json: &JsonValue) -> Result<MaybeDocument, ObjectError> {
+    json_to_schema_with_options(json, &DecodeOptions::default())
+}
+
+/// Convert JSON into a schema [`Document`], as with [`json_to_schema`], using the provided
+/// decoding options.
+pub fn json_to_schema_with_options(json: &JsonValue, opts: &DecodeOptions) -> Result<MaybeDocument, ObjectError> {
+    let mut dec = Decoder::new(opts);
+    let obj = json.as_object().ok_or(ObjectError::NotAnObject)?;
+
+    // Make sure we only have fields we recognize, and convert the schema's own fields
+    let mut fields = HashMap::new();
+    let mut envelope = JsonMap::new();
+    for (k, v) in obj {
+        match SCHEMA_KEYS.iter().find(|key| **key == k) {
+            Some(key) => {
+                let v = dec.root(v).map_err(|e| ObjectError::Decode { key, src: e })?;
+                fields.insert(*key, v);
+            },
+            None if matches!(k.as_str(), "signer" | "compression" | "hash") => {
+                envelope.insert(k.clone(), v.clone());
+            },
+            None if k == "signature" || k == GENERATOR_KEY || opts.skip_key(k) => (),
+            None => return Err(ObjectError::UnrecognizedKey(k.to_string())),
+        }
+    }
+
+    let doc = fields.get("doc").ok_or(ObjectError::MissingKey("doc"))?;
+    let mut builder = SchemaBuilder::new(schema_field(doc, &["doc"])?);
+    if let Some(v) = fields.get("description") {
+        builder = builder.description(&schema_field::<String>(v, &["description"])?);
+    }
+    if let Some(v) = fields.get("doc_compress") {
+        builder = builder.doc_compress(schema_field(v, &["doc_compress"])?);
+    }
+    if let Some(v) = fields.get("name") {
+        builder = builder.name(&schema_field::<String>(v, &["name"])?);
+    }
+    if let Some(v) = fields.get("version") {
+        builder = builder.version(schema_field::<Integer>(v, &["version"])?);
+    }
+    if let Some(v) = fields.get("max_regex") {
+        builder = builder.regexes(schema_field(v, &["max_regex"])?);
+    }
+    if let Some(v) = fields.get("types") {
+        for (name, validator) in schema_map(v, &["types"])? {
+            builder = builder.type_add(name, schema_field(validator, &["types", name])?);
+        }
+    }
+    if let Some(v) = fields.get("entries") {
+        for (key, settings) in schema_map(v, &["entries"])? {
+            let mut entry = None;
+            let mut compress = None;
+            for (k, v) in schema_map(settings, &["entries", key])? {
+                match k.as_str() {
+                    "entry" => entry = Some(schema_field::<Validator>(v, &["entries", key, k])?),
+                    "compress" => compress = Some(schema_field::<Compress>(v, &["entries", key, k])?),
+                    _ => return Err(schema_error(&["entries", key, k], "unrecognized entry setting")),
+                }
+            }
+            let entry = entry.ok_or_else(|| schema_error(&["entries", key], "missing \"entry\" validator"))?;
+            builder = builder.entry_add(key, entry, compress);
+        }
+    }
+    // Building checks the fields, then the envelope is applied as for any other document
+    let data: FogValue = builder.build()?.deserialize()?;
+    doc::doc_from_parts(&envelope, data, &mut dec)
+}
+
+/// Read one field of a schema, reporting failures at `path`.
+fn schema_field<'de, T: serde::Deserialize<'de>>(val: &'de FogValue, path: &[&str]) -> Result<T, ObjectError> {
+    value::from_value(val).map_err(|src| ObjectError::Schema { path: schema_pointer(path), src })
+}
+
+/// Read an Object in a schema, reporting a failure at `path` if it's something else.
+fn schema_map<'a>(val: &'a FogValue, path: &[&str]) -> Result<&'a BTreeMap<String, FogValue>, ObjectError> {
+    val.as_map().ok_or_else(|| schema_error(path, "expected an object"))
+}
+
+fn schema_error(path: &[&str], msg: &str) -> ObjectError {
+    let src = fog_pack::error::Error::FailValidate(msg.to_owned());
+    ObjectError::Schema { path: schema_pointer(path), src }
+}
+
+fn schema_pointer(path: &[&str]) -> String {
+    let mut pointer = String::new();
+    for segment in path {
+        dec::push_pointer_segment(&mut pointer, segment);
+    }
+    pointer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fog_crypto::identity::IdentityKey;
+    use fog_pack::{schema::{NoSchema, SchemaBuilder}, validator::*};
+
+    #[test]
+    fn schema_cache() {
+        let schema = |validator: Validator| {
+            Schema::from_doc(&SchemaBuilder::new(validator).build().unwrap()).unwrap()
+        };
+        let (ints, strs) = (schema(IntValidator::new().build()), schema(StrValidator::new().build()));
+        let mut cache: SchemaRegistry = [ints.clone()].into_iter().collect();
+        cache.insert(strs.clone());
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.schema(strs.hash()).map(|s| s.hash()), Some(strs.hash()));
+        assert!(cache.remove(ints.hash()).is_some());
+
+        let source: &dyn SchemaSource = &cache;
+        assert!(source.schema(ints.hash()).is_none());
+        assert!(source.schema(strs.hash()).is_some());
+    }
+
+    #[test]
+    fn schema_json_round_trip() {
+        let schema_doc = SchemaBuilder::new(Validator::new_ref("item"))
+            .name("Inventory")
+            .version(2)
+            .doc_compress(Compress::new_zstd_general(3))
+            .type_add("item", StrValidator::new().max_len(32).build())
+            .entry_add("count", IntValidator::new().min(0).build(), Some(Compress::None))
+            .entry_add("note", Validator::new_ref("item"), None)
+            .build()
+            .unwrap();
+        let json = schema_to_json(&schema_doc).unwrap();
+        assert_eq!(json["name"], "Inventory");
+        assert!(json["entries"]["count"]["entry"].is_object());
+        let MaybeDocument::NewDocument(back) = json_to_schema(&json).unwrap() else {
+            panic!("Schema shouldn't have needed signing")
+        };
+        let back = NoSchema::validate_new_doc(back).unwrap();
+        assert_eq!(back.hash(), schema_doc.hash());
+        assert!(Schema::from_doc(&back).is_ok());
+
+        // Signed schemas keep their signer, and the hash is checked once signed
+        let key = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let signed = schema_doc.clone().sign(&key).unwrap();
+        let opts = EncodeOptions::new().doc_hash(true).signature_details(true);
+        let json = schema_to_json_with_options(&signed, &opts).unwrap();
+        assert_eq!(json["signer"], fog_to_json(&FogValue::Identity(key.id().clone())));
+        let MaybeDocument::SignDocument(sign) = json_to_schema(&json).unwrap() else {
+            panic!("Schema should have needed signing")
+        };
+        let back = NoSchema::validate_new_doc(sign.complete(&key).unwrap()).unwrap();
+        assert_eq!(back.hash(), signed.hash());
+        let mut json = json;
+        json["compression"] = JsonValue::Null;
+        assert!(matches!(json_to_schema(&json), Ok(MaybeDocument::SignDocument(_))));
+
+        let not_schema = fog_pack::document::NewDocument::new(None, "text").unwrap();
+        let not_schema = NoSchema::validate_new_doc(not_schema).unwrap();
+        assert!(matches!(schema_to_json(&not_schema), Err(EncodeError::NotSchema(_))));
+        let not_schema = fog_pack::document::NewDocument::new(None, serde_json::json!({"doc": 1, "x": 2})).unwrap();
+        let not_schema = NoSchema::validate_new_doc(not_schema).unwrap();
+        assert!(matches!(schema_to_json(&not_schema), Err(EncodeError::NotSchema(_))));
+        let json = schema_to_json(&schema_doc).unwrap();
+
+        let mut bad = json.clone();
+        bad["entries"]["count"]["entry"] = serde_json::json!({"Nope": null});
+        let err = json_to_schema(&bad).err().unwrap();
+        assert_eq!(err.pointer().as_deref(), Some("/entries/count/entry"));
+        let mut bad = json.clone();
+        bad["types"]["item"] = 5.into();
+        assert_eq!(json_to_schema(&bad).err().unwrap().pointer().as_deref(), Some("/types/item"));
+        let mut bad = json;
+        bad.as_object_mut().unwrap().remove("doc");
+        assert!(matches!(json_to_schema(&bad), Err(ObjectError::MissingKey("doc"))));
+    }
+}
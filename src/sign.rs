@@ -0,0 +1,117 @@
+use super::*;
+use fog_crypto::{
+    identity::{new_identity_key, Identity, IdentityKey, Signature, SignInterface, UnverifiedSignature},
+    lock::LockId,
+    lockbox::IdentityLockbox,
+    stream::StreamKey,
+    CryptoSrc,
+};
+use fog_pack::types::Hash;
+use std::{future::Future, sync::Arc};
+
+/// A signer that produces signatures asynchronously, like a remote signing service or hardware
+/// security module, for use with [`SignDocument::complete_async`] and
+/// [`SignEntry::complete_async`].
+pub trait AsyncSigner {
+    /// Sign `hash` as the Identity `signer`.
+    fn sign(
+        &self,
+        signer: &Identity,
+        hash: &Hash
+    ) -> impl Future<Output = Result<Signature, Box<dyn std::error::Error + Send + Sync>>> + Send;
+}
+
+/// Get a signature from an [`AsyncSigner`] and wrap it up as an [`IdentityKey`] that fog-pack can
+/// sign with. The signature is checked against `hash` and `signer` first.
+pub(crate) async fn presigned_key<S: AsyncSigner + ?Sized>(
+    async_signer: &S,
+    signer: &Identity,
+    hash: &Hash
+) -> Result<IdentityKey, ObjectError> {
+    let signature = async_signer.sign(signer, hash).await
+        .map_err(|err| ObjectError::Signer(Arc::from(err)))?;
+    if signature.signer() != signer {
+        return Err(ObjectError::IncorrectIdentityKey(Box::new(signer.clone())));
+    }
+    let mut raw = Vec::with_capacity(signature.size());
+    signature.encode_vec(&mut raw);
+    let signature = UnverifiedSignature::try_from(raw.as_slice())
+        .and_then(|unverified| unverified.verify(hash))
+        .map_err(|err| ObjectError::Signer(Arc::new(err)))?;
+    Ok(new_identity_key(Arc::new(Presigned { signature })))
+}
+
+/// A signing key that only has one signature to give, already made for the hash being signed.
+struct Presigned {
+    signature: Signature,
+}
+
+impl SignInterface for Presigned {
+    fn id(&self) -> &Identity {
+        self.signature.signer()
+    }
+
+    fn sign(&self, _hash: &Hash) -> Signature {
+        self.signature.clone()
+    }
+
+    fn self_export_lock(&self, _: &mut dyn CryptoSrc, _: &LockId) -> Option<IdentityLockbox> {
+        None
+    }
+
+    fn self_export_stream(&self, _: &mut dyn CryptoSrc, _: &StreamKey) -> Option<IdentityLockbox> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fog_pack::{document::NewDocument, schema::NoSchema};
+    use std::{pin::pin, task::{Context, Poll, Waker}};
+
+    /// Signs with a local key, standing in for a remote signing service.
+    struct Remote(IdentityKey);
+
+    impl AsyncSigner for Remote {
+        fn sign(
+            &self,
+            _signer: &Identity,
+            hash: &Hash
+        ) -> impl Future<Output = Result<Signature, Box<dyn std::error::Error + Send + Sync>>> + Send {
+            let signature = self.0.sign(hash);
+            async move { Ok(signature) }
+        }
+    }
+
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        let mut fut = pin!(fut);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+                return out;
+            }
+        }
+    }
+
+    #[test]
+    fn complete_async() {
+        let key = IdentityKey::new_temp(&mut rand::rngs::OsRng);
+        let new_doc = NewDocument::new(None, "signed").unwrap().sign(&key).unwrap();
+        let doc = NoSchema::validate_new_doc(new_doc).unwrap();
+        let json = doc_to_json(&doc);
+        let sign = |json| match json_to_doc(json).unwrap() {
+            MaybeDocument::SignDocument(sign) => sign,
+            MaybeDocument::NewDocument(_) => panic!("Document should have needed signing"),
+        };
+
+        let signed = block_on(sign(&json).complete_async(&Remote(key))).unwrap();
+        assert_eq!(signed.hash(), doc.hash());
+        let signed = NoSchema::validate_new_doc(signed).unwrap();
+        assert_eq!(signed.signer(), doc.signer());
+
+        let other = Remote(IdentityKey::new_temp(&mut rand::rngs::OsRng));
+        let err = block_on(sign(&json).complete_async(&other));
+        assert!(matches!(err, Err(ObjectError::IncorrectIdentityKey(_))));
+    }
+}
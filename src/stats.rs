@@ -0,0 +1,17 @@
+use std::time::Duration;
+
+/// Statistics gathered while converting between fog-pack and JSON.
+///
+/// Returned by the `_with_stats` variants of the conversion functions, for monitoring and tuning
+/// conversion workloads.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConvertStats {
+    /// Number of values visited, including every array, map, and value inside them.
+    pub nodes: u64,
+    /// Number of binary bytes encoded to or decoded from Base64, including lockboxes.
+    pub base64_bytes: u64,
+    /// Number of `$fog-TYPE:` strings emitted while encoding, or parsed while decoding.
+    pub annotations: u64,
+    /// Total time spent on the conversion.
+    pub elapsed: Duration,
+}
@@ -0,0 +1,143 @@
+use super::*;
+use std::io;
+use serde_json::de::{IoRead, StreamDeserializer};
+
+/// An error from converting one item in a stream of JSON values.
+#[derive(Debug, Error)]
+pub enum StreamError {
+    /// The stream didn't contain valid JSON. No further items can be read after this.
+    #[error("Invalid JSON in stream")]
+    Json(#[from] serde_json::Error),
+    /// The JSON value was read, but couldn't be converted
+    #[error("Couldn't convert JSON value from stream")]
+    Object(#[from] ObjectError),
+}
+
+/// An iterator that reads a stream of concatenated or whitespace-separated JSON values and
+/// converts each one into a [`MaybeDocument`], as with [`json_to_doc_with_options`].
+///
+/// Conversion failures are reported per-item, and reading continues with the next value. Invalid
+/// JSON ends the stream, as there's no way to find where the next value begins.
+pub struct DocStream<R: io::Read> {
+    inner: StreamDeserializer<'static, IoRead<R>, JsonValue>,
+    opts: DecodeOptions,
+}
+
+impl<R: io::Read> DocStream<R> {
+    /// Read documents from a stream of JSON values.
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, DecodeOptions::default())
+    }
+
+    /// Read documents from a stream of JSON values, using the provided decoding options for each 
+    /// one.
+    pub fn with_options(reader: R, opts: DecodeOptions) -> Self {
+        Self { inner: serde_json::Deserializer::from_reader(reader).into_iter(), opts }
+    }
+
+    /// Get the number of bytes read from the stream so far.
+    pub fn byte_offset(&self) -> usize {
+        self.inner.byte_offset()
+    }
+}
+
+impl<R: io::Read> Iterator for DocStream<R> {
+    type Item = Result<MaybeDocument, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let json = match self.inner.next()? {
+            Ok(json) => json,
+            Err(e) => return Some(Err(e.into())),
+        };
+        Some(json_to_doc_with_options(&json, &self.opts).map_err(StreamError::from))
+    }
+}
+
+/// An iterator that reads a stream of concatenated or whitespace-separated JSON values and
+/// parses each one into a [`JsonEntry`], as with [`JsonEntry::from_json_with_options`].
+///
+/// Conversion failures are reported per-item, and reading continues with the next value. Invalid
+/// JSON ends the stream, as there's no way to find where the next value begins.
+pub struct EntryStream<R: io::Read> {
+    inner: StreamDeserializer<'static, IoRead<R>, JsonValue>,
+    opts: DecodeOptions,
+}
+
+impl<R: io::Read> EntryStream<R> {
+    /// Read entries from a stream of JSON values.
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, DecodeOptions::default())
+    }
+
+    /// Read entries from a stream of JSON values, using the provided decoding options for each 
+    /// one.
+    pub fn with_options(reader: R, opts: DecodeOptions) -> Self {
+        Self { inner: serde_json::Deserializer::from_reader(reader).into_iter(), opts }
+    }
+
+    /// Get the number of bytes read from the stream so far.
+    pub fn byte_offset(&self) -> usize {
+        self.inner.byte_offset()
+    }
+}
+
+impl<R: io::Read> Iterator for EntryStream<R> {
+    type Item = Result<JsonEntry, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let json = match self.inner.next()? {
+            Ok(json) => json,
+            Err(e) => return Some(Err(e.into())),
+        };
+        Some(JsonEntry::from_json_with_options(&json, &self.opts).map_err(StreamError::from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doc_stream() {
+        let input = r#"{"data": 1}
+            {"data": "$fog-Bad:1"}
+            {"data": [1, 2]}{"data": null}"#;
+        let results: Vec<_> = DocStream::new(input.as_bytes()).collect();
+        assert_eq!(results.len(), 4);
+        assert!(matches!(results[0], Ok(MaybeDocument::NewDocument(_))));
+        assert!(matches!(results[1], Err(StreamError::Object(_))));
+        assert!(matches!(results[2], Ok(MaybeDocument::NewDocument(_))));
+        assert!(matches!(results[3], Ok(MaybeDocument::NewDocument(_))));
+    }
+
+    #[test]
+    fn doc_stream_options() {
+        let input = r#"{"data": 1, "$schema": "x.json"} {"data": 2}"#;
+        let results: Vec<_> = DocStream::new(input.as_bytes()).collect();
+        assert!(matches!(results[0], Err(StreamError::Object(_))));
+        let opts = DecodeOptions::new().ignore_editor_keys(true);
+        let results: Vec<_> = DocStream::with_options(input.as_bytes(), opts).collect();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.is_ok()));
+    }
+
+    #[test]
+    fn entry_stream() {
+        let parent = fog_crypto::hash::Hash::new(b"parent");
+        let input = format!(
+            r#"{{"data": 1, "key": "a", "parent": "$fog-Hash:{parent}"}}
+            {{"data": 2, "key": "b"}}
+            {{"data": [1, 2], "key": "c", "parent": "$fog-Hash:{parent}"}}"#
+        );
+        let stream = EntryStream::new(input.as_bytes());
+        let results: Vec<_> = stream.collect();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(StreamError::Object(_))));
+        assert!(results[2].is_ok());
+
+        let mut stream = EntryStream::new(r#"{"data": 1} ]"#.as_bytes());
+        assert!(matches!(stream.next(), Some(Err(StreamError::Object(_)))));
+        assert!(matches!(stream.next(), Some(Err(StreamError::Json(_)))));
+    }
+}
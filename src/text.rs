@@ -0,0 +1,501 @@
+use super::*;
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::de::{IoRead, StrRead};
+use std::{fmt, io};
+
+/// Convert JSON text to a fog-pack value.
+///
+/// The text is parsed and converted in one pass, without building a JSON Value first. If it fails, 
+/// the error is a [`DecodeError::Located`] giving the line and column where the problem was found 
+/// and a JSON Pointer to the value being converted.
+///
+/// Unlike parsing the text into a JSON Value first, which silently keeps only the last of any 
+/// duplicated object keys, this fails with [`DecodeError::DuplicateKey`] if an object has the 
+/// same key more than once.
+pub fn json_str_to_fog(json: &str) -> Result<FogValue, DecodeError> {
+    json_str_to_fog_with_options(json, &DecodeOptions::default())
+}
+
+/// Convert JSON text to a fog-pack value, as with [`json_str_to_fog`], using the provided 
+/// decoding options.
+pub fn json_str_to_fog_with_options(json: &str, opts: &DecodeOptions) -> Result<FogValue, DecodeError> {
+    parse(StrRead::new(json), &mut Decoder::new(opts), |state, de| {
+        FogSeed { state, is_root: true }.deserialize(de)
+    })
+}
+
+/// Read JSON text from a reader and convert it to a fog-pack value, as with [`json_str_to_fog`].
+///
+/// The text is converted as it's read, so large inputs never need to be held in memory as text or 
+/// as a JSON Value. Reads are unbuffered, so wrap the reader in a [`BufReader`][io::BufReader] if 
+/// it isn't already buffered.
+pub fn json_reader_to_fog<R: io::Read>(reader: R) -> Result<FogValue, DecodeError> {
+    json_reader_to_fog_with_options(reader, &DecodeOptions::default())
+}
+
+/// Read JSON text from a reader and convert it to a fog-pack value, as with 
+/// [`json_reader_to_fog`], using the provided decoding options.
+pub fn json_reader_to_fog_with_options<R: io::Read>(
+    reader: R,
+    opts: &DecodeOptions
+) -> Result<FogValue, DecodeError> {
+    parse(IoRead::new(reader), &mut Decoder::new(opts), |state, de| {
+        FogSeed { state, is_root: true }.deserialize(de)
+    })
+}
+
+/// Convert JSON text with comments (JSONC) to a fog-pack value.
+///
+/// `//` line comments and `/* */` block comments outside of strings are removed with 
+/// [`strip_json_comments`], and the rest is converted as with [`json_str_to_fog`]. Line and column 
+/// numbers in errors still match the original text.
+pub fn jsonc_to_fog(jsonc: &str) -> Result<FogValue, DecodeError> {
+    jsonc_to_fog_with_options(jsonc, &DecodeOptions::default())
+}
+
+/// Convert JSON text with comments to a fog-pack value, as with [`jsonc_to_fog`], using the 
+/// provided decoding options.
+pub fn jsonc_to_fog_with_options(jsonc: &str, opts: &DecodeOptions) -> Result<FogValue, DecodeError> {
+    json_str_to_fog_with_options(&strip_json_comments(jsonc), opts)
+}
+
+/// Blank out `//` line comments and `/* */` block comments that aren't inside strings, leaving 
+/// plain JSON text.
+///
+/// Comments are replaced with spaces, keeping their line breaks, so positions in the result line 
+/// up with the original text. An unterminated block comment is left in place for the JSON parser 
+/// to reject.
+///
+/// ```
+/// # use fog_human_json::*;
+/// let text = strip_json_comments("{\"a\": 1, // Note\n \"b\": \"//\"}");
+/// assert_eq!(text, "{\"a\": 1,        \n \"b\": \"//\"}");
+/// ```
+pub fn strip_json_comments(jsonc: &str) -> String {
+    let mut text = jsonc.as_bytes().to_vec();
+    let mut in_string = false;
+    let mut i = 0;
+    while i < text.len() {
+        if in_string {
+            match text[i] {
+                b'\\' => i += 1,
+                b'"' => in_string = false,
+                _ => (),
+            }
+            i += 1;
+            continue;
+        }
+        let end = match (text[i], text.get(i + 1)) {
+            (b'"', _) => {
+                in_string = true;
+                i += 1;
+                continue;
+            },
+            (b'/', Some(b'/')) => text[i..].iter().position(|&c| c == b'\n').map_or(text.len(), |p| i + p),
+            (b'/', Some(b'*')) => match text[i + 2..].windows(2).position(|w| w == b"*/") {
+                Some(p) => i + p + 4,
+                None => break,
+            },
+            _ => {
+                i += 1;
+                continue;
+            },
+        };
+        for c in text[i..end].iter_mut().filter(|c| !c.is_ascii_whitespace()) {
+            *c = b' ';
+        }
+        i = end;
+    }
+    // Only whole comments were replaced, and only with ASCII, so this is still UTF-8
+    String::from_utf8(text).expect("Comments should be replaced with whole characters")
+}
+
+/// Convert JSON5 text to a fog-pack value.
+///
+/// JSON5 allows comments, trailing commas, unquoted keys, single-quoted strings, and more, which 
+/// makes it friendlier for hand-written values. Once parsed, values are converted exactly as with 
+/// [`json_str_to_fog`], including failing on duplicate keys. JSON5's `NaN` and `Infinity` 
+/// numbers become F64 values.
+#[cfg(feature = "json5")]
+pub fn json5_to_fog(json5: &str) -> Result<FogValue, DecodeError> {
+    json5_to_fog_with_options(json5, &DecodeOptions::default())
+}
+
+/// Convert JSON5 text to a fog-pack value, as with [`json5_to_fog`], using the provided decoding 
+/// options.
+#[cfg(feature = "json5")]
+pub fn json5_to_fog_with_options(json5: &str, opts: &DecodeOptions) -> Result<FogValue, DecodeError> {
+    parse_json5(json5, &mut Decoder::new(opts), |state, de| {
+        FogSeed { state, is_root: true }.deserialize(de)
+    })
+}
+
+/// Read the envelope of a Document, Entry, or Query from a reader, converting the value under 
+/// `data_key` straight to fog-pack and keeping the rest as JSON.
+pub(crate) fn read_envelope<R: io::Read>(
+    reader: R,
+    dec: &mut Decoder,
+    data_key: &'static str,
+) -> Result<(JsonMap, Option<FogValue>), ObjectError> {
+    let res = parse(IoRead::new(reader), dec, |state, de| {
+        EnvelopeSeed { state, data_key }.deserialize(de)
+    });
+    res.map_err(|err| envelope_error(err, data_key))
+}
+
+/// Read the envelope of a Document, Entry, or Query from JSON5 text, as with [`read_envelope`].
+#[cfg(feature = "json5")]
+pub(crate) fn read_json5_envelope(
+    json5: &str,
+    dec: &mut Decoder,
+    data_key: &'static str,
+) -> Result<(JsonMap, Option<FogValue>), ObjectError> {
+    let res = parse_json5(json5, dec, |state, de| {
+        EnvelopeSeed { state, data_key }.deserialize(de)
+    });
+    res.map_err(|err| envelope_error(err, data_key))
+}
+
+/// Blame an envelope error on the data value if it happened there, and on the envelope otherwise.
+fn envelope_error(err: DecodeError, data_key: &'static str) -> ObjectError {
+    match &err {
+        DecodeError::Located { path, .. }
+            if path.strip_prefix('/').is_some_and(|p| p.split('/').next() == Some(data_key)) =>
+        {
+            ObjectError::Decode { key: data_key, src: err }
+        },
+        _ => ObjectError::Parse(err),
+    }
+}
+
+/// Parse and convert JSON text, making sure nothing but whitespace follows the value.
+fn parse<'de, R: serde_json::de::Read<'de>, T>(
+    read: R,
+    dec: &mut Decoder,
+    convert: impl FnOnce(&mut ParseState, &mut serde_json::Deserializer<R>) -> Result<T, serde_json::Error>,
+) -> Result<T, DecodeError> {
+    let mut state = ParseState { path: Vec::new(), error: None, dec };
+    let mut de = serde_json::Deserializer::new(read);
+    let val = convert(&mut state, &mut de).and_then(|val| de.end().map(|_| val));
+    val.map_err(|err| {
+        // The position is reported separately, so drop it from the message
+        let message = err.to_string();
+        let suffix = format!(" at line {} column {}", err.line(), err.column());
+        let message = message.strip_suffix(&suffix).unwrap_or(&message).to_owned();
+        state.located(err.line(), err.column(), message)
+    })
+}
+
+/// Parse and convert JSON5 text.
+#[cfg(feature = "json5")]
+fn parse_json5<'de, T>(
+    json5: &'de str,
+    dec: &mut Decoder,
+    convert: impl FnOnce(&mut ParseState, &mut json5::Deserializer<'de>) -> Result<T, json5::Error>,
+) -> Result<T, DecodeError> {
+    let mut state = ParseState { path: Vec::new(), error: None, dec };
+    let val = json5::Deserializer::from_str(json5).and_then(|mut de| convert(&mut state, &mut de));
+    val.map_err(|err| {
+        let json5::Error::Message { msg, location } = err;
+        let (line, column) = location.map_or((0, 0), |l| (l.line, l.column));
+        // Syntax errors quote the offending line, but the position is reported separately
+        let message = msg.rsplit_once("\n  = ").map_or(msg.as_str(), |(_, m)| m).to_owned();
+        state.located(line, column, message)
+    })
+}
+
+/// The key serde_json uses to pass numbers through a map when `arbitrary_precision` is enabled.
+#[cfg(feature = "arbitrary_precision")]
+const NUMBER_TOKEN: &str = "$serde_json::private::Number";
+
+/// Where the parser currently is, and the first conversion error found.
+struct ParseState<'d, 'a> {
+    path: Vec<String>,
+    /// The error, along with a JSON Pointer to where it happened
+    error: Option<(DecodeError, String)>,
+    dec: &'d mut Decoder<'a>,
+}
+
+impl ParseState<'_, '_> {
+    /// The current location, as a JSON Pointer.
+    fn pointer(&self) -> String {
+        let mut pointer = String::new();
+        for segment in self.path.iter() {
+            dec::push_pointer_segment(&mut pointer, segment);
+        }
+        pointer
+    }
+
+    /// Record a conversion error at the current location, returning a parser error that stops 
+    /// parsing.
+    fn fail<E: de::Error>(&mut self, err: DecodeError) -> E {
+        let message = err.to_string();
+        self.error = Some((err, self.pointer()));
+        E::custom(message)
+    }
+
+    /// Turn the error that stopped parsing into a located conversion error. The parser's message 
+    /// is only used if it wasn't a conversion error.
+    fn located(&mut self, line: usize, column: usize, message: String) -> DecodeError {
+        let (inner, path) = self.error.take()
+            .unwrap_or_else(|| (DecodeError::InvalidJson(message), self.pointer()));
+        DecodeError::Located { line, column, path, err: Box::new(inner) }
+    }
+}
+
+/// Reads the envelope of a Document, Entry, or Query.
+struct EnvelopeSeed<'s, 'd, 'a> {
+    state: &'s mut ParseState<'d, 'a>,
+    data_key: &'static str,
+}
+
+impl<'de> DeserializeSeed<'de> for EnvelopeSeed<'_, '_, '_> {
+    type Value = (JsonMap, Option<FogValue>);
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for EnvelopeSeed<'_, '_, '_> {
+    type Value = (JsonMap, Option<FogValue>);
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a root Object for Doc/Entry/Query conversion")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut obj = JsonMap::new();
+        let mut data = None;
+        while let Some(key) = map.next_key::<String>()? {
+            if obj.contains_key(&key) || (key == self.data_key && data.is_some()) {
+                return Err(self.state.fail(DecodeError::DuplicateKey { key, path: String::new() }));
+            }
+            if key == self.data_key {
+                self.state.path.push(key);
+                data = Some(map.next_value_seed(FogSeed { state: &mut *self.state, is_root: true })?);
+                self.state.path.pop();
+            }
+            else {
+                obj.insert(key, map.next_value()?);
+            }
+        }
+        Ok((obj, data))
+    }
+}
+
+/// Converts a value to fog-pack as it's parsed.
+struct FogSeed<'s, 'd, 'a> {
+    state: &'s mut ParseState<'d, 'a>,
+    /// Whether this is the root value, which may have editor keys to skip
+    is_root: bool,
+}
+
+impl<'de> DeserializeSeed<'de> for FogSeed<'_, '_, '_> {
+    type Value = FogValue;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<FogValue, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for FogSeed<'_, '_, '_> {
+    type Value = FogValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any JSON value")
+    }
+
+    fn visit_unit<E>(self) -> Result<FogValue, E> {
+        Ok(FogValue::Null)
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<FogValue, E> {
+        Ok(FogValue::Bool(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<FogValue, E> {
+        Ok(FogValue::Int(fog_pack::types::Integer::from(v)))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<FogValue, E> {
+        Ok(FogValue::Int(fog_pack::types::Integer::from(v)))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<FogValue, E> {
+        Ok(FogValue::F64(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<FogValue, E> {
+        let res = self.state.dec.string(v);
+        res.map_err(|err| self.state.fail(err))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<FogValue, A::Error> {
+        let res = self.state.dec.check_depth();
+        res.map_err(|err| self.state.fail(err))?;
+        self.state.dec.depth += 1;
+        let mut array = Vec::new();
+        loop {
+            self.state.path.push(array.len().to_string());
+            let next = seq.next_element_seed(FogSeed { state: &mut *self.state, is_root: false })?;
+            self.state.path.pop();
+            let Some(val) = next else { break };
+            array.push(val);
+            let res = self.state.dec.check_len(array.len());
+            res.map_err(|err| self.state.fail(err))?;
+        }
+        self.state.dec.depth -= 1;
+        Ok(FogValue::Array(array))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<FogValue, A::Error> {
+        let opts = self.state.dec.opts;
+        let mut first = map.next_key::<String>()?;
+        // With arbitrary precision, serde_json hands over numbers as a map with a single private key
+        #[cfg(feature = "arbitrary_precision")]
+        if first.as_deref() == Some(NUMBER_TOKEN) {
+            let number: String = map.next_value()?;
+            let res = number.parse::<JsonNumber>()
+                .map_err(|_| DecodeError::InvalidJson(format!("invalid number {}", number)))
+                .and_then(|n| self.state.dec.value_inner(&JsonValue::Number(n)));
+            return res.map_err(|err| self.state.fail(err));
+        }
+        let res = self.state.dec.check_depth();
+        res.map_err(|err| self.state.fail(err))?;
+        self.state.dec.depth += 1;
+        let mut obj = BTreeMap::new();
+        // The value of a `{"$fog-Bin": ...}` wrapper, held until we know it's the only key
+        let mut bin: Option<(String, JsonValue)> = None;
+        while let Some(key) = match first.take() {
+            Some(key) => Some(key),
+            None => map.next_key::<String>()?,
+        } {
+            if self.is_root && opts.skip_key(&key) {
+                map.next_value::<IgnoredAny>()?;
+                continue;
+            }
+            if obj.contains_key(&key) || bin.as_ref().is_some_and(|(k, _)| *k == key) {
+                let path = self.state.pointer();
+                return Err(self.state.fail(DecodeError::DuplicateKey { key, path }));
+            }
+            let res = self.state.dec.add_string(key.len())
+                .and_then(|_| self.state.dec.check_len(obj.len() + 1 + usize::from(bin.is_some())));
+            res.map_err(|err| self.state.fail(err))?;
+            if obj.is_empty() && bin.is_none() && opts.is_bin_key(&key) {
+                bin = Some((key, map.next_value()?));
+                continue;
+            }
+            if let Some((k, v)) = bin.take() {
+                // Not a wrapper after all, so convert the held value like any other
+                self.state.path.push(k);
+                let res = self.state.dec.value(&v);
+                let v = res.map_err(|err| self.state.fail(err))?;
+                obj.insert(self.state.path.pop().unwrap(), v);
+            }
+            self.state.path.push(key);
+            let val = map.next_value_seed(FogSeed { state: &mut *self.state, is_root: false })?;
+            obj.insert(self.state.path.pop().unwrap(), val);
+        }
+        self.state.dec.depth -= 1;
+        match bin {
+            Some((_, v)) => {
+                let res = self.state.dec.bin_wrapper(&v);
+                res.map(FogValue::Bin).map_err(|err| self.state.fail(err))
+            },
+            None => Ok(FogValue::Map(obj)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_keys() {
+        let text = r#"{"a": 1, "b~/": [{"c": "$fog-Int:1"}, {"c": 2, "c": 3}]}"#;
+        let err = json_str_to_fog(text).unwrap_err();
+        let DecodeError::Located { err, .. } = err else { panic!("Error should have a location") };
+        assert!(matches!(*err, DecodeError::DuplicateKey { key, path } if key == "c" && path == "/b~0~1/1"));
+        assert!(serde_json::from_str::<JsonValue>(text).is_ok());
+
+        let opts = DecodeOptions::new().bin_wrapper(true);
+        let same = |text: &str| {
+            let json = serde_json::from_str(text).unwrap();
+            assert_eq!(json_str_to_fog_with_options(text, &opts).unwrap(), json_to_fog_with_options(&json, &opts).unwrap());
+            assert_eq!(json_str_to_fog(text).unwrap(), json_to_fog(&json).unwrap());
+        };
+        same(r#"{"a": [1, "$fog-Int:2", {"b": null}], "c": 1.5, "d": {"$fog-Bin": "AAEC"}}"#);
+        same(r#"{"$fog-Bin": [1, 2], "a": "$fog-Int:3"}"#);
+    }
+
+    #[test]
+    fn located() {
+        let text = "{\n  \"a\": [\n    1,\n    \"$fog-Int:x\"\n  ]\n}";
+        let err = json_str_to_fog(text).unwrap_err();
+        let DecodeError::Located { line, column, path, err } = err else { panic!("Error should have a location") };
+        assert_eq!((line, column, path.as_str()), (4, 16, "/a/1"));
+        assert!(matches!(*err, DecodeError::InvalidInteger));
+
+        let err = json_str_to_fog("[1,\n{\"b\": 2").unwrap_err();
+        let DecodeError::Located { line, path, err, .. } = err else { panic!("Error should have a location") };
+        assert_eq!((line, path.as_str()), (2, "/1"));
+        assert!(matches!(*err, DecodeError::InvalidJson(msg) if !msg.contains("line")));
+        assert!(json_str_to_fog("1 2").is_err());
+    }
+
+    #[test]
+    fn json_reader_to_fog() {
+        let text = r#"{"a": [1, "$fog-Int:2", {"$fog-Bin": "AAEC"}], "$schema": "x"}"#;
+        let expected = json_str_to_fog(text).unwrap();
+        assert_eq!(super::json_reader_to_fog(text.as_bytes()).unwrap(), expected);
+        let opts = DecodeOptions::new().ignore_editor_keys(true);
+        let expected = json_str_to_fog_with_options(text, &opts).unwrap();
+        assert_eq!(super::json_reader_to_fog_with_options(text.as_bytes(), &opts).unwrap(), expected);
+        assert!(matches!(super::json_reader_to_fog(&b"[1,\n2,,]"[..]), Err(DecodeError::Located { line: 2, .. })));
+    }
+
+    #[test]
+    fn jsonc_to_fog() {
+        let text = "{\n  // The \"list\" /* isn't */ done\n  \"a\": [1, /* two: */ \"$fog-Int:2\"],\n  /* Multi-\n   * line ñ */\n  \"b\": \"/* kept */ // \\\" kept\"\n}";
+        let expected = json_str_to_fog(r#"{"a": [1, "$fog-Int:2"], "b": "/* kept */ // \" kept"}"#);
+        assert_eq!(super::jsonc_to_fog(text).unwrap(), expected.unwrap());
+        assert_eq!(strip_json_comments(text).lines().count(), text.lines().count());
+
+        let err = super::jsonc_to_fog("// Header\n[1,\n /* x */ \"$fog-Int:x\"]").unwrap_err();
+        assert!(matches!(err, DecodeError::Located { line: 3, column: 21, .. }));
+        assert!(super::jsonc_to_fog("[1] /* unterminated").is_err());
+    }
+
+    #[cfg(feature = "json5")]
+    #[test]
+    fn json5_to_fog() {
+        let text = "{\n  // A comment\n  a: [1, '$fog-Int:2', {'$fog-Bin': 'AAEC'},],\n  \"b\": Infinity,\n}";
+        let expected = json_str_to_fog(r#"{"a": [1, "$fog-Int:2", {"$fog-Bin": "AAEC"}], "b": "$fog-F64Hex:7ff0000000000000"}"#);
+        assert_eq!(super::json5_to_fog(text).unwrap(), expected.unwrap());
+
+        let err = super::json5_to_fog("{\n  a: [1,\n    '$fog-Int:x']}").unwrap_err();
+        let DecodeError::Located { line, path, err, .. } = err else { panic!("Error should have a location") };
+        assert_eq!((line, path.as_str()), (3, "/a/1"));
+        assert!(matches!(*err, DecodeError::InvalidInteger));
+        assert!(matches!(super::json5_to_fog("{a: 1, a: 2}"), Err(DecodeError::Located { .. })));
+        let err = super::json5_to_fog("[1,,]").unwrap_err();
+        assert!(matches!(err, DecodeError::Located { line: 1, column: 4, err, .. } if matches!(&*err, DecodeError::InvalidJson(msg) if msg.starts_with("expected"))));
+    }
+
+    #[cfg(feature = "arbitrary_precision")]
+    #[test]
+    fn arbitrary_precision() {
+        let text = format!("[{}, {}, 1.5, {{\"a\": [0.1]}}]", u64::MAX, i64::MIN);
+        assert_eq!(json_str_to_fog(&text).unwrap(), json_to_fog(&serde_json::from_str(&text).unwrap()).unwrap());
+        assert_eq!(serde_json::to_string(&fog_to_json(&FogValue::from(u64::MAX))).unwrap(), u64::MAX.to_string());
+
+        let big = "[123456789012345678901234567890]";
+        let err = json_str_to_fog(big).unwrap_err();
+        assert_eq!(err.pointer(), "/0");
+        assert!(matches!(err.innermost(), DecodeError::InvalidInteger));
+        assert!(json_to_fog(&serde_json::from_str(big).unwrap()).is_err());
+        assert!(matches!(json_to_fog(&serde_json::from_str("1e400").unwrap()), Err(DecodeError::InvalidFloat)));
+    }
+}
@@ -0,0 +1,534 @@
+use super::*;
+use fog_crypto::serde::*;
+use fog_pack::{error::Error, types::*};
+use serde::{
+    de::{
+        self,
+        value::{
+            BorrowedBytesDeserializer, BorrowedStrDeserializer, BytesDeserializer, MapDeserializer,
+            SeqDeserializer, U64Deserializer,
+        },
+        IntoDeserializer,
+    },
+    ser::{self, Serialize},
+};
+
+/// Serialize a value straight into a fog-pack value, encoding it exactly as fog-pack itself would
+/// without going through an encoded Document first.
+pub(crate) fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<FogValue, Error> {
+    value.serialize(ValueSerializer)
+}
+
+/// Deserialize a value straight from a fog-pack value, reading it exactly as fog-pack itself would
+/// from an encoded Document.
+pub(crate) fn from_value<'de, T: de::Deserialize<'de>>(value: &'de FogValue) -> Result<T, Error> {
+    T::deserialize(ValueDeserializer(value))
+}
+
+fn fail(msg: impl Into<String>) -> Error {
+    Error::SerdeFail(msg.into())
+}
+
+/// Wrap up a non-unit enum variant the way fog-pack does: as a map with a single key.
+fn variant_map(variant: &str, value: FogValue) -> FogValue {
+    FogValue::Map(BTreeMap::from([(variant.to_owned(), value)]))
+}
+
+/// Build one of the fog-pack extension types from its variant index and encoded bytes.
+fn ext_value(index: u64, bytes: &[u8]) -> Result<FogValue, Error> {
+    let crypto = |e: fog_crypto::CryptoError| fail(e.to_string());
+    Ok(match index {
+        FOG_TYPE_ENUM_TIME_INDEX => FogValue::Timestamp(Timestamp::try_from(bytes).map_err(fail)?),
+        FOG_TYPE_ENUM_HASH_INDEX => FogValue::Hash(Hash::try_from(bytes).map_err(crypto)?),
+        FOG_TYPE_ENUM_IDENTITY_INDEX => FogValue::Identity(Identity::try_from(bytes).map_err(crypto)?),
+        FOG_TYPE_ENUM_LOCK_ID_INDEX => FogValue::LockId(LockId::try_from(bytes).map_err(crypto)?),
+        FOG_TYPE_ENUM_STREAM_ID_INDEX => FogValue::StreamId(StreamId::try_from(bytes).map_err(crypto)?),
+        FOG_TYPE_ENUM_DATA_LOCKBOX_INDEX =>
+            FogValue::DataLockbox(DataLockboxRef::from_bytes(bytes).map_err(crypto)?.to_owned()),
+        FOG_TYPE_ENUM_IDENTITY_LOCKBOX_INDEX =>
+            FogValue::IdentityLockbox(IdentityLockboxRef::from_bytes(bytes).map_err(crypto)?.to_owned()),
+        FOG_TYPE_ENUM_STREAM_LOCKBOX_INDEX =>
+            FogValue::StreamLockbox(StreamLockboxRef::from_bytes(bytes).map_err(crypto)?.to_owned()),
+        FOG_TYPE_ENUM_LOCK_LOCKBOX_INDEX =>
+            FogValue::LockLockbox(LockLockboxRef::from_bytes(bytes).map_err(crypto)?.to_owned()),
+        _ => return Err(fail(format!("unknown fog-pack extension type {}", index))),
+    })
+}
+
+struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = FogValue;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = VariantSerializer<SeqSerializer>;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = VariantSerializer<MapSerializer>;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<FogValue, Error> { Ok(FogValue::Bool(v)) }
+    fn serialize_i8(self, v: i8) -> Result<FogValue, Error> { Ok(FogValue::Int(v.into())) }
+    fn serialize_i16(self, v: i16) -> Result<FogValue, Error> { Ok(FogValue::Int(v.into())) }
+    fn serialize_i32(self, v: i32) -> Result<FogValue, Error> { Ok(FogValue::Int(v.into())) }
+    fn serialize_i64(self, v: i64) -> Result<FogValue, Error> { Ok(FogValue::Int(v.into())) }
+    fn serialize_u8(self, v: u8) -> Result<FogValue, Error> { Ok(FogValue::Int(v.into())) }
+    fn serialize_u16(self, v: u16) -> Result<FogValue, Error> { Ok(FogValue::Int(v.into())) }
+    fn serialize_u32(self, v: u32) -> Result<FogValue, Error> { Ok(FogValue::Int(v.into())) }
+    fn serialize_u64(self, v: u64) -> Result<FogValue, Error> { Ok(FogValue::Int(v.into())) }
+    fn serialize_f32(self, v: f32) -> Result<FogValue, Error> { Ok(FogValue::F32(v)) }
+    fn serialize_f64(self, v: f64) -> Result<FogValue, Error> { Ok(FogValue::F64(v)) }
+    fn serialize_char(self, v: char) -> Result<FogValue, Error> { Ok(FogValue::Str(v.to_string())) }
+    fn serialize_str(self, v: &str) -> Result<FogValue, Error> { Ok(FogValue::Str(v.to_owned())) }
+    fn serialize_bytes(self, v: &[u8]) -> Result<FogValue, Error> { Ok(FogValue::Bin(v.to_vec())) }
+    fn serialize_none(self) -> Result<FogValue, Error> { Ok(FogValue::Null) }
+    fn serialize_unit(self) -> Result<FogValue, Error> { Ok(FogValue::Null) }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<FogValue, Error> { Ok(FogValue::Null) }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<FogValue, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str
+    ) -> Result<FogValue, Error> {
+        Ok(FogValue::Str(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T
+    ) -> Result<FogValue, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T
+    ) -> Result<FogValue, Error> {
+        let value = value.serialize(ValueSerializer)?;
+        if name != FOG_TYPE_ENUM {
+            return Ok(variant_map(variant, value));
+        }
+        match value {
+            FogValue::Bin(bytes) => ext_value(index as u64, &bytes),
+            _ => Err(fail(format!("{} variant {} must hold bytes", FOG_TYPE_ENUM, variant))),
+        }
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer(Vec::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, len: usize) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        len: usize
+    ) -> Result<VariantSerializer<SeqSerializer>, Error> {
+        Ok(VariantSerializer { variant, inner: self.serialize_seq(Some(len))? })
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer { map: BTreeMap::new(), key: None })
+    }
+
+    fn serialize_struct(self, _: &'static str, len: usize) -> Result<MapSerializer, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        len: usize
+    ) -> Result<VariantSerializer<MapSerializer>, Error> {
+        Ok(VariantSerializer { variant, inner: self.serialize_map(Some(len))? })
+    }
+}
+
+struct SeqSerializer(Vec<FogValue>);
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = FogValue;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.0.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<FogValue, Error> {
+        Ok(FogValue::Array(self.0))
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = FogValue;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<FogValue, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = FogValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<FogValue, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+struct MapSerializer {
+    map: BTreeMap<String, FogValue>,
+    key: Option<String>,
+}
+
+impl MapSerializer {
+    fn insert(&mut self, key: String, value: FogValue) -> Result<(), Error> {
+        if self.map.contains_key(&key) {
+            return Err(fail(format!("repeated map key \"{}\"", key)));
+        }
+        self.map.insert(key, value);
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = FogValue;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        match key.serialize(ValueSerializer)? {
+            FogValue::Str(key) => {
+                self.key = Some(key);
+                Ok(())
+            },
+            _ => Err(fail("map keys must be strings")),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().ok_or_else(|| fail("map value serialized before its key"))?;
+        let value = value.serialize(ValueSerializer)?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<FogValue, Error> {
+        Ok(FogValue::Map(self.map))
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = FogValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        let value = value.serialize(ValueSerializer)?;
+        self.insert(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<FogValue, Error> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+struct VariantSerializer<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<SeqSerializer> {
+    type Ok = FogValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(&mut self.inner, value)
+    }
+
+    fn end(self) -> Result<FogValue, Error> {
+        Ok(variant_map(self.variant, ser::SerializeSeq::end(self.inner)?))
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<MapSerializer> {
+    type Ok = FogValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> Result<FogValue, Error> {
+        Ok(variant_map(self.variant, ser::SerializeMap::end(self.inner)?))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ValueDeserializer<'de>(&'de FogValue);
+
+impl<'de> IntoDeserializer<'de, Error> for ValueDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = Error;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            FogValue::Null => visitor.visit_unit(),
+            FogValue::Bool(v) => visitor.visit_bool(*v),
+            FogValue::Int(v) => match v.as_u64() {
+                Some(v) => visitor.visit_u64(v),
+                None => visitor.visit_i64(v.as_i64().expect("negative integers always fit in an i64")),
+            },
+            FogValue::Str(v) => visitor.visit_borrowed_str(v),
+            FogValue::F32(v) => visitor.visit_f32(*v),
+            FogValue::F64(v) => visitor.visit_f64(*v),
+            FogValue::Bin(v) => visitor.visit_borrowed_bytes(v),
+            FogValue::Array(v) => {
+                let mut seq = SeqDeserializer::new(v.iter().map(ValueDeserializer));
+                let val = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(val)
+            },
+            FogValue::Map(v) => {
+                let mut map = MapDeserializer::new(v.iter().map(|(k, v)| (k.as_str(), ValueDeserializer(v))));
+                let val = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(val)
+            },
+            _ => visitor.visit_enum(ExtAccess(self.0)),
+        }
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            FogValue::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            FogValue::Null => visitor.visit_unit(),
+            _ => Err(fail("expected a null")),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        _: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            FogValue::Str(v) if name != FOG_TYPE_ENUM => {
+                visitor.visit_enum(BorrowedStrDeserializer::new(v))
+            },
+            FogValue::Map(v) if name != FOG_TYPE_ENUM && v.len() == 1 => {
+                let (variant, value) = v.iter().next().unwrap();
+                visitor.visit_enum(VariantAccess { variant, value })
+            },
+            FogValue::Null | FogValue::Bool(_) | FogValue::Int(_) | FogValue::Str(_)
+                | FogValue::F32(_) | FogValue::F64(_) | FogValue::Bin(_) | FogValue::Array(_)
+                | FogValue::Map(_) =>
+            {
+                if name == FOG_TYPE_ENUM {
+                    Err(fail("expected a fog-pack extension type"))
+                } else {
+                    Err(fail("expected a size-1 map or a string"))
+                }
+            },
+            _ => visitor.visit_enum(ExtAccess(self.0)),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf unit_struct
+        seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// Access to an enum variant written as a map with a single key.
+struct VariantAccess<'de> {
+    variant: &'de str,
+    value: &'de FogValue,
+}
+
+impl<'de> de::EnumAccess<'de> for VariantAccess<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let variant = seed.deserialize(BorrowedStrDeserializer::<Error>::new(self.variant))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Err(fail("invalid type: non-unit variant, expected unit variant"))
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(ValueDeserializer(self.value))
+    }
+
+    fn tuple_variant<V: de::Visitor<'de>>(self, _: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(ValueDeserializer(self.value), visitor)
+    }
+
+    fn struct_variant<V: de::Visitor<'de>>(
+        self,
+        _: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(ValueDeserializer(self.value), visitor)
+    }
+}
+
+/// Access to a fog-pack extension type, presented the way fog-pack does: as a `FOG_TYPE_ENUM`
+/// variant holding the type's encoded bytes.
+struct ExtAccess<'de>(&'de FogValue);
+
+impl<'de> de::EnumAccess<'de> for ExtAccess<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let index = match self.0 {
+            FogValue::Timestamp(_) => FOG_TYPE_ENUM_TIME_INDEX,
+            FogValue::Hash(_) => FOG_TYPE_ENUM_HASH_INDEX,
+            FogValue::Identity(_) => FOG_TYPE_ENUM_IDENTITY_INDEX,
+            FogValue::LockId(_) => FOG_TYPE_ENUM_LOCK_ID_INDEX,
+            FogValue::StreamId(_) => FOG_TYPE_ENUM_STREAM_ID_INDEX,
+            FogValue::DataLockbox(_) => FOG_TYPE_ENUM_DATA_LOCKBOX_INDEX,
+            FogValue::IdentityLockbox(_) => FOG_TYPE_ENUM_IDENTITY_LOCKBOX_INDEX,
+            FogValue::StreamLockbox(_) => FOG_TYPE_ENUM_STREAM_LOCKBOX_INDEX,
+            FogValue::LockLockbox(_) => FOG_TYPE_ENUM_LOCK_LOCKBOX_INDEX,
+            _ => unreachable!("ExtAccess should only be made for extension types"),
+        };
+        let variant = seed.deserialize(U64Deserializer::<Error>::new(index))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for ExtAccess<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Err(fail("fog-pack extension types aren't unit variants"))
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        match self.0 {
+            FogValue::Timestamp(v) => seed.deserialize(BytesDeserializer::new(&v.as_vec())),
+            FogValue::Hash(v) => seed.deserialize(BytesDeserializer::new(v.as_ref())),
+            FogValue::Identity(v) => seed.deserialize(BytesDeserializer::new(&v.as_vec())),
+            FogValue::LockId(v) => seed.deserialize(BytesDeserializer::new(&v.as_vec())),
+            FogValue::StreamId(v) => seed.deserialize(BytesDeserializer::new(&v.as_vec())),
+            FogValue::DataLockbox(v) => seed.deserialize(BorrowedBytesDeserializer::new(v.as_bytes())),
+            FogValue::IdentityLockbox(v) => seed.deserialize(BorrowedBytesDeserializer::new(v.as_bytes())),
+            FogValue::StreamLockbox(v) => seed.deserialize(BorrowedBytesDeserializer::new(v.as_bytes())),
+            FogValue::LockLockbox(v) => seed.deserialize(BorrowedBytesDeserializer::new(v.as_bytes())),
+            _ => unreachable!("ExtAccess should only be made for extension types"),
+        }
+    }
+
+    fn tuple_variant<V: de::Visitor<'de>>(self, _: usize, _: V) -> Result<V::Value, Error> {
+        Err(fail("fog-pack extension types aren't tuple variants"))
+    }
+
+    fn struct_variant<V: de::Visitor<'de>>(
+        self,
+        _: &'static [&'static str],
+        _: V
+    ) -> Result<V::Value, Error> {
+        Err(fail("fog-pack extension types aren't struct variants"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fog_pack::{document::NewDocument, schema::NoSchema, validator::*};
+
+    #[test]
+    fn validator_round_trip() {
+        let hash = Hash::new(b"hash");
+        let time = Timestamp::from_utc(1_600_000_000, 5).unwrap();
+        let validators = vec![
+            Validator::Null,
+            Validator::Any,
+            IntValidator::new().max(10).min(-3).build(),
+            F64Validator::new().max(1.5).min(-1.0).build(),
+            BinValidator::new().in_add(vec![1u8, 2, 3]).build(),
+            StrValidator::new().in_add("x").max_len(8).build(),
+            HashValidator::new().in_add(hash.clone()).build(),
+            TimeValidator::new().min(time).build(),
+            ArrayValidator::new().items(IntValidator::new().build()).build(),
+            MapValidator::new().req_add("a", Validator::new_ref("Item")).build(),
+            MultiValidator::new().push(Validator::Null).push(BoolValidator::new().build()).build(),
+            EnumValidator::new().insert("One", None).insert("Two", Some(Validator::Any)).build(),
+        ];
+        for validator in validators {
+            let value = to_value(&validator).unwrap();
+            let doc = NoSchema::validate_new_doc(NewDocument::new(None, &validator).unwrap()).unwrap();
+            let through_doc: FogValue = doc.deserialize().unwrap();
+            assert_eq!(value, through_doc);
+            let back: Validator = from_value(&value).unwrap();
+            assert_eq!(back, validator);
+        }
+    }
+}
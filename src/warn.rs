@@ -0,0 +1,121 @@
+use std::fmt;
+
+/// A value that was converted, but not exactly as it was written.
+///
+/// Returned by [`json_to_fog_with_warnings`][crate::json_to_fog_with_warnings] and
+/// [`fog_to_json_with_warnings`][crate::fog_to_json_with_warnings], so tools can tell users when
+/// a value was accepted but normalized along the way.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    /// RFC 6901 JSON Pointer to the value, relative to the root value being converted.
+    pub pointer: String,
+    /// What happened to the value.
+    pub kind: WarningKind,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at \"{}\"", self.kind, self.pointer)
+    }
+}
+
+/// The ways a value can be changed during conversion without failing it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WarningKind {
+    /// Whitespace around a `$fog-TYPE:` value was trimmed off.
+    TrimmedWhitespace,
+    /// A type name alias, or a type name in the wrong case, was accepted.
+    TypeAlias,
+    /// Padding on Base64 data was accepted.
+    PaddedBase64,
+    /// A decimal F32 had more digits than it can hold, and was rounded.
+    RoundedF32,
+    /// A decimal F64 had more digits than it can hold, and was rounded.
+    RoundedF64,
+    /// A time had digits finer than nanoseconds, which were dropped.
+    TruncatedTime,
+    /// A number was written with `_` digit separators, like `1_000_000`, which were removed.
+    DigitSeparators,
+    /// An unrecognized `$fog-TYPE:` string was kept as a plain string, as allowed by
+    /// [`DecodeOptions::lenient`][crate::DecodeOptions::lenient].
+    UnrecognizedType(String),
+    /// An F32 was written as a plain JSON number, and will convert back as an F64.
+    PlainF32,
+    /// A NaN or infinite float was written in hex, as JSON numbers can't hold it.
+    NonFiniteFloat,
+    /// A Timestamp couldn't be written in the chosen format, so it was written as a
+    /// `$fog-TimeRaw:` string instead.
+    RawTime,
+    /// A float was rounded to the digits set with
+    /// [`EncodeOptions::float_precision`][crate::EncodeOptions::float_precision].
+    RoundedFloat,
+}
+
+impl WarningKind {
+    /// A short description of the warning.
+    pub fn message(&self) -> &'static str {
+        match self {
+            WarningKind::TrimmedWhitespace => "whitespace around typed value",
+            WarningKind::TypeAlias => "type alias or mismatched case",
+            WarningKind::PaddedBase64 => "padded Base64",
+            WarningKind::RoundedF32 => "too many digits for F32",
+            WarningKind::RoundedF64 => "too many digits for F64",
+            WarningKind::TruncatedTime => "time finer than nanoseconds",
+            WarningKind::DigitSeparators => "digit separators in number",
+            WarningKind::UnrecognizedType(_) => "unrecognized type kept as a string",
+            WarningKind::PlainF32 => "F32 written as a plain number",
+            WarningKind::NonFiniteFloat => "non-finite float written in hex",
+            WarningKind::RawTime => "time written in raw form",
+            WarningKind::RoundedFloat => "float rounded to fewer digits",
+        }
+    }
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WarningKind::UnrecognizedType(ty) => write!(f, "unrecognized type \"{}\" kept as a string", ty),
+            kind => f.write_str(kind.message()),
+        }
+    }
+}
+
+/// Collects warnings during a conversion, if they were asked for.
+#[derive(Default)]
+pub(crate) struct Warnings(Option<Vec<Warning>>);
+
+impl Warnings {
+    pub(crate) fn collect() -> Self {
+        Self(Some(Vec::new()))
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Record a warning for the value currently being converted. Its pointer is filled in by
+    /// [`locate`][Self::locate].
+    pub(crate) fn push(&mut self, kind: WarningKind) {
+        if let Some(warnings) = &mut self.0 {
+            warnings.push(Warning { pointer: String::new(), kind });
+        }
+    }
+
+    /// Number of warnings recorded so far.
+    pub(crate) fn len(&self) -> usize {
+        self.0.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Set the pointer for every warning recorded since the first `from` warnings.
+    pub(crate) fn locate(&mut self, from: usize, pointer: impl FnOnce() -> String) {
+        let Some(warnings) = self.0.as_mut().filter(|w| w.len() > from) else { return };
+        let pointer = pointer();
+        for warning in &mut warnings[from..] {
+            warning.pointer.clone_from(&pointer);
+        }
+    }
+
+    pub(crate) fn into_vec(self) -> Vec<Warning> {
+        self.0.unwrap_or_default()
+    }
+}
@@ -0,0 +1,215 @@
+use super::*;
+use enc::Scratch;
+use std::{fmt, io, str};
+
+/// Adapts a [`fmt::Write`] sink so JSON text can be written into it.
+struct FmtWriter<'a, W: fmt::Write> {
+    inner: &'a mut W,
+}
+
+impl<W: fmt::Write> io::Write for FmtWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // The encoder only ever writes out whole UTF-8 sequences
+        let s = str::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.inner.write_str(s).map_err(|_| io::Error::other("fmt::Write sink failed"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Convert a fog-pack value straight to compact JSON text, using the provided encoding options.
+///
+/// The result is the same as serializing the output of [`fog_to_json_with_options`], but no
+/// intermediate JSON Value is built for the arrays and maps in `val`.
+///
+/// # Panics
+///
+/// Panics if the value is nested deeper than [`EncodeOptions::max_depth`].
+pub fn fog_to_json_string(val: &FogValue, opts: &EncodeOptions) -> String {
+    let mut out = String::new();
+    // Writing to a String can't fail
+    fog_to_json_fmt(val, opts, &mut out).unwrap();
+    out
+}
+
+/// Convert a fog-pack ValueRef straight to compact JSON text, as with [`fog_to_json_string`].
+///
+/// # Panics
+///
+/// Panics if the value is nested deeper than [`EncodeOptions::max_depth`].
+pub fn fogref_to_json_string(val: &FogValueRef, opts: &EncodeOptions) -> String {
+    let mut out = String::new();
+    // Writing to a String can't fail
+    fogref_to_json_fmt(val, opts, &mut out).unwrap();
+    out
+}
+
+/// Convert a fog-pack value to compact JSON text, writing it directly into a [`fmt::Write`] sink.
+///
+/// # Panics
+///
+/// Panics if the value is nested deeper than [`EncodeOptions::max_depth`].
+pub fn fog_to_json_fmt<W: fmt::Write>(val: &FogValue, opts: &EncodeOptions, out: &mut W) -> fmt::Result {
+    let mut enc = Encoder::new(opts);
+    let result = enc.write_fog(&mut FmtWriter { inner: out }, val);
+    enc.check();
+    result.map_err(|_| fmt::Error)
+}
+
+/// Convert a fog-pack ValueRef to compact JSON text, writing it directly into a [`fmt::Write`]
+/// sink, as with [`fog_to_json_fmt`].
+///
+/// # Panics
+///
+/// Panics if the value is nested deeper than [`EncodeOptions::max_depth`].
+pub fn fogref_to_json_fmt<W: fmt::Write>(val: &FogValueRef, opts: &EncodeOptions, out: &mut W) -> fmt::Result {
+    let mut enc = Encoder::new(opts);
+    let result = enc.write(&mut FmtWriter { inner: out }, val);
+    enc.check();
+    result.map_err(|_| fmt::Error)
+}
+
+/// A reusable converter from fog-pack to JSON text.
+///
+/// Owns its [`EncodeOptions`] along with an output buffer and scratch buffers for Base64, base58, 
+/// and float text, all reused by every conversion, so hot loops converting many values, 
+/// documents, or entries don't allocate fresh Strings for each one.
+///
+/// ```
+/// # use fog_human_json::*;
+/// let mut encoder = JsonEncoder::new(EncodeOptions::new());
+/// for i in 0..3u8 {
+///     let val = fog_pack::types::Value::Array(vec![i.into()]);
+///     assert_eq!(encoder.to_str(&val.as_ref()), format!("[{}]", i));
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct JsonEncoder {
+    opts: EncodeOptions,
+    buf: Vec<u8>,
+    scratch: Scratch,
+}
+
+impl JsonEncoder {
+    /// Create a new encoder using the provided options.
+    pub fn new(opts: EncodeOptions) -> Self {
+        Self { opts, buf: Vec::new(), scratch: Scratch::default() }
+    }
+
+    /// Get the options this encoder uses.
+    pub fn options(&self) -> &EncodeOptions {
+        &self.opts
+    }
+
+    /// Convert a fog-pack ValueRef to a JSON Value, as with [`fogref_to_json_with_options`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is nested deeper than [`EncodeOptions::max_depth`].
+    pub fn to_json(&self, val: &FogValueRef) -> JsonValue {
+        fogref_to_json_with_options(val, &self.opts)
+    }
+
+    /// Convert a fog-pack ValueRef to compact JSON text. The returned string borrows the 
+    /// encoder's buffer, and is overwritten by the next conversion.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is nested deeper than [`EncodeOptions::max_depth`].
+    pub fn to_str(&mut self, val: &FogValueRef) -> &str {
+        self.buf.clear();
+        let mut enc = Encoder::with_scratch(&self.opts, std::mem::take(&mut self.scratch));
+        // Writing to a Vec can't fail
+        enc.write(&mut self.buf, val).unwrap();
+        enc.check();
+        self.scratch = enc.into_scratch();
+        self.as_str()
+    }
+
+    /// Convert a [`Document`][fog_pack::document::Document] to compact JSON text, as with 
+    /// [`doc_to_json_writer`], reusing the encoder's buffers.
+    ///
+    /// Fails if the document's data can't be read, as can happen with documents loaded without 
+    /// validation, or if it's nested deeper than [`EncodeOptions::max_depth`].
+    pub fn doc_to_str(&mut self, doc: &fog_pack::document::Document) -> Result<&str, EncodeError> {
+        let data = doc::doc_to_fogref(doc, &self.opts)?;
+        let extra = doc::doc_extra(doc, &self.opts);
+        self.envelope_to_str(&data, extra)
+    }
+
+    /// Convert an [`Entry`][fog_pack::entry::Entry] to compact JSON text, as with 
+    /// [`entry_to_json_writer`], reusing the encoder's buffers.
+    ///
+    /// Fails if the entry's data can't be read, as can happen with entries loaded without 
+    /// validation, or if it's nested deeper than [`EncodeOptions::max_depth`].
+    pub fn entry_to_str(&mut self, entry: &fog_pack::entry::Entry) -> Result<&str, EncodeError> {
+        let data = entry::entry_to_fogref(entry, &self.opts)?;
+        self.envelope_to_str(&data, JsonMap::new())
+    }
+
+    fn envelope_to_str(&mut self, data: &FogValueRef, extra: JsonMap) -> Result<&str, EncodeError> {
+        self.buf.clear();
+        let mut enc = Encoder::with_scratch(&self.opts, std::mem::take(&mut self.scratch));
+        // Writing to a Vec can't fail
+        enc.try_write_envelope(&mut self.buf, data, extra).unwrap();
+        let error = enc.take_error();
+        self.scratch = enc.into_scratch();
+        match error {
+            Some(err) => Err(err),
+            None => Ok(self.as_str()),
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        // serde_json only ever writes valid UTF-8
+        str::from_utf8(&self.buf).expect("JSON output should be valid UTF-8")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fog_to_json_string() {
+        let json = serde_json::json!({
+            "a\"b": [1, -2, 1.5, "$fog-F32:0.5", "$fog-Str:$fog-", null, true, {}, []],
+            "bin": "$fog-Bin:AAECAwQ",
+            "hash": format!("$fog-Hash:{}", fog_pack::types::Hash::new(b"x").to_base58()),
+            "unicode": "h\u{e9}llo \u{1f600}\n",
+        });
+        let val = json_to_fog(&json).unwrap();
+        let opts = EncodeOptions::new().bin_as_array(2);
+        let expected = serde_json::to_string(&fog_to_json_with_options(&val, &opts)).unwrap();
+        assert_eq!(super::fog_to_json_string(&val, &opts), expected);
+        assert_eq!(serde_json::from_str::<JsonValue>(&expected).unwrap(), json);
+    }
+
+    #[test]
+    fn json_encoder() {
+        use fog_pack::{document::NewDocument, schema::NoSchema};
+        let opts = EncodeOptions::new().int_annotate(true);
+        let mut encoder = JsonEncoder::new(opts.clone());
+        for data in [serde_json::json!({"a": [1, "x", null]}), serde_json::json!("$fog-Bin:AAEC")] {
+            let val = json_to_fog(&data).unwrap();
+            let doc = NoSchema::validate_new_doc(NewDocument::new(None, &val).unwrap()).unwrap();
+            let expected = serde_json::to_string(&fog_to_json_with_options(&val, &opts)).unwrap();
+            assert_eq!(encoder.to_str(&val.as_ref()), expected);
+            let expected = serde_json::to_string(&doc_to_json_with_options(&doc, &opts)).unwrap();
+            assert_eq!(encoder.doc_to_str(&doc).unwrap(), expected);
+        }
+
+        // Errors are returned, and don't stop the encoder from being reused
+        let data = serde_json::json!({"a": [[1.5, "$fog-F32:2.5", "$fog-Bin:AAEC"]]});
+        let val = json_to_fog(&data).unwrap();
+        let doc = NoSchema::validate_new_doc(NewDocument::new(None, &val).unwrap()).unwrap();
+        let mut shallow = JsonEncoder::new(EncodeOptions::new().max_depth(2));
+        assert!(matches!(shallow.doc_to_str(&doc), Err(EncodeError::TooDeep(2))));
+        let expected = serde_json::to_string(&doc_to_json(&doc)).unwrap();
+        assert_eq!(encoder.doc_to_str(&doc).unwrap(), expected);
+        assert_eq!(encoder.to_str(&val.as_ref()), serde_json::to_string(&data).unwrap());
+    }
+}